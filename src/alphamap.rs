@@ -1,4 +1,5 @@
-use image::{DynamicImage, GenericImage, ImageError, Pixel};
+use crate::textures;
+use image::{ColorType, DynamicImage, GenericImage, ImageError, Pixel};
 use std::path::Path;
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -10,6 +11,16 @@ pub struct Alphamap {
     src_img: DynamicImage,
 }
 
+/// How much of the terrain an alphamap channel paints
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ChannelCoverage {
+    pub name: &'static str,
+    /// Mean weight in [0, 1]
+    pub mean: f32,
+    /// Fraction of pixels with a non-zero weight
+    pub painted: f32,
+}
+
 impl Alphamap {
     pub fn from_png_file<P: AsRef<Path>>(
         file_path: Option<P>,
@@ -19,16 +30,7 @@ impl Alphamap {
         let src_img = if let Some(p) = file_path {
             image::open(p)?
         } else {
-            // Use a default image with Red channel maxed out, assumes a single
-            // texture will be used
-            let mut img = DynamicImage::new_rgb8(desired_width as _, desired_height as _);
-            let (w, h) = img.dimensions();
-            for y in 0..h {
-                for x in 0..w {
-                    img.put_pixel(x as _, y as _, Pixel::from_channels(255, 0, 0, 0));
-                }
-            }
-            img
+            Self::default_image(desired_width, desired_height)
         };
 
         let (src_width, src_height) = src_img.dimensions();
@@ -38,15 +40,104 @@ impl Alphamap {
         Ok(Self { src_img })
     }
 
+    /// Default image with Red channel maxed out, assumes a single texture
+    /// will be used
+    pub fn default_image(width: usize, height: usize) -> DynamicImage {
+        let mut img = DynamicImage::new_rgb8(width as _, height as _);
+        for y in 0..height {
+            for x in 0..width {
+                img.put_pixel(x as _, y as _, Pixel::from_channels(255, 0, 0, 0));
+            }
+        }
+        img
+    }
+
+    /// Replace the image, i.e. with a generated one, and its texture
+    pub fn set_image(&mut self, img: DynamicImage) {
+        assert_eq!(img.dimensions(), self.src_img.dimensions());
+        self.src_img = img;
+        textures::set_texture_image(self.src_texture(), "alphamap_src");
+    }
+
+    /// Coverage of the red, green, blue and, when present, alpha channels
+    pub fn channel_coverage(&self) -> Vec<ChannelCoverage> {
+        let channels = match self.src_img.color() {
+            ColorType::RGBA(_) | ColorType::GrayA(_) => 4,
+            _ => 3,
+        };
+        let img = self.src_img.to_rgba();
+        let count = img.pixels().len().max(1) as f32;
+
+        ["red", "green", "blue", "alpha"]
+            .iter()
+            .take(channels)
+            .enumerate()
+            .map(|(c, name)| {
+                let (sum, painted) = img.pixels().fold((0u64, 0usize), |(sum, painted), p| {
+                    (sum + u64::from(p[c]), painted + (p[c] != 0) as usize)
+                });
+                ChannelCoverage {
+                    name,
+                    mean: sum as f32 / 255.0 / count,
+                    painted: painted as f32 / count,
+                }
+            })
+            .collect()
+    }
+
     pub fn src_texture(&self) -> DynamicImage {
         DynamicImage::ImageRgb8(self.src_img.to_rgb())
     }
 }
 
 impl From<ImageError> for Error {
-    fn from(e: ImageError) -> Error {
-        match e {
-            _ => Error::FileNotSupported,
+    fn from(_: ImageError) -> Error {
+        Error::FileNotSupported
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage, Rgba, RgbaImage};
+
+    #[test]
+    fn coverage_of_each_channel() {
+        // Red everywhere, green on half of the pixels, blue faded in, alpha
+        // on a single pixel
+        let pixels = [
+            [255, 255, 0, 0],
+            [255, 255, 51, 0],
+            [255, 0, 102, 0],
+            [255, 0, 255, 255],
+        ];
+        let img = RgbaImage::from_fn(2, 2, |x, y| Rgba(pixels[(x + y * 2) as usize]));
+        let amap = Alphamap {
+            src_img: DynamicImage::ImageRgba8(img),
+        };
+
+        let coverage = amap.channel_coverage();
+        let names: Vec<&str> = coverage.iter().map(|c| c.name).collect();
+        assert_eq!(names, vec!["red", "green", "blue", "alpha"]);
+
+        let expected = [(1.0, 1.0), (0.5, 0.5), (0.4, 0.75), (0.25, 0.25)];
+        for (c, (mean, painted)) in coverage.iter().zip(&expected) {
+            assert!((c.mean - mean).abs() < 1e-6, "{} mean {}", c.name, c.mean);
+            assert_eq!(c.painted, *painted, "{} painted", c.name);
         }
     }
+
+    #[test]
+    fn rgb_has_no_alpha_coverage() {
+        let img = RgbImage::from_pixel(4, 2, Rgb([0, 128, 0]));
+        let amap = Alphamap {
+            src_img: DynamicImage::ImageRgb8(img),
+        };
+
+        let coverage = amap.channel_coverage();
+        assert_eq!(coverage.len(), 3);
+        assert_eq!(coverage[0].painted, 0.0);
+        assert_eq!(coverage[1].painted, 1.0);
+        assert!((coverage[1].mean - 128.0 / 255.0).abs() < 1e-6);
+    }
 }
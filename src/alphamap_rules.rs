@@ -0,0 +1,261 @@
+use crate::heightmap::Heightmap;
+use image::{DynamicImage, ImageBuffer, Rgba};
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Error {
+    InvalidFile,
+    InvalidRules(String),
+}
+
+/// Alphamap channel, one per terrain texture
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Channel {
+    Red,
+    Green,
+    Blue,
+    Alpha,
+}
+
+/// Terrain attribute a rule condition applies to
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Attribute {
+    /// Elevation normalized to the heightmap value range, [0, 1]
+    Elevation,
+    /// Slope angle in degrees, at the current height scale
+    Slope,
+    /// Negative laplacian of the displayed elevations, positive on ridges
+    /// and negative in valleys
+    Curvature,
+}
+
+/// Full weight within [min, max], fading out over `blend` outside of it
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Condition {
+    pub attribute: Attribute,
+    pub min: f32,
+    pub max: f32,
+    pub blend: f32,
+}
+
+/// Paint a channel where all of the conditions hold
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+    pub channel: Channel,
+    pub conditions: Vec<Condition>,
+}
+
+/// Ordered list of rules, later rules are painted over earlier ones
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rules {
+    pub rules: Vec<Rule>,
+}
+
+/// Per-pixel terrain attributes, row-major
+pub struct Attributes {
+    pub elevation: Vec<f32>,
+    pub slope: Vec<f32>,
+    pub curvature: Vec<f32>,
+}
+
+impl Attributes {
+    pub fn from_heightmap(hmap: &Heightmap) -> Self {
+        Self {
+            elevation: hmap.normalized_elevations(),
+            slope: hmap.slope_degrees(),
+            curvature: hmap.curvature(),
+        }
+    }
+
+    fn get(&self, attribute: Attribute, index: usize) -> f32 {
+        match attribute {
+            Attribute::Elevation => self.elevation[index],
+            Attribute::Slope => self.slope[index],
+            Attribute::Curvature => self.curvature[index],
+        }
+    }
+}
+
+impl Channel {
+    fn index(self) -> usize {
+        match self {
+            Channel::Red => 0,
+            Channel::Green => 1,
+            Channel::Blue => 2,
+            Channel::Alpha => 3,
+        }
+    }
+}
+
+impl Condition {
+    fn weight(&self, v: f32) -> f32 {
+        let outside = if v < self.min {
+            self.min - v
+        } else if v > self.max {
+            v - self.max
+        } else {
+            return 1.0;
+        };
+
+        if self.blend > 0.0 {
+            let t = (1.0 - outside / self.blend).max(0.0);
+            t * t * (3.0 - 2.0 * t)
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Rules {
+    pub fn from_file(file_path: &Path) -> Result<Self, Error> {
+        fs::read_to_string(file_path)?
+            .parse()
+            .map_err(Error::InvalidRules)
+    }
+
+    /// Generate an alphamap, channel weights of each pixel add up to 255
+    ///
+    /// Pixels no rule applies to use the red channel. The alphamap only has
+    /// an alpha channel when a rule paints it.
+    pub fn generate(&self, attrs: &Attributes, width: usize, height: usize) -> DynamicImage {
+        let img =
+            DynamicImage::ImageRgba8(ImageBuffer::from_fn(width as _, height as _, |x, y| {
+                let index = x as usize + y as usize * width;
+                let mut weights = [0.0; 4];
+
+                for rule in &self.rules {
+                    let w: f32 = rule
+                        .conditions
+                        .iter()
+                        .map(|c| c.weight(attrs.get(c.attribute, index)))
+                        .product();
+                    for v in weights.iter_mut() {
+                        *v *= 1.0 - w;
+                    }
+                    weights[rule.channel.index()] += w;
+                }
+
+                let sum: f32 = weights.iter().sum();
+                if sum > 0.0 {
+                    for v in weights.iter_mut() {
+                        *v /= sum;
+                    }
+                } else {
+                    weights[0] = 1.0;
+                }
+
+                let c = |v: f32| (v * 255.0).round() as u8;
+                Rgba([c(weights[0]), c(weights[1]), c(weights[2]), c(weights[3])])
+            }));
+
+        if self.rules.iter().any(|r| r.channel == Channel::Alpha) {
+            img
+        } else {
+            DynamicImage::ImageRgb8(img.to_rgb())
+        }
+    }
+}
+
+/// Parse rules, one per line: `CHANNEL ATTRIBUTE:MIN:MAX[:BLEND] ...`
+///
+/// CHANNEL is red, green, blue or alpha, ATTRIBUTE is elevation, slope or
+/// curvature. Empty lines and lines starting with `#` are ignored.
+impl FromStr for Rules {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rules = Vec::new();
+
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let err = |e: String| format!("Line {}: {}", i + 1, e);
+
+            let mut parts = line.split_whitespace();
+            let channel = parts.next().unwrap_or("").parse().map_err(err)?;
+            let conditions = parts
+                .map(|p| p.parse())
+                .collect::<Result<Vec<Condition>, _>>()
+                .map_err(err)?;
+            if conditions.is_empty() {
+                return Err(err(format!("Rule '{}' has no conditions", line)));
+            }
+
+            rules.push(Rule {
+                channel,
+                conditions,
+            });
+        }
+
+        Ok(Self { rules })
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("Invalid condition '{}', use ATTRIBUTE:MIN:MAX[:BLEND]", s);
+        let parts: Vec<&str> = s.split(':').collect();
+        let num = |i: usize| -> Result<f32, String> {
+            parts
+                .get(i)
+                .ok_or_else(err)?
+                .parse::<f32>()
+                .map_err(|_| err())
+        };
+        if parts.len() > 4 {
+            return Err(err());
+        }
+
+        Ok(Condition {
+            attribute: parts[0].parse()?,
+            min: num(1)?,
+            max: num(2)?,
+            blend: if parts.len() > 3 { num(3)? } else { 0.0 },
+        })
+    }
+}
+
+impl FromStr for Channel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "red" | "r" => Ok(Channel::Red),
+            "green" | "g" => Ok(Channel::Green),
+            "blue" | "b" => Ok(Channel::Blue),
+            "alpha" | "a" => Ok(Channel::Alpha),
+            _ => Err(format!(
+                "Invalid channel '{}', use red, green, blue or alpha",
+                s
+            )),
+        }
+    }
+}
+
+impl FromStr for Attribute {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "elevation" => Ok(Attribute::Elevation),
+            "slope" => Ok(Attribute::Slope),
+            "curvature" => Ok(Attribute::Curvature),
+            _ => Err(format!(
+                "Invalid attribute '{}', use elevation, slope or curvature",
+                s
+            )),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(_: std::io::Error) -> Error {
+        Error::InvalidFile
+    }
+}
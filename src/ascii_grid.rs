@@ -0,0 +1,212 @@
+use crate::geo_transform::GeoTransform;
+use crate::heightmap::Error;
+use crate::raster::Raster;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// Nodata value written to the header, filled rasters don't contain any
+const ASC_NODATA: f32 = -9999.0;
+
+/// Projection written next to geographic grids
+const WGS84_PRJ: &str = "GEOGCS[\"GCS_WGS_1984\",DATUM[\"D_WGS_1984\",\
+SPHEROID[\"WGS_1984\",6378137.0,298.257223563]],PRIMEM[\"Greenwich\",0.0],\
+UNIT[\"Degree\",0.0174532925199433]]";
+
+/// Read an ESRI ASCII grid (.asc) DEM
+///
+/// The grid itself doesn't carry a projection, cell sizes are only taken
+/// as degrees when a `.prj` file next to it names a geographic coordinate
+/// system.
+pub fn read_file(file_path: &Path) -> Result<Raster, Error> {
+    let text = fs::read_to_string(file_path)?;
+    let geographic = match fs::read_to_string(file_path.with_extension("prj")) {
+        Ok(prj) => is_geographic_prj(&prj),
+        Err(_) => false,
+    };
+    parse_grid(&text, geographic)
+}
+
+/// Parse the text of an ASCII grid, `geographic` when the world units are
+/// degrees of longitude/latitude
+fn parse_grid(text: &str, geographic: bool) -> Result<Raster, Error> {
+    let mut tokens = text.split_whitespace().peekable();
+
+    let mut ncols = None;
+    let mut nrows = None;
+    let mut xll = None;
+    let mut yll = None;
+    let mut centered = false;
+    let mut cell_size = None;
+    let mut nodata = None;
+
+    // Header is a list of key/value pairs, the data starts at the
+    // first token that isn't a known key
+    while let Some(key) = tokens.peek().map(|k| k.to_lowercase()) {
+        let is_key = matches!(
+            key.as_str(),
+            "ncols"
+                | "nrows"
+                | "xllcorner"
+                | "yllcorner"
+                | "xllcenter"
+                | "yllcenter"
+                | "cellsize"
+                | "dx"
+                | "nodata_value"
+        );
+        if !is_key {
+            break;
+        }
+        tokens.next();
+
+        let value = tokens.next().ok_or(Error::InvalidFile)?;
+        match key.as_str() {
+            "ncols" => ncols = Some(parse::<usize>(value)?),
+            "nrows" => nrows = Some(parse::<usize>(value)?),
+            "xllcorner" => xll = Some(parse::<f64>(value)?),
+            "yllcorner" => yll = Some(parse::<f64>(value)?),
+            "xllcenter" => {
+                xll = Some(parse::<f64>(value)?);
+                centered = true;
+            }
+            "yllcenter" => {
+                yll = Some(parse::<f64>(value)?);
+                centered = true;
+            }
+            "cellsize" | "dx" => cell_size = Some(parse::<f64>(value)?),
+            "nodata_value" => nodata = Some(parse::<f32>(value)?),
+            _ => unreachable!(),
+        }
+    }
+
+    let (width, height) = (
+        ncols.ok_or(Error::InvalidFile)?,
+        nrows.ok_or(Error::InvalidFile)?,
+    );
+    let (xll, yll) = (
+        xll.ok_or(Error::InvalidFile)?,
+        yll.ok_or(Error::InvalidFile)?,
+    );
+    let cell_size = cell_size.ok_or(Error::InvalidFile)?;
+
+    let data = tokens
+        .map(parse::<f32>)
+        .collect::<Result<Vec<f32>, Error>>()?;
+    if data.len() != width * height {
+        return Err(Error::InvalidFile);
+    }
+
+    // Lower-left is the corner of the bottom-left cell, or its center
+    let (xll, yll) = if centered {
+        (xll - cell_size / 2.0, yll - cell_size / 2.0)
+    } else {
+        (xll, yll)
+    };
+
+    Ok(Raster {
+        width,
+        height,
+        data,
+        geo_transform: GeoTransform {
+            origin_x: xll,
+            origin_y: yll + height as f64 * cell_size,
+            pixel_width: cell_size,
+            pixel_height: -cell_size,
+            geographic,
+        },
+        nodata,
+        bit_depth: None,
+    })
+}
+
+/// Write a raster as an ESRI ASCII grid, cells are assumed to be square
+pub fn write_file(file_path: &Path, raster: &Raster) -> Result<(), Error> {
+    let gt = &raster.geo_transform;
+    let mut w = BufWriter::new(File::create(file_path)?);
+
+    writeln!(w, "ncols {}", raster.width)?;
+    writeln!(w, "nrows {}", raster.height)?;
+    writeln!(w, "xllcorner {}", gt.origin_x)?;
+    writeln!(
+        w,
+        "yllcorner {}",
+        gt.origin_y + raster.height as f64 * gt.pixel_height
+    )?;
+    writeln!(w, "cellsize {}", gt.pixel_width.abs())?;
+    writeln!(w, "NODATA_value {}", raster.nodata.unwrap_or(ASC_NODATA))?;
+
+    for row in raster.data.chunks(raster.width) {
+        let line: Vec<String> = row.iter().map(|v| v.to_string()).collect();
+        writeln!(w, "{}", line.join(" "))?;
+    }
+
+    if gt.geographic {
+        fs::write(file_path.with_extension("prj"), WGS84_PRJ)?;
+    }
+
+    Ok(())
+}
+
+/// Whether a `.prj` file describes a geographic (lat/lon) coordinate
+/// system, either as WKT or in the older ESRI `Projection GEOGRAPHIC` form
+fn is_geographic_prj(prj: &str) -> bool {
+    let prj = prj.trim_start().to_uppercase();
+    if prj.starts_with("GEOGCS") {
+        return true;
+    }
+    prj.lines().any(|line| {
+        let mut words = line.split_whitespace();
+        words.next() == Some("PROJECTION") && words.next() == Some("GEOGRAPHIC")
+    })
+}
+
+fn parse<T: std::str::FromStr>(s: &str) -> Result<T, Error> {
+    s.parse::<T>().map_err(|_| Error::InvalidFile)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRID: &str = "ncols 3\nnrows 2\nxllcorner 100\nyllcorner 200\n\
+                        cellsize 10\nNODATA_value -9999\n1 2 3\n4 5 6\n";
+
+    #[test]
+    fn parses_header() {
+        let raster = parse_grid(GRID, false).unwrap();
+        let gt = raster.geo_transform;
+
+        assert_eq!((raster.width, raster.height), (3, 2));
+        assert_eq!(raster.data, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        assert_eq!(raster.nodata, Some(-9999.0));
+        assert_eq!((gt.origin_x, gt.origin_y), (100.0, 220.0));
+        assert_eq!((gt.pixel_width, gt.pixel_height), (10.0, -10.0));
+        assert!(!gt.geographic);
+    }
+
+    #[test]
+    fn centered_origin_moves_to_corner() {
+        let text = GRID
+            .replace("xllcorner", "xllcenter")
+            .replace("yllcorner", "yllcenter");
+        let gt = parse_grid(&text, false).unwrap().geo_transform;
+        assert_eq!((gt.origin_x, gt.origin_y), (95.0, 215.0));
+    }
+
+    #[test]
+    fn rejects_incomplete_grids() {
+        assert!(parse_grid(&GRID.replace("cellsize 10", ""), false).is_err());
+        assert!(parse_grid(&GRID.replace(" 6", ""), false).is_err());
+    }
+
+    #[test]
+    fn detects_geographic_prj() {
+        assert!(is_geographic_prj(WGS84_PRJ));
+        assert!(is_geographic_prj("Projection    GEOGRAPHIC\nDatum WGS84\n"));
+        assert!(!is_geographic_prj(
+            "PROJCS[\"WGS_1984_UTM_Zone_10N\",GEOGCS[\"GCS_WGS_1984\"]]"
+        ));
+        assert!(!is_geographic_prj("Projection    UTM\nZone 10\n"));
+    }
+}
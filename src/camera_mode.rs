@@ -0,0 +1,19 @@
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CameraMode {
+    /// Fly through the scene, mouse to look around and WASD to move
+    FirstPerson,
+    /// Orbit around a point, mouse to rotate, AD to orbit and WS to zoom
+    ArcBall,
+    /// Orthographic view straight down, mouse to pan and WASD to move
+    TopDown,
+}
+
+impl CameraMode {
+    pub fn next(&self) -> Self {
+        match *self {
+            CameraMode::FirstPerson => CameraMode::ArcBall,
+            CameraMode::ArcBall => CameraMode::TopDown,
+            CameraMode::TopDown => CameraMode::FirstPerson,
+        }
+    }
+}
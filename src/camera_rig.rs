@@ -0,0 +1,470 @@
+use crate::camera_mode::CameraMode;
+use crate::top_down::TopDown;
+use kiss3d::camera::{ArcBall, Camera, FirstPerson};
+use kiss3d::event::{Action, Key, MouseButton, WindowEvent};
+use kiss3d::resource::ShaderUniform;
+use kiss3d::window::Canvas;
+use nalgebra::{Isometry3, Matrix4, Orthographic3, Perspective3, Point3, Vector3};
+use std::time::{Duration, Instant};
+
+/// Vertical field of view of the perspective cameras, in radians
+pub const CAMERA_FOV: f32 = std::f32::consts::FRAC_PI_4;
+
+const ZNEAR: f32 = 0.1;
+const ZFAR: f32 = 1024.0;
+
+/// How long switching modes takes
+const TRANSITION_TIME: Duration = Duration::from_millis(600);
+
+/// Cameras of every mode, each keeping its own view and speed, switching
+/// between them moves smoothly from one view to the other
+pub struct CameraRig {
+    mode: CameraMode,
+    first_person: FirstPerson,
+    arc_ball: ArcBall,
+    top_down: TopDown,
+    /// Flies between the views when switching modes
+    transition_cam: BlendCam,
+    transition: Option<Transition>,
+    /// Movement speed multipliers of the FirstPerson, ArcBall and TopDown
+    /// cameras
+    speeds: [f32; 3],
+}
+
+struct Transition {
+    from: View,
+    to: View,
+    start: Instant,
+}
+
+/// Where a camera is and how orthographic its projection is, 0 for
+/// perspective and 1 for orthographic
+#[derive(Copy, Clone, Debug)]
+struct View {
+    eye: Point3<f32>,
+    at: Point3<f32>,
+    ortho: f32,
+}
+
+impl CameraRig {
+    pub fn new(eye: Point3<f32>, at: Point3<f32>) -> Self {
+        let mut first_person = FirstPerson::new_with_frustrum(CAMERA_FOV, ZNEAR, ZFAR, eye, at);
+        first_person.set_move_step(1.0);
+        // Rebind arrow key movement to ASDW keys
+        first_person.rebind_up_key(Some(Key::W));
+        first_person.rebind_down_key(Some(Key::S));
+        first_person.rebind_left_key(Some(Key::A));
+        first_person.rebind_right_key(Some(Key::D));
+
+        let mut arc_ball = ArcBall::new_with_frustrum(CAMERA_FOV, ZNEAR, ZFAR, eye, at);
+        // Return resets the camera through the gui
+        arc_ball.rebind_reset_key(None);
+
+        Self {
+            mode: CameraMode::FirstPerson,
+            first_person,
+            arc_ball,
+            top_down: TopDown::new(),
+            transition_cam: BlendCam::new(),
+            transition: None,
+            speeds: [1.0; 3],
+        }
+    }
+
+    pub fn mode(&self) -> CameraMode {
+        self.mode
+    }
+
+    /// Switch modes, the ArcBall orbits around `pivot` and the TopDown view
+    /// shows everything between the `bounds`
+    pub fn set_mode(
+        &mut self,
+        mode: CameraMode,
+        pivot: &Point3<f32>,
+        bounds: (&Point3<f32>, &Point3<f32>),
+    ) {
+        let from = if self.transition.is_some() {
+            self.transition_cam.view()
+        } else {
+            self.view()
+        };
+
+        match mode {
+            CameraMode::FirstPerson => (),
+            CameraMode::ArcBall => self.arc_ball.look_at(from.eye, *pivot),
+            CameraMode::TopDown => self.top_down.fit(bounds.0, bounds.1),
+        }
+        self.mode = mode;
+        let to = self.view();
+
+        self.transition_cam.set_view(from);
+        self.transition = Some(Transition {
+            from,
+            to,
+            start: Instant::now(),
+        });
+    }
+
+    /// Show everything between the scene coordinates in the TopDown view
+    pub fn fit_top_down(&mut self, min: &Point3<f32>, max: &Point3<f32>) {
+        self.top_down.fit(min, max);
+    }
+
+    /// Keep everything between the scene coordinates in the depth of the
+    /// TopDown view, without moving it
+    pub fn fit_top_down_depth(&mut self, min: &Point3<f32>, max: &Point3<f32>) {
+        self.top_down.fit_depth(min.y, max.y);
+    }
+
+    /// Move the camera of the current mode, the TopDown view centers on `at`
+    pub fn look_at(&mut self, eye: Point3<f32>, at: Point3<f32>) {
+        self.transition = None;
+        match self.mode {
+            CameraMode::FirstPerson => self.first_person.look_at(eye, at),
+            CameraMode::ArcBall => self.arc_ball.look_at(eye, at),
+            CameraMode::TopDown => self.top_down.look_at(at),
+        }
+    }
+
+    /// The point looked at
+    pub fn at(&self) -> Point3<f32> {
+        if self.transition.is_some() {
+            return self.transition_cam.at();
+        }
+        match self.mode {
+            CameraMode::FirstPerson => self.first_person.at(),
+            CameraMode::ArcBall => self.arc_ball.at(),
+            CameraMode::TopDown => self.top_down.at(),
+        }
+    }
+
+    pub fn eye_dir(&self) -> Vector3<f32> {
+        (self.at() - self.eye()).normalize()
+    }
+
+    /// Movement speed multiplier of the current mode
+    pub fn speed(&self) -> f32 {
+        self.speeds[self.mode as usize]
+    }
+
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speeds[self.mode as usize] = speed;
+        match self.mode {
+            CameraMode::FirstPerson => self.first_person.set_move_step(speed),
+            CameraMode::ArcBall => (),
+            CameraMode::TopDown => self.top_down.set_move_step(0.01 * speed),
+        }
+    }
+
+    /// View of the current mode, the TopDown eye is high enough above the
+    /// point looked at for a perspective camera to see the same area there
+    fn view(&self) -> View {
+        let (eye, at, ortho) = match self.mode {
+            CameraMode::FirstPerson => (self.first_person.eye(), self.first_person.at(), 0.0),
+            CameraMode::ArcBall => (self.arc_ball.eye(), self.arc_ball.at(), 0.0),
+            CameraMode::TopDown => {
+                let at = self.top_down.at();
+                let dist = self.top_down.extent() / (CAMERA_FOV / 2.0).tan();
+                (at + Vector3::new(0.0, dist, 0.0), at, 1.0)
+            }
+        };
+        View { eye, at, ortho }
+    }
+
+    fn active(&self) -> &dyn Camera {
+        if self.transition.is_some() {
+            return &self.transition_cam;
+        }
+        match self.mode {
+            CameraMode::FirstPerson => &self.first_person,
+            CameraMode::ArcBall => &self.arc_ball,
+            CameraMode::TopDown => &self.top_down,
+        }
+    }
+
+    /// Orbit with AD and zoom with WS, the ArcBall has no key bindings
+    fn update_arc_ball(&mut self, canvas: &Canvas) {
+        let pressed = |key| canvas.get_key(key) == Action::Press;
+        let step = 0.02 * self.speed();
+
+        if pressed(Key::A) {
+            self.arc_ball.set_yaw(self.arc_ball.yaw() + step);
+        }
+        if pressed(Key::D) {
+            self.arc_ball.set_yaw(self.arc_ball.yaw() - step);
+        }
+        if pressed(Key::W) {
+            self.arc_ball.set_dist(self.arc_ball.dist() * (1.0 - step));
+        }
+        if pressed(Key::S) {
+            self.arc_ball.set_dist(self.arc_ball.dist() * (1.0 + step));
+        }
+    }
+}
+
+impl Camera for CameraRig {
+    fn handle_event(&mut self, canvas: &Canvas, event: &WindowEvent) {
+        let released = |button| canvas.get_mouse_button(button) == Action::Release;
+        let sync = match *event {
+            // Keep the aspect ratio of the idle cameras up to date
+            WindowEvent::FramebufferSize(..) => true,
+            // Nothing moves while no button is held, the idle cameras only
+            // track the cursor so they don't jump once they're active
+            WindowEvent::CursorPos(..) => {
+                released(MouseButton::Button1) && released(MouseButton::Button2)
+            }
+            _ => false,
+        };
+        if sync {
+            self.first_person.handle_event(canvas, event);
+            self.arc_ball.handle_event(canvas, event);
+            self.top_down.handle_event(canvas, event);
+            self.transition_cam.handle_event(canvas, event);
+            return;
+        }
+
+        if self.transition.is_some() {
+            return;
+        }
+        match self.mode {
+            CameraMode::FirstPerson => self.first_person.handle_event(canvas, event),
+            CameraMode::ArcBall => self.arc_ball.handle_event(canvas, event),
+            CameraMode::TopDown => self.top_down.handle_event(canvas, event),
+        }
+    }
+
+    fn eye(&self) -> Point3<f32> {
+        self.active().eye()
+    }
+
+    fn view_transform(&self) -> Isometry3<f32> {
+        self.active().view_transform()
+    }
+
+    fn transformation(&self) -> Matrix4<f32> {
+        self.active().transformation()
+    }
+
+    fn inverse_transformation(&self) -> Matrix4<f32> {
+        self.active().inverse_transformation()
+    }
+
+    fn clip_planes(&self) -> (f32, f32) {
+        self.active().clip_planes()
+    }
+
+    fn update(&mut self, canvas: &Canvas) {
+        if let Some(transition) = &self.transition {
+            let t =
+                transition.start.elapsed().as_millis() as f32 / TRANSITION_TIME.as_millis() as f32;
+            if t >= 1.0 {
+                self.transition = None;
+            } else {
+                let t = t * t * (3.0 - 2.0 * t);
+                let (from, to) = (transition.from, transition.to);
+                self.transition_cam.set_view(View {
+                    eye: from.eye + (to.eye - from.eye) * t,
+                    at: from.at + (to.at - from.at) * t,
+                    ortho: from.ortho + (to.ortho - from.ortho) * t,
+                });
+                return;
+            }
+        }
+
+        match self.mode {
+            CameraMode::FirstPerson => self.first_person.update(canvas),
+            CameraMode::ArcBall => self.update_arc_ball(canvas),
+            CameraMode::TopDown => self.top_down.update(canvas),
+        }
+    }
+
+    fn upload(
+        &self,
+        pass: usize,
+        proj: &mut ShaderUniform<Matrix4<f32>>,
+        view: &mut ShaderUniform<Matrix4<f32>>,
+    ) {
+        self.active().upload(pass, proj, view);
+    }
+}
+
+/// Camera of the transitions, blends the perspective projection into an
+/// orthographic one showing the same area at the point looked at
+struct BlendCam {
+    view: View,
+    aspect: f32,
+    proj: Matrix4<f32>,
+    view_matrix: Matrix4<f32>,
+    proj_view: Matrix4<f32>,
+    inverse_proj_view: Matrix4<f32>,
+}
+
+impl BlendCam {
+    fn new() -> Self {
+        let mut cam = Self {
+            view: View {
+                eye: Point3::new(1.0, 1.0, 1.0),
+                at: Point3::origin(),
+                ortho: 0.0,
+            },
+            aspect: 800.0 / 600.0,
+            proj: Matrix4::identity(),
+            view_matrix: Matrix4::identity(),
+            proj_view: Matrix4::identity(),
+            inverse_proj_view: Matrix4::identity(),
+        };
+        cam.update_projviews();
+        cam
+    }
+
+    fn view(&self) -> View {
+        self.view
+    }
+
+    fn set_view(&mut self, view: View) {
+        self.view = view;
+        self.update_projviews();
+    }
+
+    fn at(&self) -> Point3<f32> {
+        self.view.at
+    }
+
+    fn dist(&self) -> f32 {
+        (self.view.at - self.view.eye).norm().max(ZNEAR)
+    }
+
+    fn zfar(&self) -> f32 {
+        ZFAR.max(4.0 * self.dist())
+    }
+
+    /// Screen up is +y in perspective and +z looking straight down, like the
+    /// TopDown view
+    fn up(&self) -> Vector3<f32> {
+        let dir = (self.view.at - self.view.eye) / self.dist();
+        let up = Vector3::y() * (1.0 - self.view.ortho) + Vector3::z() * self.view.ortho;
+        if up.normalize().dot(&dir).abs() > 0.999 {
+            Vector3::z()
+        } else {
+            up
+        }
+    }
+
+    fn update_projviews(&mut self) {
+        let (dist, zfar) = (self.dist(), self.zfar());
+        let perspective = Perspective3::new(self.aspect, CAMERA_FOV, ZNEAR, zfar);
+
+        // Same area as the perspective at the point looked at, scaled by its
+        // distance so the w of both projections match there
+        let h = dist * (CAMERA_FOV / 2.0).tan();
+        let w = h * self.aspect;
+        let orthographic = Orthographic3::new(-w, w, -h, h, ZNEAR, zfar);
+
+        let ortho = self.view.ortho;
+        self.proj = perspective.to_homogeneous() * (1.0 - ortho)
+            + orthographic.to_homogeneous() * (dist * ortho);
+        self.view_matrix = self.view_transform().to_homogeneous();
+        self.proj_view = self.proj * self.view_matrix;
+        if let Some(inverse) = self.proj_view.try_inverse() {
+            self.inverse_proj_view = inverse;
+        }
+    }
+}
+
+impl Camera for BlendCam {
+    fn handle_event(&mut self, _: &Canvas, event: &WindowEvent) {
+        if let WindowEvent::FramebufferSize(w, h) = *event {
+            self.aspect = w as f32 / h.max(1) as f32;
+            self.update_projviews();
+        }
+    }
+
+    fn eye(&self) -> Point3<f32> {
+        self.view.eye
+    }
+
+    fn view_transform(&self) -> Isometry3<f32> {
+        Isometry3::look_at_rh(&self.view.eye, &self.view.at, &self.up())
+    }
+
+    fn transformation(&self) -> Matrix4<f32> {
+        self.proj_view
+    }
+
+    fn inverse_transformation(&self) -> Matrix4<f32> {
+        self.inverse_proj_view
+    }
+
+    fn clip_planes(&self) -> (f32, f32) {
+        (ZNEAR, self.zfar())
+    }
+
+    fn update(&mut self, _: &Canvas) {}
+
+    fn upload(
+        &self,
+        _: usize,
+        proj: &mut ShaderUniform<Matrix4<f32>>,
+        view: &mut ShaderUniform<Matrix4<f32>>,
+    ) {
+        proj.upload(&self.proj);
+        view.upload(&self.view_matrix);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project(cam: &BlendCam, p: &Point3<f32>) -> Point3<f32> {
+        let h = cam.transformation() * p.to_homogeneous();
+        Point3::from_homogeneous(h).unwrap()
+    }
+
+    #[test]
+    fn blend_keeps_the_area_at_the_point_looked_at() {
+        let mut cam = BlendCam::new();
+        let (eye, at) = (Point3::new(0.0, 50.0, 0.0), Point3::new(0.0, 0.0, 0.0));
+        // On the ground around the point looked at
+        let p = Point3::new(7.0, 0.0, 3.0);
+
+        cam.set_view(View {
+            eye,
+            at,
+            ortho: 0.0,
+        });
+        let expected = project(&cam, &p);
+        for ortho in &[0.25, 0.5, 1.0] {
+            cam.set_view(View {
+                eye,
+                at,
+                ortho: *ortho,
+            });
+            let q = project(&cam, &p);
+            assert!((q.x - expected.x).abs() < 1e-4 && (q.y - expected.y).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn blend_flattens_relief_when_orthographic() {
+        let mut cam = BlendCam::new();
+        let (eye, at) = (Point3::new(0.0, 50.0, 0.0), Point3::new(0.0, 0.0, 0.0));
+        let (low, high) = (Point3::new(7.0, 0.0, 3.0), Point3::new(7.0, 20.0, 3.0));
+
+        cam.set_view(View {
+            eye,
+            at,
+            ortho: 1.0,
+        });
+        let (a, b) = (project(&cam, &low), project(&cam, &high));
+        assert!((a.x - b.x).abs() < 1e-4 && (a.y - b.y).abs() < 1e-4);
+
+        cam.set_view(View {
+            eye,
+            at,
+            ortho: 0.0,
+        });
+        let (a, b) = (project(&cam, &low), project(&cam, &high));
+        assert!((a.x - b.x).abs() > 0.01);
+    }
+}
@@ -0,0 +1,204 @@
+use image::{DynamicImage, FilterType, ImageBuffer, Rgba};
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+use std::f32;
+use std::fmt;
+use std::str::FromStr;
+
+/// Maximum number of classes, one per alphamap channel
+pub const MAX_CLASSES: usize = 4;
+
+/// Pixels sampled for k-means clustering, the classification still covers
+/// every pixel
+const KMEANS_SAMPLES: usize = 20_000;
+const KMEANS_ITERATIONS: usize = 20;
+
+/// 8 bit RGB color, parsed from RRGGBB hex
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Color(pub [u8; 3]);
+
+/// How the class colors are chosen
+#[derive(Clone, Debug, PartialEq)]
+pub enum Method {
+    /// Cluster the image colors into the given number of classes
+    KMeans { classes: usize, seed: u64 },
+    /// User picked class colors
+    Reference(Vec<Color>),
+}
+
+/// Classify the pixels of a color image into up to 4 classes, resampled to
+/// width x height
+///
+/// Returns the alphamap, with soft class weights in the channels adding up
+/// to 255, and the class colors in channel order. The alphamap only has an
+/// alpha channel with 4 classes. `softness` is the color distance, in 8 bit
+/// units, over which neighboring classes blend.
+pub fn classify(
+    img: &DynamicImage,
+    width: usize,
+    height: usize,
+    method: &Method,
+    softness: f32,
+) -> (DynamicImage, Vec<Color>) {
+    let img = img
+        .resize_exact(width as _, height as _, FilterType::Triangle)
+        .to_rgb();
+    let pixels: Vec<[f32; 3]> = img.pixels().map(|p| to_f32(p.data)).collect();
+
+    let centers = match method {
+        Method::KMeans { classes, seed } => kmeans(&pixels, *classes, *seed),
+        Method::Reference(colors) => colors.iter().map(|c| to_f32(c.0)).collect(),
+    };
+    let centers = &centers[..centers.len().min(MAX_CLASSES)];
+
+    let two_sigma_sq = 2.0 * softness.max(1.0).powi(2);
+    let alphamap =
+        DynamicImage::ImageRgba8(ImageBuffer::from_fn(width as _, height as _, |x, y| {
+            let p = pixels[x as usize + y as usize * width];
+            let dists: Vec<f32> = centers.iter().map(|c| distance_sq(&p, c)).collect();
+
+            // Relative to the closest class, keeps far off colors from
+            // underflowing to all zero weights
+            let min = dists.iter().cloned().fold(f32::MAX, f32::min);
+            let mut weights = [0.0; MAX_CLASSES];
+            for (w, d) in weights.iter_mut().zip(dists.iter()) {
+                *w = (-(d - min) / two_sigma_sq).exp();
+            }
+            let sum: f32 = weights.iter().sum();
+
+            let c = |w: f32| (w / sum * 255.0).round() as u8;
+            Rgba([c(weights[0]), c(weights[1]), c(weights[2]), c(weights[3])])
+        }));
+    let alphamap = if centers.len() < MAX_CLASSES {
+        DynamicImage::ImageRgb8(alphamap.to_rgb())
+    } else {
+        alphamap
+    };
+
+    let colors = centers
+        .iter()
+        .map(|c| Color([c[0] as u8, c[1] as u8, c[2] as u8]))
+        .collect();
+
+    (alphamap, colors)
+}
+
+/// k-means++ seeded clustering of a sample of the pixels, the clusters are
+/// sorted dark to bright so the channel order is stable
+fn kmeans(pixels: &[[f32; 3]], classes: usize, seed: u64) -> Vec<[f32; 3]> {
+    let mut rng = Pcg64::seed_from_u64(seed);
+    let classes = classes.clamp(1, MAX_CLASSES);
+
+    let step = (pixels.len() / KMEANS_SAMPLES).max(1);
+    let samples: Vec<[f32; 3]> = pixels.iter().step_by(step).cloned().collect();
+
+    // Pick initial centers far apart from each other
+    let mut centers = vec![samples[rng.gen_range(0, samples.len())]];
+    while centers.len() < classes {
+        let dists: Vec<f32> = samples
+            .iter()
+            .map(|s| {
+                centers
+                    .iter()
+                    .map(|c| distance_sq(s, c))
+                    .fold(f32::MAX, f32::min)
+            })
+            .collect();
+        let total: f32 = dists.iter().sum();
+        if total == 0.0 {
+            // Fewer distinct colors than classes
+            break;
+        }
+
+        let mut target = rng.gen_range(0.0, total);
+        let mut index = samples.len() - 1;
+        for (i, d) in dists.iter().enumerate() {
+            if target < *d {
+                index = i;
+                break;
+            }
+            target -= d;
+        }
+        centers.push(samples[index]);
+    }
+
+    for _ in 0..KMEANS_ITERATIONS {
+        let mut sums = vec![[0.0; 3]; centers.len()];
+        let mut counts = vec![0; centers.len()];
+        for s in &samples {
+            let i = closest(s, &centers);
+            for (sum, v) in sums[i].iter_mut().zip(s.iter()) {
+                *sum += v;
+            }
+            counts[i] += 1;
+        }
+
+        let mut changed = false;
+        for (c, (sum, count)) in centers.iter_mut().zip(sums.iter().zip(counts.iter())) {
+            if *count == 0 {
+                continue;
+            }
+            let mean = [
+                sum[0] / *count as f32,
+                sum[1] / *count as f32,
+                sum[2] / *count as f32,
+            ];
+            changed |= distance_sq(c, &mean) > 0.01;
+            *c = mean;
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    centers.sort_by(|a, b| {
+        let (la, lb) = (a.iter().sum::<f32>(), b.iter().sum::<f32>());
+        la.partial_cmp(&lb).unwrap_or(std::cmp::Ordering::Equal)
+    });
+    centers
+}
+
+fn closest(p: &[f32; 3], centers: &[[f32; 3]]) -> usize {
+    let mut best = 0;
+    let mut best_dist = f32::MAX;
+    for (i, c) in centers.iter().enumerate() {
+        let d = distance_sq(p, c);
+        if d < best_dist {
+            best = i;
+            best_dist = d;
+        }
+    }
+    best
+}
+
+fn distance_sq(a: &[f32; 3], b: &[f32; 3]) -> f32 {
+    (a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)
+}
+
+fn to_f32(c: [u8; 3]) -> [f32; 3] {
+    [f32::from(c[0]), f32::from(c[1]), f32::from(c[2])]
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("Invalid color '{}', use RRGGBB hex", s);
+        let hex = s.trim_start_matches('#');
+        if hex.len() != 6 {
+            return Err(err());
+        }
+        let channel = |i: usize| {
+            hex.get(i * 2..i * 2 + 2)
+                .and_then(|c| u8::from_str_radix(c, 16).ok())
+                .ok_or_else(err)
+        };
+        Ok(Color([channel(0)?, channel(1)?, channel(2)?]))
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.0[0], self.0[1], self.0[2])
+    }
+}
@@ -0,0 +1,135 @@
+use std::str::FromStr;
+
+/// Color ramps mapping normalized values to colors
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ColorRamp {
+    /// Hypsometric tints, green lowlands through brown to white peaks
+    Terrain,
+    /// Perceptually uniform blue-green-yellow
+    Viridis,
+    /// Blue to red through white
+    Diverging,
+    Grayscale,
+}
+
+impl ColorRamp {
+    pub fn next(self) -> Self {
+        match self {
+            ColorRamp::Terrain => ColorRamp::Viridis,
+            ColorRamp::Viridis => ColorRamp::Diverging,
+            ColorRamp::Diverging => ColorRamp::Grayscale,
+            ColorRamp::Grayscale => ColorRamp::Terrain,
+        }
+    }
+
+    /// Color at `t` in [0, 1], clamped
+    pub fn color(self, t: f32) -> [u8; 3] {
+        let stops = self.stops();
+        let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+
+        let i = stops
+            .iter()
+            .position(|s| s.0 >= t)
+            .unwrap_or(stops.len() - 1)
+            .max(1);
+        let (t0, c0) = stops[i - 1];
+        let (t1, c1) = stops[i];
+        let f = if t1 > t0 { (t - t0) / (t1 - t0) } else { 0.0 };
+
+        let lerp = |a: u8, b: u8| (f32::from(a) + (f32::from(b) - f32::from(a)) * f).round() as u8;
+        [lerp(c0[0], c1[0]), lerp(c0[1], c1[1]), lerp(c0[2], c1[2])]
+    }
+
+    /// Color as floats in [0, 1], i.e. for drawing text
+    pub fn color_f32(self, t: f32) -> [f32; 3] {
+        let c = self.color(t);
+        [
+            f32::from(c[0]) / 255.0,
+            f32::from(c[1]) / 255.0,
+            f32::from(c[2]) / 255.0,
+        ]
+    }
+
+    fn stops(self) -> &'static [(f32, [u8; 3])] {
+        match self {
+            ColorRamp::Terrain => &[
+                (0.0, [46, 110, 60]),
+                (0.25, [120, 168, 80]),
+                (0.5, [222, 206, 128]),
+                (0.75, [150, 104, 64]),
+                (0.9, [170, 160, 150]),
+                (1.0, [255, 255, 255]),
+            ],
+            ColorRamp::Viridis => &[
+                (0.0, [68, 1, 84]),
+                (0.25, [59, 82, 139]),
+                (0.5, [33, 145, 140]),
+                (0.75, [94, 201, 98]),
+                (1.0, [253, 231, 37]),
+            ],
+            ColorRamp::Diverging => &[
+                (0.0, [59, 76, 192]),
+                (0.5, [221, 221, 221]),
+                (1.0, [180, 4, 38]),
+            ],
+            ColorRamp::Grayscale => &[(0.0, [0, 0, 0]), (1.0, [255, 255, 255])],
+        }
+    }
+}
+
+impl FromStr for ColorRamp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "terrain" => Ok(ColorRamp::Terrain),
+            "viridis" => Ok(ColorRamp::Viridis),
+            "diverging" => Ok(ColorRamp::Diverging),
+            "grayscale" => Ok(ColorRamp::Grayscale),
+            _ => Err(format!(
+                "Invalid color ramp '{}', use terrain, viridis, diverging or grayscale",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RAMPS: [ColorRamp; 4] = [
+        ColorRamp::Terrain,
+        ColorRamp::Viridis,
+        ColorRamp::Diverging,
+        ColorRamp::Grayscale,
+    ];
+
+    #[test]
+    fn endpoints_are_the_first_and_last_stops() {
+        for ramp in &RAMPS {
+            let stops = ramp.stops();
+            assert_eq!(ramp.color(0.0), stops[0].1);
+            assert_eq!(ramp.color(1.0), stops[stops.len() - 1].1);
+        }
+    }
+
+    #[test]
+    fn clamps_outside_the_unit_range() {
+        for ramp in &RAMPS {
+            assert_eq!(ramp.color(-0.5), ramp.color(0.0));
+            assert_eq!(ramp.color(f32::NAN), ramp.color(0.0));
+            assert_eq!(ramp.color(3.0), ramp.color(1.0));
+        }
+    }
+
+    #[test]
+    fn interpolates_between_stops() {
+        assert_eq!(ColorRamp::Grayscale.color(0.5), [128, 128, 128]);
+        assert_eq!(ColorRamp::Terrain.color(0.25), [120, 168, 80]);
+        // Halfway between the 0.75 and 0.9 terrain stops
+        assert_eq!(ColorRamp::Terrain.color(0.825), [160, 132, 107]);
+        // A quarter of the way from blue to the white midpoint
+        assert_eq!(ColorRamp::Diverging.color(0.125), [100, 112, 199]);
+    }
+}
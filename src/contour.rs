@@ -0,0 +1,323 @@
+use crate::geo_transform::GeoTransform;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Contour polyline at an elevation, in pixel coordinates with pixel centers
+/// at whole numbers
+#[derive(Clone, Debug, PartialEq)]
+pub struct Contour {
+    pub level: f32,
+    pub points: Vec<(f32, f32)>,
+}
+
+/// Every nth contour is drawn as an index contour in exports
+pub const INDEX_EVERY: usize = 5;
+
+/// Most contour levels traced over the elevation range
+pub const MAX_LEVELS: i64 = 10_000;
+
+/// Parse a contour interval, it has to be positive
+pub fn parse_interval(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(interval) if interval > 0.0 && interval.is_finite() => Ok(interval),
+        _ => Err(format!("Invalid interval '{}', use a positive number", s)),
+    }
+}
+
+/// Pick a round interval giving roughly 20 contours over the value range
+pub fn auto_interval(value_range: (f32, f32)) -> f32 {
+    let range = (value_range.1 - value_range.0).max(f32::EPSILON);
+    let raw = range / 20.0;
+    let magnitude = 10f32.powf(raw.log10().floor());
+    [1.0, 2.0, 2.5, 5.0, 10.0]
+        .iter()
+        .map(|m| m * magnitude)
+        .find(|i| *i >= raw)
+        .unwrap_or(10.0 * magnitude)
+}
+
+/// Trace contours of a width x height grid of elevations with marching
+/// squares, at every multiple of `interval` offset by `base`
+///
+/// Fails if the interval is not positive or gives more than `MAX_LEVELS`
+/// levels over the elevation range.
+pub fn trace(
+    data: &[f32],
+    width: usize,
+    height: usize,
+    interval: f32,
+    base: f32,
+) -> Result<Vec<Contour>, String> {
+    if !(interval > 0.0 && interval.is_finite() && base.is_finite()) {
+        return Err(format!(
+            "Invalid contour interval {} and base {}",
+            interval, base
+        ));
+    }
+    if width < 2 || height < 2 {
+        return Ok(Vec::new());
+    }
+
+    let (min, max) = data.iter().fold((f32::MAX, f32::MIN), |(min, max), e| {
+        (min.min(*e), max.max(*e))
+    });
+    let levels = (f64::from(max) - f64::from(min)) / f64::from(interval);
+    if levels > MAX_LEVELS as f64 {
+        return Err(format!(
+            "A contour interval of {} gives more than {} levels, use a larger one",
+            interval, MAX_LEVELS
+        ));
+    }
+    let first = ((min - base) / interval).ceil() as i64;
+    let last = ((max - base) / interval).floor() as i64;
+
+    let mut contours = Vec::new();
+    for i in first..=last {
+        let level = base + i as f32 * interval;
+        for points in trace_level(data, width, height, level) {
+            contours.push(Contour { level, points });
+        }
+    }
+    Ok(contours)
+}
+
+/// Segments of a single level, joined into polylines
+fn trace_level(data: &[f32], width: usize, height: usize, level: f32) -> Vec<Vec<(f32, f32)>> {
+    // Segment ends are identified by the grid edge they cross, edge
+    // (x, y, 0) runs right from pixel (x, y), edge (x, y, 1) runs down
+    type Edge = (usize, usize, u8);
+    let e = |x: usize, y: usize| data[x + y * width];
+
+    let crossing = |edge: Edge| -> (f32, f32) {
+        let (x, y, dir) = edge;
+        let (x1, y1) = if dir == 0 { (x + 1, y) } else { (x, y + 1) };
+        let (a, b) = (e(x, y), e(x1, y1));
+        let t = if b != a { (level - a) / (b - a) } else { 0.5 };
+        (
+            x as f32 + t * (x1 - x) as f32,
+            y as f32 + t * (y1 - y) as f32,
+        )
+    };
+
+    let mut segments: Vec<(Edge, Edge)> = Vec::new();
+    for y in 0..height - 1 {
+        for x in 0..width - 1 {
+            // Corners clockwise from the top left, levels are treated as above
+            let nw = e(x, y) >= level;
+            let ne = e(x + 1, y) >= level;
+            let se = e(x + 1, y + 1) >= level;
+            let sw = e(x, y + 1) >= level;
+            let case = (nw as u8) << 3 | (ne as u8) << 2 | (se as u8) << 1 | sw as u8;
+
+            let top = (x, y, 0);
+            let bottom = (x, y + 1, 0);
+            let left = (x, y, 1);
+            let right = (x + 1, y, 1);
+
+            match case {
+                0 | 15 => (),
+                1 | 14 => segments.push((left, bottom)),
+                2 | 13 => segments.push((bottom, right)),
+                3 | 12 => segments.push((left, right)),
+                4 | 11 => segments.push((top, right)),
+                6 | 9 => segments.push((top, bottom)),
+                7 | 8 => segments.push((left, top)),
+                5 | 10 => {
+                    // Saddle, resolved by the average of the corners
+                    let center = (e(x, y) + e(x + 1, y) + e(x + 1, y + 1) + e(x, y + 1)) / 4.0;
+                    if (center >= level) == (case == 5) {
+                        segments.push((left, top));
+                        segments.push((bottom, right));
+                    } else {
+                        segments.push((left, bottom));
+                        segments.push((top, right));
+                    }
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    // Join segments sharing an edge into polylines
+    let mut by_edge: HashMap<Edge, Vec<usize>> = HashMap::new();
+    for (i, (a, b)) in segments.iter().enumerate() {
+        by_edge.entry(*a).or_default().push(i);
+        by_edge.entry(*b).or_default().push(i);
+    }
+
+    let mut used = vec![false; segments.len()];
+    let mut lines = Vec::new();
+    for start in 0..segments.len() {
+        if used[start] {
+            continue;
+        }
+        used[start] = true;
+        let (a, b) = segments[start];
+        let mut line = vec![a, b];
+
+        // Extend forwards from the end, then backwards from the start
+        for backwards in &[false, true] {
+            loop {
+                let end = if *backwards {
+                    line[0]
+                } else {
+                    line[line.len() - 1]
+                };
+                let next = by_edge[&end].iter().cloned().find(|i| !used[*i]);
+                let next = match next {
+                    Some(i) => i,
+                    None => break,
+                };
+                used[next] = true;
+                let (na, nb) = segments[next];
+                let other = if na == end { nb } else { na };
+                if *backwards {
+                    line.insert(0, other);
+                } else {
+                    line.push(other);
+                }
+            }
+        }
+
+        lines.push(line.into_iter().map(crossing).collect());
+    }
+    lines
+}
+
+/// Write contours as SVG paths, in pixel coordinates
+pub fn write_svg(
+    file_path: &Path,
+    contours: &[Contour],
+    interval: f32,
+    width: usize,
+    height: usize,
+) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(file_path)?);
+
+    writeln!(
+        w,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" viewBox="-0.5 -0.5 {} {}">"#,
+        width, height, width, height
+    )?;
+    writeln!(
+        w,
+        r#"<g fill="none" stroke="rgb(120,72,32)" stroke-linejoin="round">"#
+    )?;
+
+    for c in contours {
+        let index = is_index(c.level, interval);
+        write!(
+            w,
+            r#"<path data-elevation="{}" stroke-width="{}" d=""#,
+            c.level,
+            if index { 1.5 } else { 0.5 }
+        )?;
+        for (i, (x, y)) in c.points.iter().enumerate() {
+            write!(w, "{}{:.2},{:.2}", if i == 0 { "M" } else { " L" }, x, y)?;
+        }
+        writeln!(w, r#""/>"#)?;
+    }
+
+    writeln!(w, "</g>")?;
+    writeln!(w, "</svg>")?;
+    Ok(())
+}
+
+/// Write contours as GeoJSON LineStrings, in world coordinates, with the
+/// elevation as a property
+pub fn write_geojson(
+    file_path: &Path,
+    contours: &[Contour],
+    interval: f32,
+    geo: &GeoTransform,
+) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(file_path)?);
+
+    writeln!(w, r#"{{"type":"FeatureCollection","features":["#)?;
+    for (i, c) in contours.iter().enumerate() {
+        write!(
+            w,
+            r#"{{"type":"Feature","properties":{{"elevation":{},"index":{}}},"geometry":{{"type":"LineString","coordinates":["#,
+            c.level,
+            is_index(c.level, interval)
+        )?;
+        for (j, (x, y)) in c.points.iter().enumerate() {
+            let (wx, wy) = geo.pixel_to_world(f64::from(*x), f64::from(*y));
+            write!(w, "{}[{},{}]", if j == 0 { "" } else { "," }, wx, wy)?;
+        }
+        writeln!(w, "]}}}}{}", if i + 1 < contours.len() { "," } else { "" })?;
+    }
+    writeln!(w, "]}}")?;
+    Ok(())
+}
+
+/// Index contours are at every INDEX_EVERY multiple of the interval
+pub fn is_index(level: f32, interval: f32) -> bool {
+    let n = (level / interval).round() as i64;
+    n % INDEX_EVERY as i64 == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn traces_straight_lines_on_a_ramp() {
+        // Elevation is the x coordinate
+        let data = [0.0, 1.0, 2.0, 0.0, 1.0, 2.0, 0.0, 1.0, 2.0];
+        let contours = trace(&data, 3, 3, 1.0, 0.25).unwrap();
+
+        assert_eq!(contours.len(), 2);
+        for (contour, x) in contours.iter().zip(&[0.25, 1.25]) {
+            let mut points = contour.points.clone();
+            points.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+            assert_eq!(points, vec![(*x, 0.0), (*x, 1.0), (*x, 2.0)]);
+        }
+    }
+
+    #[test]
+    fn traces_closed_loop_around_a_peak() {
+        let data = [0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0];
+        let contours = trace(&data, 3, 3, 0.5, 0.0).unwrap();
+
+        // Levels 0 and 1 touch the min/max, only 0.5 crosses the grid
+        let loops: Vec<&Contour> = contours.iter().filter(|c| c.level == 0.5).collect();
+        assert_eq!(loops.len(), 1);
+
+        let points = &loops[0].points;
+        assert_eq!(points.len(), 5);
+        assert_eq!(points[0], points[4]);
+        for p in &[(0.5, 1.0), (1.0, 0.5), (1.5, 1.0), (1.0, 1.5)] {
+            assert!(points.contains(p));
+        }
+    }
+
+    #[test]
+    fn auto_interval_is_round() {
+        assert_eq!(auto_interval((0.0, 100.0)), 5.0);
+        assert_eq!(auto_interval((0.0, 30.0)), 2.0);
+        assert!((auto_interval((0.0, 0.9)) - 0.05).abs() < 1e-6);
+        assert_eq!(auto_interval((120.0, 2120.0)), 100.0);
+    }
+
+    #[test]
+    fn rejects_invalid_intervals() {
+        let data = [0.0, 1.0, 2.0, 3.0];
+        for interval in &[0.0, -1.0, f32::NAN, f32::INFINITY] {
+            assert!(trace(&data, 2, 2, *interval, 0.0).is_err());
+        }
+        assert!(trace(&data, 2, 2, 1.0, f32::NAN).is_err());
+        assert!(parse_interval("0").is_err());
+        assert!(parse_interval("inf").is_err());
+        assert_eq!(parse_interval("2.5"), Ok(2.5));
+    }
+
+    #[test]
+    fn caps_the_level_count() {
+        let data = [0.0, 1.0, 2.0, 3000.0];
+        assert!(trace(&data, 2, 2, 0.1, 0.0).is_err());
+        assert!(trace(&data, 2, 2, 1.0, 0.0).is_ok());
+    }
+}
@@ -0,0 +1,383 @@
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+
+/// Particle based hydraulic erosion parameters
+///
+/// Heights are normalized to the heightmap value range while simulating,
+/// so the parameters behave the same for PNG heightmaps and DEMs.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HydraulicParams {
+    /// Number of droplets to simulate
+    pub iterations: usize,
+    /// Initial water volume of a droplet
+    pub rain: f32,
+    /// How much a droplet keeps its direction instead of following the slope
+    pub inertia: f32,
+    /// Sediment a droplet can carry, per unit of speed/water/slope
+    pub sediment_capacity: f32,
+    /// Lower bound on the slope used for the capacity, keeps flat areas eroding
+    pub min_slope: f32,
+    /// Fraction of free capacity eroded per step
+    pub erode_speed: f32,
+    /// Fraction of excess sediment deposited per step
+    pub deposit_speed: f32,
+    /// Fraction of water evaporated per step
+    pub evaporation: f32,
+    pub gravity: f32,
+    /// Maximum number of steps a droplet is simulated for
+    pub max_lifetime: usize,
+    /// Radius, in pixels, of the brush eroding around a droplet
+    pub radius: usize,
+}
+
+impl Default for HydraulicParams {
+    fn default() -> Self {
+        Self {
+            iterations: 50_000,
+            rain: 1.0,
+            inertia: 0.05,
+            sediment_capacity: 4.0,
+            min_slope: 0.01,
+            erode_speed: 0.3,
+            deposit_speed: 0.3,
+            evaporation: 0.01,
+            gravity: 4.0,
+            max_lifetime: 30,
+            radius: 3,
+        }
+    }
+}
+
+/// Thermal (talus) erosion parameters
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ThermalParams {
+    pub iterations: usize,
+    /// Material slides down slopes steeper than this angle, in degrees
+    pub talus_angle: f32,
+    /// Fraction of the excess material moved per iteration
+    pub rate: f32,
+}
+
+impl Default for ThermalParams {
+    fn default() -> Self {
+        Self {
+            iterations: 50,
+            talus_angle: 35.0,
+            rate: 0.5,
+        }
+    }
+}
+
+/// Simulate rain droplets eroding and depositing sediment, deterministic
+/// for a given seed
+pub fn hydraulic(
+    data: &mut [f32],
+    width: usize,
+    height: usize,
+    value_range: (f32, f32),
+    params: &HydraulicParams,
+    seed: u64,
+) {
+    if width < 3 || height < 3 {
+        return;
+    }
+
+    let mut rng = Pcg64::seed_from_u64(seed);
+    let mut map = Normalized::new(data, value_range);
+    let brush = Brush::new(params.radius);
+
+    for _ in 0..params.iterations {
+        let mut px = rng.gen_range(0.0, (width - 1) as f32);
+        let mut py = rng.gen_range(0.0, (height - 1) as f32);
+        let mut dir_x = 0.0;
+        let mut dir_y = 0.0;
+        let mut speed = 1.0;
+        let mut water = params.rain;
+        let mut sediment = 0.0;
+
+        for _ in 0..params.max_lifetime {
+            let (node_x, node_y) = (px as usize, py as usize);
+            // Offset within the cell, sediment is deposited here once the
+            // droplet has moved on
+            let (u, v) = (px - node_x as f32, py - node_y as f32);
+            let (h, gx, gy) = height_and_gradient(&map.data, width, px, py);
+
+            // Blend the previous direction with the downhill direction
+            dir_x = dir_x * params.inertia - gx * (1.0 - params.inertia);
+            dir_y = dir_y * params.inertia - gy * (1.0 - params.inertia);
+            let len = (dir_x * dir_x + dir_y * dir_y).sqrt();
+            if len == 0.0 {
+                break;
+            }
+            dir_x /= len;
+            dir_y /= len;
+            px += dir_x;
+            py += dir_y;
+
+            if px < 0.0 || py < 0.0 || px >= (width - 1) as f32 || py >= (height - 1) as f32 {
+                // The sediment is carried off the map
+                sediment = 0.0;
+                break;
+            }
+
+            let (new_h, _, _) = height_and_gradient(&map.data, width, px, py);
+            let dh = new_h - h;
+
+            let capacity = (-dh).max(params.min_slope) * speed * water * params.sediment_capacity;
+
+            if sediment > capacity || dh > 0.0 {
+                // Fill the pit when moving uphill, otherwise drop the excess
+                let amount = if dh > 0.0 {
+                    dh.min(sediment)
+                } else {
+                    (sediment - capacity) * params.deposit_speed
+                };
+                sediment -= amount;
+                deposit(&mut map.data, width, node_x, node_y, u, v, amount);
+            } else {
+                // Never erode deeper than the height difference, avoids digging holes
+                let amount = ((capacity - sediment) * params.erode_speed).min(-dh);
+                sediment += brush.erode(&mut map.data, width, height, node_x, node_y, amount);
+            }
+
+            speed = (speed * speed + dh * params.gravity).max(0.0).sqrt();
+            water *= 1.0 - params.evaporation;
+        }
+
+        // Drop what's left where the droplet came to rest
+        if sediment > 0.0 {
+            let (node_x, node_y) = (px as usize, py as usize);
+            let (u, v) = (px - node_x as f32, py - node_y as f32);
+            deposit(&mut map.data, width, node_x, node_y, u, v, sediment);
+        }
+    }
+
+    map.restore(data);
+}
+
+/// Move material from cells to lower neighbors where the slope exceeds
+/// the talus angle
+///
+/// `vertical_scale` converts source elevation units to pixels, as displayed.
+pub fn thermal(
+    data: &mut [f32],
+    width: usize,
+    height: usize,
+    vertical_scale: f32,
+    params: &ThermalParams,
+) {
+    // Maximum stable height difference per pixel of distance
+    let talus = params.talus_angle.to_radians().tan() / vertical_scale;
+    let mut delta = vec![0.0; data.len()];
+    let neighbors: [(isize, isize); 8] = [
+        (-1, -1),
+        (0, -1),
+        (1, -1),
+        (-1, 0),
+        (1, 0),
+        (-1, 1),
+        (0, 1),
+        (1, 1),
+    ];
+
+    for _ in 0..params.iterations {
+        for d in delta.iter_mut() {
+            *d = 0.0;
+        }
+
+        for y in 0..height {
+            for x in 0..width {
+                let h = data[x + y * width];
+                let mut excess = [0.0; 8];
+                let mut total_excess = 0.0;
+                let mut max_excess: f32 = 0.0;
+
+                for (i, (dx, dy)) in neighbors.iter().enumerate() {
+                    let (nx, ny) = (x as isize + dx, y as isize + dy);
+                    if nx < 0 || ny < 0 || nx >= width as isize || ny >= height as isize {
+                        continue;
+                    }
+                    let dist = ((dx * dx + dy * dy) as f32).sqrt();
+                    let diff = h - data[nx as usize + ny as usize * width];
+                    let e = diff - talus * dist;
+                    if e > 0.0 {
+                        excess[i] = e;
+                        total_excess += e;
+                        max_excess = max_excess.max(e);
+                    }
+                }
+
+                if total_excess == 0.0 {
+                    continue;
+                }
+
+                // Move half the largest excess, split by each neighbor's share
+                let amount = params.rate * max_excess / 2.0;
+                delta[x + y * width] -= amount;
+                for (i, (dx, dy)) in neighbors.iter().enumerate() {
+                    if excess[i] > 0.0 {
+                        let n = (x as isize + dx) as usize + (y as isize + dy) as usize * width;
+                        delta[n] += amount * excess[i] / total_excess;
+                    }
+                }
+            }
+        }
+
+        for (h, d) in data.iter_mut().zip(delta.iter()) {
+            *h += d;
+        }
+    }
+}
+
+/// Copy of the elevations normalized to [0, 1] over the value range
+struct Normalized {
+    data: Vec<f32>,
+    min: f32,
+    range: f32,
+}
+
+impl Normalized {
+    fn new(data: &[f32], value_range: (f32, f32)) -> Self {
+        let (min, max) = value_range;
+        let range = if max > min { max - min } else { 1.0 };
+        Self {
+            data: data.iter().map(|e| (e - min) / range).collect(),
+            min,
+            range,
+        }
+    }
+
+    fn restore(self, data: &mut [f32]) {
+        for (e, n) in data.iter_mut().zip(self.data.iter()) {
+            *e = self.min + n * self.range;
+        }
+    }
+}
+
+/// Bilinear height and gradient at a position within the grid
+fn height_and_gradient(data: &[f32], width: usize, px: f32, py: f32) -> (f32, f32, f32) {
+    let (x, y) = (px as usize, py as usize);
+    let (u, v) = (px - x as f32, py - y as f32);
+
+    let nw = data[x + y * width];
+    let ne = data[x + 1 + y * width];
+    let sw = data[x + (y + 1) * width];
+    let se = data[x + 1 + (y + 1) * width];
+
+    let gx = (ne - nw) * (1.0 - v) + (se - sw) * v;
+    let gy = (sw - nw) * (1.0 - u) + (se - ne) * u;
+    let h = nw * (1.0 - u) * (1.0 - v) + ne * u * (1.0 - v) + sw * (1.0 - u) * v + se * u * v;
+
+    (h, gx, gy)
+}
+
+/// Deposit sediment on the 4 corners of cell (x, y), bilinearly weighted by
+/// the (u, v) offset of the droplet's previous position within it
+fn deposit(data: &mut [f32], width: usize, x: usize, y: usize, u: f32, v: f32, amount: f32) {
+    data[x + y * width] += amount * (1.0 - u) * (1.0 - v);
+    data[x + 1 + y * width] += amount * u * (1.0 - v);
+    data[x + (y + 1) * width] += amount * (1.0 - u) * v;
+    data[x + 1 + (y + 1) * width] += amount * u * v;
+}
+
+/// Weighted circular area eroded around a droplet
+struct Brush {
+    offsets: Vec<(isize, isize, f32)>,
+}
+
+impl Brush {
+    fn new(radius: usize) -> Self {
+        let r = radius.max(1) as isize;
+        let mut offsets = Vec::new();
+        for dy in -r..=r {
+            for dx in -r..=r {
+                let dist = ((dx * dx + dy * dy) as f32).sqrt();
+                if dist < r as f32 {
+                    offsets.push((dx, dy, r as f32 - dist));
+                }
+            }
+        }
+        let total: f32 = offsets.iter().map(|o| o.2).sum();
+        for o in offsets.iter_mut() {
+            o.2 /= total;
+        }
+        Self { offsets }
+    }
+
+    /// Erode up to `amount` spread over the brush, returns the amount removed
+    fn erode(
+        &self,
+        data: &mut [f32],
+        width: usize,
+        height: usize,
+        x: usize,
+        y: usize,
+        amount: f32,
+    ) -> f32 {
+        let mut removed = 0.0;
+        for &(dx, dy, w) in &self.offsets {
+            let (nx, ny) = (x as isize + dx, y as isize + dy);
+            if nx < 0 || ny < 0 || nx >= width as isize || ny >= height as isize {
+                continue;
+            }
+            let cell = &mut data[nx as usize + ny as usize * width];
+            let delta = (amount * w).min(*cell);
+            *cell -= delta;
+            removed += delta;
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Droplets flow to the middle of a bowl instead of off the edges
+    fn bowl(size: usize) -> Vec<f32> {
+        let c = (size - 1) as f32 / 2.0;
+        (0..size * size)
+            .map(|i| {
+                let (x, y) = ((i % size) as f32 - c, (i / size) as f32 - c);
+                (x * x + y * y) / (2.0 * c * c) + 0.1
+            })
+            .collect()
+    }
+
+    fn params() -> HydraulicParams {
+        HydraulicParams {
+            iterations: 500,
+            evaporation: 0.0,
+            ..HydraulicParams::default()
+        }
+    }
+
+    #[test]
+    fn hydraulic_is_deterministic_per_seed() {
+        let run = |seed| {
+            let mut data = bowl(32);
+            hydraulic(&mut data, 32, 32, (0.0, 1.5), &params(), seed);
+            data
+        };
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+        assert_ne!(run(7), bowl(32));
+    }
+
+    #[test]
+    fn hydraulic_conserves_mass_without_outflow() {
+        let mut data = bowl(32);
+        let before: f64 = data.iter().map(|e| f64::from(*e)).sum();
+        hydraulic(&mut data, 32, 32, (0.0, 1.5), &params(), 1);
+        let after: f64 = data.iter().map(|e| f64::from(*e)).sum();
+
+        assert!((after - before).abs() < 1e-3, "{} != {}", after, before);
+    }
+
+    #[test]
+    fn deposit_weights_by_offset() {
+        let mut data = vec![0.0; 4];
+        deposit(&mut data, 2, 0, 0, 0.25, 0.5, 8.0);
+        assert_eq!(data, vec![3.0, 1.0, 3.0, 1.0]);
+    }
+}
@@ -0,0 +1,245 @@
+use std::f32;
+use std::fmt;
+use std::str::FromStr;
+
+/// Heightmap processing filters
+///
+/// Filters operate on the row-major source elevations, parameters that
+/// refer to elevation levels are normalized to the source value range.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Filter {
+    /// Gaussian blur with the given standard deviation, in pixels
+    GaussianBlur { sigma: f32 },
+    /// Replace cells deviating from their local median by more than
+    /// `k` median absolute deviations, `k` of 0 is a plain median filter
+    Despike { radius: usize, k: f32 },
+    /// Map the normalized [low, high] levels to [0, 1] through a curve
+    Levels { low: f32, high: f32, curve: Curve },
+    /// Smooth the steps of quantized (8 bit) heightmaps, cells are only
+    /// moved within half a quantization step
+    Terrace { radius: usize },
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Curve {
+    Linear,
+    /// Smoothstep s-curve, flattens the lows and highs
+    Smooth,
+    /// Power curve, < 1.0 raises and > 1.0 lowers the mid levels
+    Gamma(f32),
+}
+
+impl Curve {
+    fn eval(self, v: f32) -> f32 {
+        match self {
+            Curve::Linear => v,
+            Curve::Smooth => v * v * (3.0 - 2.0 * v),
+            Curve::Gamma(g) => v.powf(g),
+        }
+    }
+}
+
+/// Apply a filter to a width x height grid of elevations spanning `value_range`
+pub fn apply(
+    filter: &Filter,
+    data: &mut [f32],
+    width: usize,
+    height: usize,
+    value_range: (f32, f32),
+) {
+    assert_eq!(data.len(), width * height);
+
+    match *filter {
+        Filter::GaussianBlur { sigma } => gaussian_blur(data, width, height, sigma),
+        Filter::Despike { radius, k } => despike(data, width, height, radius, k),
+        Filter::Levels { low, high, curve } => levels(data, value_range, low, high, curve),
+        Filter::Terrace { radius } => terrace(data, width, height, radius),
+    }
+}
+
+/// Separable gaussian blur, edges are clamped
+pub fn gaussian_blur(data: &mut [f32], width: usize, height: usize, sigma: f32) {
+    if sigma.is_nan() || sigma <= 0.0 {
+        return;
+    }
+
+    let radius = (sigma * 3.0).ceil() as isize;
+    let kernel: Vec<f32> = (-radius..=radius)
+        .map(|i| (-(i * i) as f32 / (2.0 * sigma * sigma)).exp())
+        .collect();
+    let kernel_sum: f32 = kernel.iter().sum();
+
+    let clamp = |v: isize, max: usize| v.max(0).min(max as isize - 1) as usize;
+    let mut tmp = vec![0.0; data.len()];
+
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = 0.0;
+            for (k, w) in kernel.iter().enumerate() {
+                let sx = clamp(x as isize + k as isize - radius, width);
+                sum += data[sx + y * width] * w;
+            }
+            tmp[x + y * width] = sum / kernel_sum;
+        }
+    }
+
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = 0.0;
+            for (k, w) in kernel.iter().enumerate() {
+                let sy = clamp(y as isize + k as isize - radius, height);
+                sum += tmp[x + sy * width] * w;
+            }
+            data[x + y * width] = sum / kernel_sum;
+        }
+    }
+}
+
+fn despike(data: &mut [f32], width: usize, height: usize, radius: usize, k: f32) {
+    if radius == 0 {
+        return;
+    }
+
+    let src = data.to_vec();
+    let mut window = Vec::with_capacity((2 * radius + 1) * (2 * radius + 1));
+    let mut deviations = Vec::with_capacity(window.capacity());
+
+    for y in 0..height {
+        for x in 0..width {
+            window.clear();
+            for wy in y.saturating_sub(radius)..(y + radius + 1).min(height) {
+                for wx in x.saturating_sub(radius)..(x + radius + 1).min(width) {
+                    window.push(src[wx + wy * width]);
+                }
+            }
+            let local_median = median(&mut window);
+
+            let v = src[x + y * width];
+            let replace = if k > 0.0 {
+                deviations.clear();
+                deviations.extend(window.iter().map(|w| (w - local_median).abs()));
+                let mad = median(&mut deviations);
+                (v - local_median).abs() > k * mad.max(f32::EPSILON)
+            } else {
+                true
+            };
+
+            if replace {
+                data[x + y * width] = local_median;
+            }
+        }
+    }
+}
+
+fn levels(data: &mut [f32], value_range: (f32, f32), low: f32, high: f32, curve: Curve) {
+    let (min, max) = value_range;
+    let range = if max > min { max - min } else { 1.0 };
+    let span = if high > low { high - low } else { f32::EPSILON };
+
+    for e in data.iter_mut() {
+        let n = (((*e - min) / range) - low) / span;
+        *e = min + curve.eval(n.clamp(0.0, 1.0)) * range;
+    }
+}
+
+fn terrace(data: &mut [f32], width: usize, height: usize, radius: usize) {
+    // The quantization step is the smallest difference between levels
+    let mut distinct = data.to_vec();
+    distinct.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    distinct.dedup();
+    let step = distinct
+        .windows(2)
+        .map(|w| w[1] - w[0])
+        .fold(f32::MAX, f32::min);
+    if step == f32::MAX {
+        return;
+    }
+
+    let src = data.to_vec();
+    gaussian_blur(data, width, height, radius as f32);
+
+    let half_step = step / 2.0;
+    for (e, s) in data.iter_mut().zip(src.iter()) {
+        *e = e.max(s - half_step).min(s + half_step);
+    }
+}
+
+fn median(values: &mut [f32]) -> f32 {
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    values[values.len() / 2]
+}
+
+/// Parse a filter from `name:param:...`
+///
+/// - `blur:SIGMA`
+/// - `despike:RADIUS[:K]`, K defaults to 3
+/// - `levels:LOW:HIGH[:CURVE]`, CURVE is linear, smooth or a gamma value
+/// - `terrace:RADIUS`
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || format!("Invalid filter '{}'", s);
+        let parts: Vec<&str> = s.split(':').collect();
+        let num = |i: usize| -> Result<f32, String> {
+            parts
+                .get(i)
+                .ok_or_else(err)?
+                .parse::<f32>()
+                .map_err(|_| err())
+        };
+
+        match parts[0].to_lowercase().as_str() {
+            "blur" => Ok(Filter::GaussianBlur { sigma: num(1)? }),
+            "despike" => Ok(Filter::Despike {
+                radius: num(1)? as usize,
+                k: if parts.len() > 2 { num(2)? } else { 3.0 },
+            }),
+            "levels" => Ok(Filter::Levels {
+                low: num(1)?,
+                high: num(2)?,
+                curve: if let Some(c) = parts.get(3) {
+                    c.parse()?
+                } else {
+                    Curve::Linear
+                },
+            }),
+            "terrace" => Ok(Filter::Terrace {
+                radius: num(1)? as usize,
+            }),
+            _ => Err(format!("{}, use blur, despike, levels or terrace", err())),
+        }
+    }
+}
+
+impl FromStr for Curve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "linear" => Ok(Curve::Linear),
+            "smooth" => Ok(Curve::Smooth),
+            _ => s
+                .parse::<f32>()
+                .ok()
+                .filter(|g| *g > 0.0)
+                .map(Curve::Gamma)
+                .ok_or_else(|| format!("Invalid curve '{}', use linear, smooth or a gamma", s)),
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Filter::GaussianBlur { sigma } => write!(f, "blur:{}", sigma),
+            Filter::Despike { radius, k } => write!(f, "despike:{}:{}", radius, k),
+            Filter::Levels { low, high, curve } => match curve {
+                Curve::Linear => write!(f, "levels:{}:{}:linear", low, high),
+                Curve::Smooth => write!(f, "levels:{}:{}:smooth", low, high),
+                Curve::Gamma(g) => write!(f, "levels:{}:{}:{}", low, high, g),
+            },
+            Filter::Terrace { radius } => write!(f, "terrace:{}", radius),
+        }
+    }
+}
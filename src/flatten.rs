@@ -0,0 +1,286 @@
+use crate::interpolation::Interpolation;
+use std::f64;
+use std::str::FromStr;
+
+/// Area to flatten, in pixel coordinates with pixel centers at whole numbers
+#[derive(Clone, Debug, PartialEq)]
+pub enum Region {
+    /// Two opposite corners
+    Rect((f64, f64), (f64, f64)),
+    Polygon(Vec<(f64, f64)>),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FlattenParams {
+    /// Elevation, in source units, the region is flattened to, defaults to
+    /// the mean elevation within the region
+    pub target: Option<f32>,
+    /// Width, in pixels, of the blend from the flat region to the terrain
+    pub margin: f32,
+}
+
+/// Pixel bounds (min_x, min_y, max_x, max_y) touched by a flatten, inclusive
+pub type Bounds = (usize, usize, usize, usize);
+
+impl Region {
+    /// Map the coordinates of the region, i.e. from world to pixel coordinates
+    pub fn map<F: Fn(f64, f64) -> (f64, f64)>(&self, f: F) -> Self {
+        match self {
+            Region::Rect(a, b) => Region::Rect(f(a.0, a.1), f(b.0, b.1)),
+            Region::Polygon(points) => {
+                Region::Polygon(points.iter().map(|p| f(p.0, p.1)).collect())
+            }
+        }
+    }
+
+    fn vertices(&self) -> Vec<(f64, f64)> {
+        match self {
+            Region::Rect(a, b) => vec![(a.0, a.1), (b.0, a.1), (b.0, b.1), (a.0, b.1)],
+            Region::Polygon(points) => points.clone(),
+        }
+    }
+}
+
+/// Flatten a region of a width x height grid of elevations, returns the
+/// bounds of the modified cells, None if the region is outside the grid.
+/// Without a target, regions too thin to contain a cell center are
+/// flattened to the mean elevation at their vertices
+pub fn flatten(
+    data: &mut [f32],
+    width: usize,
+    height: usize,
+    region: &Region,
+    params: &FlattenParams,
+) -> Option<Bounds> {
+    let polygon = region.vertices();
+    if polygon.len() < 3 {
+        return None;
+    }
+
+    let margin = f64::from(params.margin.max(0.0));
+    let (min_x, min_y, max_x, max_y) = polygon.iter().fold(
+        (f64::MAX, f64::MAX, f64::MIN, f64::MIN),
+        |(x0, y0, x1, y1), p| (x0.min(p.0), y0.min(p.1), x1.max(p.0), y1.max(p.1)),
+    );
+
+    let clamp = |v: f64, max: usize| v.max(0.0).min(max as f64 - 1.0) as usize;
+    if max_x + margin < 0.0
+        || max_y + margin < 0.0
+        || min_x - margin > (width - 1) as f64
+        || min_y - margin > (height - 1) as f64
+    {
+        return None;
+    }
+    let bounds = (
+        clamp((min_x - margin).floor(), width),
+        clamp((min_y - margin).floor(), height),
+        clamp((max_x + margin).ceil(), width),
+        clamp((max_y + margin).ceil(), height),
+    );
+
+    // Blend weight of every cell within the bounds, 1 inside the region
+    let bw = bounds.2 - bounds.0 + 1;
+    let mut weights = Vec::with_capacity(bw * (bounds.3 - bounds.1 + 1));
+    let mut sum = 0.0;
+    let mut count = 0;
+    for y in bounds.1..=bounds.3 {
+        for x in bounds.0..=bounds.2 {
+            let p = (x as f64, y as f64);
+            let w = if contains(&polygon, p) {
+                sum += f64::from(data[x + y * width]);
+                count += 1;
+                1.0
+            } else if margin > 0.0 {
+                let t = (1.0 - distance(&polygon, p) / margin).max(0.0);
+                t * t * (3.0 - 2.0 * t)
+            } else {
+                0.0
+            };
+            weights.push(w as f32);
+        }
+    }
+
+    let target = match params.target {
+        Some(t) => t,
+        None if count > 0 => (sum / f64::from(count)) as f32,
+        // Region too thin to cover a cell center, measure along its outline
+        None => {
+            let sum: f32 = polygon
+                .iter()
+                .map(|p| Interpolation::Bilinear.sample(data, width, height, p.0 as _, p.1 as _))
+                .sum();
+            sum / polygon.len() as f32
+        }
+    };
+
+    for y in bounds.1..=bounds.3 {
+        for x in bounds.0..=bounds.2 {
+            let w = weights[(x - bounds.0) + (y - bounds.1) * bw];
+            let e = &mut data[x + y * width];
+            *e += (target - *e) * w;
+        }
+    }
+
+    Some(bounds)
+}
+
+/// Even-odd point in polygon test
+fn contains(polygon: &[(f64, f64)], p: (f64, f64)) -> bool {
+    let mut inside = false;
+    let mut j = polygon.len() - 1;
+    for i in 0..polygon.len() {
+        let (a, b) = (polygon[i], polygon[j]);
+        if (a.1 > p.1) != (b.1 > p.1) && p.0 < (b.0 - a.0) * (p.1 - a.1) / (b.1 - a.1) + a.0 {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+/// Distance from a point to the closest polygon edge
+fn distance(polygon: &[(f64, f64)], p: (f64, f64)) -> f64 {
+    let mut min = f64::MAX;
+    let mut j = polygon.len() - 1;
+    for i in 0..polygon.len() {
+        let (a, b) = (polygon[j], polygon[i]);
+        let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+        let len_sq = dx * dx + dy * dy;
+        let t = if len_sq > 0.0 {
+            (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / len_sq).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        let (cx, cy) = (a.0 + t * dx - p.0, a.1 + t * dy - p.1);
+        min = min.min((cx * cx + cy * cy).sqrt());
+        j = i;
+    }
+    min
+}
+
+/// Parse a region from `X0,Y0,X1,Y1` (rectangle) or `X,Y;X,Y;X,Y...`
+/// (polygon)
+impl FromStr for Region {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || {
+            format!(
+                "Invalid region '{}', use X0,Y0,X1,Y1 or X,Y;X,Y;X,Y for a polygon",
+                s
+            )
+        };
+        let nums = |p: &str| -> Result<Vec<f64>, String> {
+            p.split(',')
+                .map(|n| n.trim().parse::<f64>().map_err(|_| err()))
+                .collect()
+        };
+
+        if s.contains(';') {
+            let points = s
+                .split(';')
+                .filter(|p| !p.trim().is_empty())
+                .map(|p| match nums(p)?.as_slice() {
+                    [x, y] => Ok((*x, *y)),
+                    _ => Err(err()),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            if points.len() < 3 {
+                return Err(err());
+            }
+            Ok(Region::Polygon(points))
+        } else {
+            match nums(s)?.as_slice() {
+                [x0, y0, x1, y1] => Ok(Region::Rect((*x0, *y0), (*x1, *y1))),
+                _ => Err(err()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 8x8 ramp rising by one per column
+    fn ramp() -> Vec<f32> {
+        (0..64).map(|i| (i % 8) as f32).collect()
+    }
+
+    #[test]
+    fn flattens_to_the_target() {
+        let mut data = ramp();
+        let params = FlattenParams {
+            target: Some(10.0),
+            margin: 0.0,
+        };
+        let bounds = flatten(
+            &mut data,
+            8,
+            8,
+            &Region::Rect((1.5, 1.5), (4.5, 4.5)),
+            &params,
+        );
+
+        assert_eq!(bounds, Some((1, 1, 5, 5)));
+        for y in 0..8 {
+            for x in 0..8 {
+                let inside = (2..=4).contains(&x) && (2..=4).contains(&y);
+                let expected = if inside { 10.0 } else { x as f32 };
+                assert_eq!(data[x + y * 8], expected, "cell ({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn measures_the_mean_elevation() {
+        let mut data = ramp();
+        let params = FlattenParams {
+            target: None,
+            margin: 0.0,
+        };
+        flatten(
+            &mut data,
+            8,
+            8,
+            &Region::Rect((1.5, 1.5), (4.5, 4.5)),
+            &params,
+        );
+
+        assert_eq!(data[2 + 3 * 8], 3.0);
+        assert_eq!(data[4 + 3 * 8], 3.0);
+    }
+
+    #[test]
+    fn measures_thin_regions_at_their_vertices() {
+        let mut data = ramp();
+        let params = FlattenParams {
+            target: None,
+            margin: 2.0,
+        };
+        // Strip between pixel rows, no cell center is inside
+        let region = Region::Rect((2.0, 3.2), (6.0, 3.4));
+        let bounds = flatten(&mut data, 8, 8, &region, &params);
+
+        assert_eq!(bounds, Some((0, 1, 7, 6)));
+        // Mean of the vertices at columns 2 and 6, blended in from 0.2 pixels
+        let t = 0.9;
+        let w = t * t * (3.0 - 2.0 * t);
+        for x in 2..=6 {
+            let expected = x as f32 + (4.0 - x as f32) * w;
+            assert!((data[x + 3 * 8] - expected).abs() < 1e-5, "column {}", x);
+        }
+    }
+
+    #[test]
+    fn rejects_regions_outside_the_grid() {
+        let mut data = ramp();
+        let params = FlattenParams {
+            target: Some(0.0),
+            margin: 1.0,
+        };
+        let region = Region::Rect((20.0, 20.0), (30.0, 30.0));
+        assert_eq!(flatten(&mut data, 8, 8, &region, &params), None);
+        assert_eq!(data, ramp());
+    }
+}
@@ -0,0 +1,301 @@
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg64;
+use std::f32;
+use std::str::FromStr;
+
+/// Procedural terrain algorithms
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Algorithm {
+    /// Fractal (fBm) Perlin gradient noise
+    Perlin,
+    /// Fractal (fBm) simplex noise
+    Simplex,
+    /// Ridged multifractal, sharp mountain ridges
+    Ridged,
+    /// Midpoint displacement, octaves and scale are not used
+    DiamondSquare,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GenerateParams {
+    pub algorithm: Algorithm,
+    pub width: usize,
+    pub height: usize,
+    pub seed: u64,
+    /// Number of noise layers added together
+    pub octaves: usize,
+    /// Size of the largest features, in pixels
+    pub scale: f32,
+    /// Amplitude multiplier between octaves, the roughness
+    pub persistence: f32,
+    /// Frequency multiplier between octaves
+    pub lacunarity: f32,
+    /// Elevation range used, normalized to the full heightmap range
+    pub amplitude: f32,
+}
+
+/// Parse a feature scale in pixels, it divides the pixel coordinates so it
+/// has to be positive
+pub fn parse_scale(s: &str) -> Result<f32, String> {
+    match s.parse::<f32>() {
+        Ok(scale) if scale > 0.0 && scale.is_finite() => Ok(scale),
+        _ => Err(format!("Invalid scale '{}', use a positive number", s)),
+    }
+}
+
+/// Generate a width x height grid of elevations in [0, amplitude],
+/// deterministic for a given seed
+pub fn generate(params: &GenerateParams) -> Vec<f32> {
+    let mut rng = Pcg64::seed_from_u64(params.seed);
+
+    let mut data = match params.algorithm {
+        Algorithm::DiamondSquare => diamond_square(params, &mut rng),
+        _ => {
+            let noise = Noise::new(&mut rng);
+            let mut data = Vec::with_capacity(params.width * params.height);
+            for y in 0..params.height {
+                for x in 0..params.width {
+                    let (fx, fy) = (x as f32 / params.scale, y as f32 / params.scale);
+                    data.push(match params.algorithm {
+                        Algorithm::Perlin => fbm(params, fx, fy, |x, y| noise.perlin(x, y)),
+                        Algorithm::Simplex => fbm(params, fx, fy, |x, y| noise.simplex(x, y)),
+                        _ => ridged(params, fx, fy, &noise),
+                    });
+                }
+            }
+            data
+        }
+    };
+
+    // Stretch to the requested range
+    let (min, max) = data.iter().fold((f32::MAX, f32::MIN), |(min, max), e| {
+        (min.min(*e), max.max(*e))
+    });
+    let range = if max > min { max - min } else { 1.0 };
+    let amplitude = params.amplitude.clamp(0.0, 1.0);
+    for e in data.iter_mut() {
+        *e = (*e - min) / range * amplitude;
+    }
+
+    data
+}
+
+/// Fractal brownian motion, sum of octaves of a noise function
+fn fbm<F: Fn(f32, f32) -> f32>(params: &GenerateParams, x: f32, y: f32, noise: F) -> f32 {
+    let mut sum = 0.0;
+    let mut amplitude = 1.0;
+    let mut frequency = 1.0;
+    for _ in 0..params.octaves.max(1) {
+        sum += noise(x * frequency, y * frequency) * amplitude;
+        amplitude *= params.persistence;
+        frequency *= params.lacunarity;
+    }
+    sum
+}
+
+/// Musgrave's ridged multifractal, each octave is weighted by the previous
+/// one so detail accumulates on the ridges
+fn ridged(params: &GenerateParams, x: f32, y: f32, noise: &Noise) -> f32 {
+    let offset = 1.0;
+    let gain = 2.0;
+    let mut sum = 0.0;
+    let mut weight = 1.0;
+    let mut amplitude = 1.0;
+    let mut frequency = 1.0;
+    for _ in 0..params.octaves.max(1) {
+        let mut signal = offset - noise.perlin(x * frequency, y * frequency).abs();
+        signal = signal * signal * weight;
+        weight = (signal * gain).clamp(0.0, 1.0);
+        sum += signal * amplitude;
+        amplitude *= params.persistence;
+        frequency *= params.lacunarity;
+    }
+    sum
+}
+
+/// Diamond-square on the smallest 2^n + 1 grid covering the requested size,
+/// cropped to it
+fn diamond_square(params: &GenerateParams, rng: &mut Pcg64) -> Vec<f32> {
+    let mut size = 2;
+    while size + 1 < params.width.max(params.height) {
+        size *= 2;
+    }
+    let n = size + 1;
+    let mut grid = vec![0.0; n * n];
+
+    for &(x, y) in &[(0, 0), (size, 0), (0, size), (size, size)] {
+        grid[x + y * n] = rng.gen_range(-1.0, 1.0);
+    }
+
+    let mut step = size;
+    let mut displacement = 1.0;
+    while step > 1 {
+        let half = step / 2;
+
+        // Diamond, centers of the squares
+        for y in (half..n).step_by(step) {
+            for x in (half..n).step_by(step) {
+                let avg = (grid[(x - half) + (y - half) * n]
+                    + grid[(x + half) + (y - half) * n]
+                    + grid[(x - half) + (y + half) * n]
+                    + grid[(x + half) + (y + half) * n])
+                    / 4.0;
+                grid[x + y * n] = avg + rng.gen_range(-displacement, displacement);
+            }
+        }
+
+        // Square, edge midpoints, with 3 neighbors along the border
+        for y in (0..n).step_by(half) {
+            let start = if (y / half) % 2 == 0 { half } else { 0 };
+            for x in (start..n).step_by(step) {
+                let mut sum = 0.0;
+                let mut count = 0.0;
+                if x >= half {
+                    sum += grid[(x - half) + y * n];
+                    count += 1.0;
+                }
+                if x + half < n {
+                    sum += grid[(x + half) + y * n];
+                    count += 1.0;
+                }
+                if y >= half {
+                    sum += grid[x + (y - half) * n];
+                    count += 1.0;
+                }
+                if y + half < n {
+                    sum += grid[x + (y + half) * n];
+                    count += 1.0;
+                }
+                grid[x + y * n] = sum / count + rng.gen_range(-displacement, displacement);
+            }
+        }
+
+        step = half;
+        displacement *= params.persistence;
+    }
+
+    let mut data = Vec::with_capacity(params.width * params.height);
+    for y in 0..params.height {
+        data.extend_from_slice(&grid[y * n..y * n + params.width]);
+    }
+    data
+}
+
+/// Gradient noise permutation table, shared by the Perlin and simplex noise
+struct Noise {
+    perm: [u8; 512],
+}
+
+/// Gradient directions, rotated off the axes, axis aligned gradients on
+/// neighboring corners zero out whole lattice edges which shows up as
+/// straight creases in ridged noise
+const GRADIENTS: [(f32, f32); 8] = [
+    (0.923_88, 0.382_683),
+    (0.382_683, 0.923_88),
+    (-0.382_683, 0.923_88),
+    (-0.923_88, 0.382_683),
+    (-0.923_88, -0.382_683),
+    (-0.382_683, -0.923_88),
+    (0.382_683, -0.923_88),
+    (0.923_88, -0.382_683),
+];
+
+impl Noise {
+    fn new(rng: &mut Pcg64) -> Self {
+        let mut p: Vec<u8> = (0..=255).collect();
+        p.shuffle(rng);
+        let mut perm = [0; 512];
+        for (i, v) in perm.iter_mut().enumerate() {
+            *v = p[i & 255];
+        }
+        Self { perm }
+    }
+
+    fn gradient(&self, x: i32, y: i32, dx: f32, dy: f32) -> f32 {
+        let hash = self.perm[(x & 255) as usize + self.perm[(y & 255) as usize] as usize];
+        let (gx, gy) = GRADIENTS[(hash & 7) as usize];
+        gx * dx + gy * dy
+    }
+
+    /// Perlin noise, roughly in [-1, 1]
+    fn perlin(&self, x: f32, y: f32) -> f32 {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (dx, dy) = (x - x0, y - y0);
+        let (ix, iy) = (x0 as i32, y0 as i32);
+
+        let fade = |t: f32| t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        let (u, v) = (fade(dx), fade(dy));
+
+        let n00 = self.gradient(ix, iy, dx, dy);
+        let n10 = self.gradient(ix + 1, iy, dx - 1.0, dy);
+        let n01 = self.gradient(ix, iy + 1, dx, dy - 1.0);
+        let n11 = self.gradient(ix + 1, iy + 1, dx - 1.0, dy - 1.0);
+
+        lerp(lerp(n00, n10, u), lerp(n01, n11, u), v) * f32::consts::SQRT_2
+    }
+
+    /// 2D simplex noise, roughly in [-1, 1]
+    fn simplex(&self, x: f32, y: f32) -> f32 {
+        let f2 = 0.5 * (3.0f32.sqrt() - 1.0);
+        let g2 = (3.0 - 3.0f32.sqrt()) / 6.0;
+
+        // Skew to the simplex grid to find the cell
+        let s = (x + y) * f2;
+        let (i, j) = ((x + s).floor(), (y + s).floor());
+        let t = (i + j) * g2;
+        let (x0, y0) = (x - (i - t), y - (j - t));
+
+        // Upper or lower triangle of the cell
+        let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+        let (x1, y1) = (x0 - i1 as f32 + g2, y0 - j1 as f32 + g2);
+        let (x2, y2) = (x0 - 1.0 + 2.0 * g2, y0 - 1.0 + 2.0 * g2);
+
+        let (i, j) = (i as i32, j as i32);
+        let corner = |gi: i32, gj: i32, dx: f32, dy: f32| {
+            let t = 0.5 - dx * dx - dy * dy;
+            if t < 0.0 {
+                0.0
+            } else {
+                t * t * t * t * self.gradient(gi, gj, dx, dy)
+            }
+        };
+
+        70.0 * (corner(i, j, x0, y0)
+            + corner(i + i1, j + j1, x1, y1)
+            + corner(i + 1, j + 1, x2, y2))
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "perlin" => Ok(Algorithm::Perlin),
+            "simplex" => Ok(Algorithm::Simplex),
+            "ridged" => Ok(Algorithm::Ridged),
+            "diamond-square" => Ok(Algorithm::DiamondSquare),
+            _ => Err(format!(
+                "Invalid algorithm '{}', use perlin, simplex, ridged or diamond-square",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_scale_rejects_non_positive() {
+        assert_eq!(parse_scale("256"), Ok(256.0));
+        assert_eq!(parse_scale("0.5"), Ok(0.5));
+        assert!(parse_scale("0").is_err());
+        assert!(parse_scale("-64").is_err());
+        assert!(parse_scale("inf").is_err());
+        assert!(parse_scale("large").is_err());
+    }
+}
@@ -0,0 +1,90 @@
+use std::f64;
+
+/// Mean radius of the earth, used to approximate geographic pixel sizes
+const EARTH_RADIUS_METERS: f64 = 6_371_008.8;
+
+/// Affine mapping from raster pixel coordinates to real-world coordinates
+///
+/// Follows the GDAL convention, rotation/shear terms are not supported.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct GeoTransform {
+    /// World X coordinate of the top-left corner of the top-left pixel
+    pub origin_x: f64,
+    /// World Y coordinate of the top-left corner of the top-left pixel
+    pub origin_y: f64,
+    /// Pixel width in world units
+    pub pixel_width: f64,
+    /// Pixel height in world units, negative for north-up rasters
+    pub pixel_height: f64,
+    /// World units are degrees of longitude/latitude rather than meters
+    pub geographic: bool,
+}
+
+impl Default for GeoTransform {
+    /// One world unit per pixel, no georeferencing
+    fn default() -> Self {
+        Self {
+            origin_x: 0.0,
+            origin_y: 0.0,
+            pixel_width: 1.0,
+            pixel_height: -1.0,
+            geographic: false,
+        }
+    }
+}
+
+impl GeoTransform {
+    /// World coordinates of the center of pixel (x, y)
+    pub fn pixel_to_world(&self, x: f64, y: f64) -> (f64, f64) {
+        (
+            self.origin_x + (x + 0.5) * self.pixel_width,
+            self.origin_y + (y + 0.5) * self.pixel_height,
+        )
+    }
+
+    /// Pixel coordinates of a world position, pixel centers are at whole
+    /// numbers
+    pub fn world_to_pixel(&self, x: f64, y: f64) -> (f64, f64) {
+        (
+            (x - self.origin_x) / self.pixel_width - 0.5,
+            (y - self.origin_y) / self.pixel_height - 0.5,
+        )
+    }
+
+    /// Pixel (width, height) in meters, measured at the center of a raster
+    /// `height` pixels tall
+    pub fn pixel_size_meters(&self, height: usize) -> (f64, f64) {
+        if self.geographic {
+            let (_, lat) = self.pixel_to_world(0.0, height as f64 / 2.0);
+            let meters_per_degree = EARTH_RADIUS_METERS * f64::consts::PI / 180.0;
+            (
+                self.pixel_width.abs() * meters_per_degree * lat.to_radians().cos(),
+                self.pixel_height.abs() * meters_per_degree,
+            )
+        } else {
+            (self.pixel_width.abs(), self.pixel_height.abs())
+        }
+    }
+}
+
+/// Parse a position, "X,Y"
+pub fn parse_position(s: &str) -> Result<(f64, f64), String> {
+    let err = || format!("Invalid position '{}', use X,Y", s);
+    let mut parts = s.split(',');
+    let x = parts
+        .next()
+        .ok_or_else(err)?
+        .trim()
+        .parse()
+        .map_err(|_| err())?;
+    let y = parts
+        .next()
+        .ok_or_else(err)?
+        .trim()
+        .parse()
+        .map_err(|_| err())?;
+    if parts.next().is_some() {
+        return Err(err());
+    }
+    Ok((x, y))
+}
@@ -0,0 +1,121 @@
+use crate::geo_transform::GeoTransform;
+use crate::heightmap::Error;
+use crate::raster::Raster;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use tiff::decoder::{Decoder, DecodingResult, Limits};
+use tiff::tags::Tag;
+use tiff::ColorType;
+
+// GeoKey IDs and values, see the GeoTIFF 1.1 spec
+const GT_MODEL_TYPE_GEO_KEY: u16 = 1024;
+const GT_RASTER_TYPE_GEO_KEY: u16 = 1025;
+const MODEL_TYPE_GEOGRAPHIC: u16 = 2;
+const RASTER_PIXEL_IS_POINT: u16 = 2;
+
+/// Read the first band of a single channel GeoTIFF (int/float) DEM
+pub fn read_file(file_path: &Path) -> Result<Raster, Error> {
+    let reader = BufReader::new(File::open(file_path)?);
+    let mut decoder = Decoder::new(reader)?.with_limits(Limits::unlimited());
+
+    let bit_depth = match decoder.colortype()? {
+        ColorType::Gray(bits) => bits,
+        _ => return Err(Error::FileNotSupported),
+    };
+
+    let (width, height) = decoder.dimensions()?;
+    let geo_transform = read_geo_transform(&mut decoder)?;
+    let nodata = decoder
+        .find_tag(Tag::GdalNodata)?
+        .map(|v| v.into_string())
+        .transpose()?
+        .and_then(|s| s.trim_matches(char::from(0)).trim().parse::<f32>().ok());
+
+    let data: Vec<f32> = match decoder.read_image()? {
+        DecodingResult::U8(v) => v.into_iter().map(f32::from).collect(),
+        DecodingResult::U16(v) => v.into_iter().map(f32::from).collect(),
+        DecodingResult::U32(v) => v.into_iter().map(|e| e as f32).collect(),
+        DecodingResult::U64(v) => v.into_iter().map(|e| e as f32).collect(),
+        DecodingResult::I8(v) => v.into_iter().map(f32::from).collect(),
+        DecodingResult::I16(v) => v.into_iter().map(f32::from).collect(),
+        DecodingResult::I32(v) => v.into_iter().map(|e| e as f32).collect(),
+        DecodingResult::I64(v) => v.into_iter().map(|e| e as f32).collect(),
+        DecodingResult::F32(v) => v,
+        DecodingResult::F64(v) => v.into_iter().map(|e| e as f32).collect(),
+    };
+
+    if data.len() != (width * height) as usize {
+        return Err(Error::InvalidFile);
+    }
+
+    Ok(Raster {
+        width: width as _,
+        height: height as _,
+        data,
+        geo_transform,
+        nodata,
+        bit_depth: Some(bit_depth),
+    })
+}
+
+fn read_geo_transform<R: std::io::Read + std::io::Seek>(
+    decoder: &mut Decoder<R>,
+) -> Result<GeoTransform, Error> {
+    let mut gt = GeoTransform::default();
+
+    let geo_keys = decoder
+        .find_tag(Tag::GeoKeyDirectoryTag)?
+        .map(|v| v.into_u16_vec())
+        .transpose()?
+        .unwrap_or_default();
+    // Header is 4 shorts, then 4 shorts per key: id, location, count, value
+    // Only keys stored inline (location == 0) are needed here
+    let geo_key = |id: u16| {
+        geo_keys
+            .chunks(4)
+            .skip(1)
+            .find(|k| k.len() == 4 && k[0] == id && k[1] == 0)
+            .map(|k| k[3])
+    };
+    gt.geographic = geo_key(GT_MODEL_TYPE_GEO_KEY) == Some(MODEL_TYPE_GEOGRAPHIC);
+
+    let pixel_scale = decoder
+        .find_tag(Tag::ModelPixelScaleTag)?
+        .map(|v| v.into_f64_vec())
+        .transpose()?;
+    let tie_point = decoder
+        .find_tag(Tag::ModelTiepointTag)?
+        .map(|v| v.into_f64_vec())
+        .transpose()?;
+    let transformation = decoder
+        .find_tag(Tag::ModelTransformationTag)?
+        .map(|v| v.into_f64_vec())
+        .transpose()?;
+
+    if let (Some(scale), Some(tp)) = (pixel_scale, tie_point) {
+        if scale.len() < 2 || tp.len() < 6 {
+            return Err(Error::InvalidFile);
+        }
+        gt.pixel_width = scale[0];
+        gt.pixel_height = -scale[1];
+        gt.origin_x = tp[3] - tp[0] * gt.pixel_width;
+        gt.origin_y = tp[4] - tp[1] * gt.pixel_height;
+    } else if let Some(m) = transformation {
+        if m.len() < 8 {
+            return Err(Error::InvalidFile);
+        }
+        gt.pixel_width = m[0];
+        gt.pixel_height = m[5];
+        gt.origin_x = m[3];
+        gt.origin_y = m[7];
+    }
+
+    // Tie points refer to the pixel center rather than the corner
+    if geo_key(GT_RASTER_TYPE_GEO_KEY) == Some(RASTER_PIXEL_IS_POINT) {
+        gt.origin_x -= gt.pixel_width / 2.0;
+        gt.origin_y -= gt.pixel_height / 2.0;
+    }
+
+    Ok(gt)
+}
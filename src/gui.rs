@@ -1,30 +1,72 @@
 // TODO - break this down into smaller bits
 
 use crate::alphamap::Alphamap;
+use crate::alphamap_rules::{Attributes, Rules};
+use crate::camera_mode::CameraMode;
+use crate::camera_rig::{CameraRig, CAMERA_FOV};
+use crate::contour::{self, Contour};
+use crate::filter::{Curve, Filter};
+use crate::flatten::{FlattenParams, Region};
 use crate::ground_plane::GroundPlane;
-use crate::heightmap::Heightmap;
+use crate::heightmap::{Heightmap, TILE_SIZE};
+use crate::interpolation::Interpolation;
+use crate::landing::{LandingParams, LandingSpot};
 use crate::origin_model::OriginModel;
 use crate::ortho_view::OrthoView;
-use kiss3d::camera::{Camera, FirstPerson};
-use kiss3d::event::{Action, Key, WindowEvent};
+use crate::profile::{self, Measurement, ProfileSample};
+use crate::profile_view::ProfileView;
+use crate::terrain_mode::TerrainMode;
+use crate::textures;
+use crate::viewshed::ViewshedParams;
+use image::DynamicImage;
+use kiss3d::camera::Camera;
+use kiss3d::event::{Action, Key, Modifiers, MouseButton, WindowEvent};
 use kiss3d::light::Light;
 use kiss3d::resource::{MeshManager, TextureManager};
 use kiss3d::text::Font;
 use kiss3d::window::Window;
-use nalgebra::{Point2, Point3};
+use nalgebra::{Point2, Point3, Vector2};
+use std::path::PathBuf;
 
 pub struct Gui {
     hmap: Heightmap,
     amap: Alphamap,
     win: Window,
-    cam: FirstPerson,
+    cam: CameraRig,
     ortho_view: OrthoView,
+    /// Tile outlines and names shown in the ortho view
+    show_tile_grid: bool,
     origin_model: OriginModel,
     ground_plane: GroundPlane,
+    last_filter: Option<Filter>,
+    /// Outline of the region to flatten, placed at the origin model or
+    /// added with shift+click
+    flatten_points: Vec<Point3<f32>>,
+    /// Rules file the alphamap is generated from
+    alphamap_rules: Option<PathBuf>,
+    contour_interval: f32,
+    /// Contour lines shown as an overlay, empty when hidden
+    contours: Vec<Contour>,
+    /// Contours in scene coordinates, placed at the height scale and offset
+    /// they were built for, None once stale
+    contour_lines: Vec<Vec<Point3<f32>>>,
+    contour_lines_scale: Option<(f32, f32)>,
+    show_contours: bool,
+    /// Interpolation of the terrain readout under the origin model
+    interpolation: Interpolation,
+    cursor_pos: Point2<f32>,
+    /// Points on the terrain to measure between, added with ctrl+click
+    measure_points: Vec<Point3<f32>>,
+    profile: Vec<ProfileSample>,
+    profile_view: ProfileView,
+    /// CSV file the profile is exported to
+    profile_file: PathBuf,
+    landing_params: LandingParams,
+    landing_spots: Vec<LandingSpot>,
 }
 
 impl Gui {
-    pub fn new(mut hmap: Heightmap, amap: Alphamap) -> Self {
+    pub fn new(mut hmap: Heightmap, amap: Alphamap, texture: Option<DynamicImage>) -> Self {
         let mut win = Window::new("Heli-X Scene3D Tool");
 
         // TODO - which lighting is better?
@@ -36,61 +78,100 @@ impl Gui {
             tm.add_image(hmap.src_texture(), "heightmap");
             tm.add_image(amap.src_texture(), "alphamap_src");
         });
+        // Textures are opened as is, gray or gray+alpha images are converted
+        if let Some(img) = texture {
+            textures::set_texture_image(img, "texture");
+        }
 
         // Load all of the GPU mesh tiles that make up the heightmap terrain
         MeshManager::get_global_manager(|mm| hmap.generate_mesh_tiles(mm));
         hmap.create_mesh_tile_scene_nodes(&mut win);
 
-        // TODO - toggle/size/location/resize-event/etc
         let mut ortho_view = OrthoView::new(
             &mut win,
             Point2::new(300.0, 300.0),
             Point2::new(hmap.dimensions().0 as _, hmap.dimensions().1 as _),
         );
         ortho_view.set_visible(false);
+        let profile_view = ProfileView::new(&win, Point2::new(600.0, 150.0));
         let origin_model = OriginModel::new(&mut win);
+        let contour_interval = contour::auto_interval(hmap.value_range());
+        let landing_params = LandingParams::new(hmap.value_range());
         let ground_plane = GroundPlane::new(800, 10);
 
         let mut gui = Self {
             hmap,
             amap,
             win,
-            cam: FirstPerson::new(Point3::new(1.0, 1.0, 1.0), Point3::origin()),
+            cam: CameraRig::new(Point3::new(1.0, 1.0, 1.0), Point3::origin()),
             ortho_view,
+            show_tile_grid: true,
             origin_model,
             ground_plane,
+            last_filter: None,
+            flatten_points: Vec::new(),
+            alphamap_rules: None,
+            contour_interval,
+            contours: Vec::new(),
+            contour_lines: Vec::new(),
+            contour_lines_scale: None,
+            show_contours: false,
+            interpolation: Interpolation::Bilinear,
+            cursor_pos: Point2::origin(),
+            measure_points: Vec::new(),
+            profile: Vec::new(),
+            profile_view,
+            profile_file: PathBuf::from("profile.csv"),
+            landing_params,
+            landing_spots: Vec::new(),
         };
 
         gui.reset_camera();
 
+        // Sync the ortho view with the initial terrain mode
+        let mode = gui.hmap.terrain_mode();
+        gui.set_terrain_mode(mode);
+
         gui
     }
 
     pub fn render(&mut self) -> bool {
-        let mut some_events = false;
         let keep_rendering = self.win.render_with_camera(&mut self.cam);
 
         if keep_rendering {
+            let mut ortho_cursor = self.ortho_view.cursor_to_planar(&self.cursor_pos);
+
             // TODO - break apart event handling
             for mut event in self.win.events().iter() {
-                some_events = true;
                 match event.value {
                     WindowEvent::Key(button, Action::Press, _) => {
                         // TODO - this keymap makes no sense
                         if button == Key::Return {
                             self.reset_camera();
+                        } else if button == Key::Tab {
+                            self.set_camera_mode(self.cam.mode().next());
+                        } else if button == Key::Key5 {
+                            self.adjust_camera_speed(1.0 / 1.5);
+                        } else if button == Key::Key6 {
+                            self.adjust_camera_speed(1.5);
                         } else if button == Key::T {
-                            self.hmap.set_terrain_mode(self.hmap.terrain_mode().next());
+                            self.set_terrain_mode(self.hmap.terrain_mode().next());
                         } else if button == Key::I {
                             self.hmap.set_height_scale(self.hmap.height_scale() + 1.0);
+                            self.fit_camera_depth();
                         } else if button == Key::K {
                             self.hmap.set_height_scale(self.hmap.height_scale() - 1.0);
+                            self.fit_camera_depth();
                         } else if button == Key::O {
                             self.hmap.set_height_offset(self.hmap.height_offset() + 1.0);
+                            self.fit_camera_depth();
                         } else if button == Key::L {
                             self.hmap.set_height_offset(self.hmap.height_offset() - 1.0);
+                            self.fit_camera_depth();
                         } else if button == Key::Y {
                             self.ortho_view.set_visible(!self.ortho_view.is_visible());
+                        } else if button == Key::Q {
+                            self.show_tile_grid = !self.show_tile_grid;
                         } else if button == Key::N {
                             self.origin_model
                                 .set_static_height(self.origin_model.position().y + 1.0);
@@ -106,49 +187,665 @@ impl Gui {
                         } else if button == Key::R {
                             println!("Reloading map files");
                             MeshManager::get_global_manager(|mm| self.hmap.reload(mm));
+                            self.last_filter = None;
+                            self.update_contours();
+                            self.update_profile();
+                        } else if button == Key::Key1 {
+                            self.apply_filter(Filter::GaussianBlur { sigma: 1.0 });
+                        } else if button == Key::Key2 {
+                            self.apply_filter(Filter::Despike { radius: 2, k: 3.0 });
+                        } else if button == Key::Key3 {
+                            self.apply_filter(Filter::Terrace { radius: 2 });
+                        } else if button == Key::Key4 {
+                            self.apply_filter(Filter::Levels {
+                                low: 0.0,
+                                high: 1.0,
+                                curve: Curve::Smooth,
+                            });
+                        } else if button == Key::P {
+                            self.flatten_points.push(*self.origin_model.position());
+                        } else if button == Key::F {
+                            self.flatten_region();
+                        } else if button == Key::C {
+                            self.flatten_points.clear();
+                        } else if button == Key::U {
+                            self.generate_alphamap();
+                        } else if button == Key::Z {
+                            self.show_contours = !self.show_contours;
+                            self.update_contours();
+                        } else if button == Key::X {
+                            self.measure_points.clear();
+                            self.update_profile();
+                        } else if button == Key::E {
+                            self.export_profile();
+                        } else if button == Key::V {
+                            self.place_pilot();
+                        } else if button == Key::Comma {
+                            self.adjust_flight_height(-1.0);
+                        } else if button == Key::Period {
+                            self.adjust_flight_height(1.0);
+                        } else if button == Key::J {
+                            self.find_landing_spots();
+                        } else if button == Key::H {
+                            self.hmap.set_color_ramp(self.hmap.color_ramp().next());
+                        } else if button == Key::LBracket {
+                            self.adjust_ramp_range(-1.0, 0.0);
+                        } else if button == Key::RBracket {
+                            self.adjust_ramp_range(1.0, 0.0);
+                        } else if button == Key::Minus {
+                            self.adjust_ramp_range(0.0, -1.0);
+                        } else if button == Key::Equals {
+                            self.adjust_ramp_range(0.0, 1.0);
                         }
 
                         // Override the default keyboard handler
                         event.inhibited = true
                     }
+                    WindowEvent::Key(Key::I, Action::Release, _)
+                    | WindowEvent::Key(Key::K, Action::Release, _) => {
+                        // Bake once the height scale settles
+                        self.hmap.refresh_mode_texture();
+                        event.inhibited = true
+                    }
+                    WindowEvent::MouseButton(MouseButton::Button1, Action::Press, mods)
+                        if mods.contains(Modifiers::Control) =>
+                    {
+                        self.add_measure_point();
+                        event.inhibited = true
+                    }
+                    WindowEvent::MouseButton(MouseButton::Button1, Action::Press, mods)
+                        if mods.contains(Modifiers::Shift) =>
+                    {
+                        self.add_flatten_point();
+                        event.inhibited = true
+                    }
+                    WindowEvent::MouseButton(MouseButton::Button1, Action::Press, _)
+                        if self.ortho_view.contains(&ortho_cursor) =>
+                    {
+                        self.ortho_view.start_drag(&ortho_cursor);
+                        event.inhibited = true
+                    }
+                    WindowEvent::MouseButton(MouseButton::Button1, Action::Release, _)
+                        if self.ortho_view.is_dragging() =>
+                    {
+                        if let Some((x, z)) = self.ortho_view.end_drag() {
+                            self.move_camera_to(x, z);
+                        }
+                        event.inhibited = true
+                    }
+                    WindowEvent::Scroll(_, off, _) if self.ortho_view.contains(&ortho_cursor) => {
+                        self.ortho_view.zoom_at(&ortho_cursor, off as _);
+                        event.inhibited = true
+                    }
                     WindowEvent::CursorPos(x, y, _) => {
-                        self.origin_model.set_position(&self.cam, x as _, y as _);
-                        self.ortho_view
-                            .set_origin_position(self.origin_model.position());
-                        // Dont override the default handler
+                        self.cursor_pos = Point2::new(x as _, y as _);
+                        ortho_cursor = self.ortho_view.cursor_to_planar(&self.cursor_pos);
+
+                        if self.ortho_view.is_dragging() {
+                            // Pan the ortho view instead of rotating the camera
+                            self.ortho_view.drag_to(&ortho_cursor);
+                            event.inhibited = true
+                        } else if !self.ortho_view.contains(&ortho_cursor) {
+                            self.origin_model.set_position(&self.cam, x as _, y as _);
+                            self.ortho_view
+                                .set_origin_position(self.origin_model.position());
+                            // Dont override the default handler
+                        }
                     }
                     WindowEvent::FramebufferSize(w, h) => {
                         self.origin_model.set_screen_size(&self.cam, w as _, h as _);
+                        self.ortho_view
+                            .set_window_size(w, h, self.win.hidpi_factor());
+                        self.profile_view
+                            .set_window_size(w, h, self.win.hidpi_factor());
                         // Dont override the default handler
                     }
                     _ => {}
                 }
             }
 
-            if some_events {
-                self.ortho_view.set_cam_position(&self.cam.eye());
-                self.ortho_view.set_cam_orientation(&self.cam.eye_dir());
-            }
-
             self.ground_plane.draw(&mut self.win);
 
+            self.draw_tile_grid();
+
+            self.draw_ortho_camera();
+
+            self.draw_flatten_outline();
+
+            self.draw_contours();
+
+            self.draw_profile();
+
+            self.draw_pilot();
+
+            self.draw_landing_spots();
+
             self.render_scene_info_text();
         }
 
         keep_rendering
     }
 
+    /// Reset the camera of the current mode, the ArcBall orbits around the
+    /// origin model from where it is
     fn reset_camera(&mut self) {
-        let eye = Point3::new(-20.0, 20.0, -20.0);
-        let at = Point3::new(0.0, 0.0, 0.0);
-        self.cam.look_at(eye, at);
-        self.cam.set_move_step(1.0);
+        match self.cam.mode() {
+            CameraMode::FirstPerson => {
+                let eye = Point3::new(-20.0, 20.0, -20.0);
+                let at = Point3::new(0.0, 0.0, 0.0);
+                self.cam.look_at(eye, at);
+            }
+            CameraMode::ArcBall => {
+                let eye = self.cam.eye();
+                self.cam.look_at(eye, *self.origin_model.position());
+            }
+            CameraMode::TopDown => {
+                let (min, max) = self.hmap.mesh_bounds();
+                self.cam.fit_top_down(&min, &max);
+            }
+        }
+    }
 
-        // Rebind arrow key movement to ASDW keys
-        self.cam.rebind_up_key(Some(Key::W));
-        self.cam.rebind_down_key(Some(Key::S));
-        self.cam.rebind_left_key(Some(Key::A));
-        self.cam.rebind_right_key(Some(Key::D));
+    /// Switch camera modes, moving smoothly to the view of the new mode
+    fn set_camera_mode(&mut self, mode: CameraMode) {
+        let (min, max) = self.hmap.mesh_bounds();
+        self.cam
+            .set_mode(mode, self.origin_model.position(), (&min, &max));
+    }
+
+    /// Keep the raised or lowered terrain within the depth of the top down
+    /// view
+    fn fit_camera_depth(&mut self) {
+        let (min, max) = self.hmap.mesh_bounds();
+        self.cam.fit_top_down_depth(&min, &max);
+    }
+
+    /// Scale the movement speed of the current camera mode
+    fn adjust_camera_speed(&mut self, factor: f32) {
+        let speed = (self.cam.speed() * factor).clamp(0.05, 20.0);
+        self.cam.set_speed(speed);
+    }
+
+    /// Center the camera on the terrain at scene (x, z) coordinates, keeping
+    /// its distance and direction
+    fn move_camera_to(&mut self, x: f32, z: f32) {
+        let y = self
+            .hmap
+            .elevation_at(x, z, self.interpolation)
+            .unwrap_or(0.0);
+        let at = Point3::new(x, y, z);
+        let offset = self.cam.eye() - self.cam.at();
+        self.cam.look_at(at + offset, at);
+    }
+
+    /// Preview a filter, the source file is left untouched, reload (R) to revert
+    fn apply_filter(&mut self, filter: Filter) {
+        println!("Applying filter {}", filter);
+        self.hmap.apply_filter(&filter);
+        MeshManager::get_global_manager(|mm| self.hmap.update_mesh_tiles(mm));
+        self.last_filter = Some(filter);
+        self.update_contours();
+        self.update_profile();
+    }
+
+    /// Set the terrain mode, the ortho view shows the same texture
+    fn set_terrain_mode(&mut self, mode: TerrainMode) {
+        self.hmap.set_terrain_mode(mode);
+        self.ortho_view.set_texture(self.hmap.mode_texture_name());
+    }
+
+    /// Move the pilot to the origin model and show what's visible from there
+    fn place_pilot(&mut self) {
+        let (x, y) = self.hmap.mesh_to_pixel(self.origin_model.position());
+        let (w, h) = self.hmap.dimensions();
+        if x < 0.0 || y < 0.0 || x > (w - 1) as f64 || y > (h - 1) as f64 {
+            return;
+        }
+
+        let params = ViewshedParams {
+            pilot: (x, y),
+            ..self.hmap.viewshed_params()
+        };
+        self.hmap.set_viewshed_params(params);
+        self.set_terrain_mode(TerrainMode::Viewshed);
+    }
+
+    /// Move the flight height of the viewshed by steps of 1% of the value
+    /// range
+    fn adjust_flight_height(&mut self, steps: f32) {
+        let (min, max) = self.hmap.value_range();
+        let params = self.hmap.viewshed_params();
+        let flight_height = (params.flight_height + steps * (max - min) / 100.0).max(0.0);
+        self.hmap.set_viewshed_params(ViewshedParams {
+            flight_height,
+            ..params
+        });
+    }
+
+    /// Pilot eye and flight height above the pilot position, in viewshed mode
+    fn draw_pilot(&mut self) {
+        if self.hmap.terrain_mode() != TerrainMode::Viewshed {
+            return;
+        }
+
+        let params = self.hmap.viewshed_params();
+        let (x, y) = (params.pilot.0 as f32, params.pilot.1 as f32);
+        let (w, h) = self.hmap.dimensions();
+        let ground = self
+            .interpolation
+            .sample(self.hmap.elevations(), w, h, x, y);
+        let base = self.hmap.pixel_to_mesh(x, y, ground);
+        let eye = self.hmap.pixel_to_mesh(x, y, ground + params.eye_height);
+        let flight = self.hmap.pixel_to_mesh(x, y, ground + params.flight_height);
+
+        self.win.draw_line(&base, &eye, &Point3::new(0.0, 0.0, 1.0));
+        self.win
+            .draw_line(&eye, &flight, &Point3::new(1.0, 1.0, 1.0));
+
+        if self.ortho_view.is_visible() {
+            self.ortho_view
+                .draw_marker(&mut self.win, &base, &Point3::new(1.0, 1.0, 1.0));
+        }
+    }
+
+    pub fn set_landing(&mut self, params: LandingParams, spots: Vec<LandingSpot>) {
+        self.landing_params = params;
+        self.landing_spots = spots;
+    }
+
+    /// Find landing spots, ranked by distance from the origin model when
+    /// ranking by distance
+    fn find_landing_spots(&mut self) {
+        let origin = self.hmap.mesh_to_pixel(self.origin_model.position());
+        self.landing_spots = self.hmap.landing_spots(&self.landing_params, origin);
+        println!("Found {} landing spots", self.landing_spots.len());
+    }
+
+    /// Outline the landing spots on the terrain and in the ortho view, the
+    /// best spot is brightest
+    fn draw_landing_spots(&mut self) {
+        let ortho = self.ortho_view.is_visible();
+        let (w, _) = self.hmap.dimensions();
+
+        for (rank, spot) in self.landing_spots.iter().enumerate() {
+            let fade = 1.0 - 0.6 * rank as f32 / self.landing_spots.len() as f32;
+            let color = Point3::new(0.2 * fade, 0.6 * fade, 1.0 * fade);
+
+            let (cx, cy) = (spot.center.0 as f32, spot.center.1 as f32);
+            let ground = self.hmap.elevations()[spot.center.0 + spot.center.1 * w];
+            let r = spot.half_size as f32 + 0.5;
+            let corners: Vec<Point3<f32>> = [(-r, -r), (r, -r), (r, r), (-r, r)]
+                .iter()
+                .map(|(dx, dy)| self.hmap.pixel_to_mesh(cx + dx, cy + dy, ground))
+                .collect();
+
+            for i in 0..4 {
+                let (a, b) = (&corners[i], &corners[(i + 1) % 4]);
+                self.win.draw_line(a, b, &color);
+                if ortho {
+                    self.ortho_view.draw_line(&mut self.win, a, b, &color);
+                }
+            }
+
+            // Pole marking the center, visible from a distance
+            let center = self.hmap.pixel_to_mesh(cx, cy, ground);
+            let top = Point3::new(center.x, center.y + 2.0 * r + 5.0, center.z);
+            self.win.draw_line(&center, &top, &color);
+        }
+    }
+
+    pub fn set_profile_file(&mut self, path: PathBuf) {
+        self.profile_file = path;
+    }
+
+    /// Add the point of the terrain under the cursor to the measurement
+    fn add_measure_point(&mut self) {
+        let size = Vector2::new(self.win.width() as f32, self.win.height() as f32);
+        let (origin, dir) = self.cam.unproject(&self.cursor_pos, &size);
+
+        if let Some(p) = self
+            .hmap
+            .ray_intersection(&origin, &dir, self.interpolation)
+        {
+            self.measure_points.push(p);
+            self.update_profile();
+        }
+    }
+
+    /// Add the point of the terrain under the cursor to the flatten outline
+    fn add_flatten_point(&mut self) {
+        let size = Vector2::new(self.win.width() as f32, self.win.height() as f32);
+        let (origin, dir) = self.cam.unproject(&self.cursor_pos, &size);
+
+        if let Some(p) = self
+            .hmap
+            .ray_intersection(&origin, &dir, self.interpolation)
+        {
+            self.flatten_points.push(p);
+        }
+    }
+
+    /// Resample the profile along the measure points, i.e. after the terrain
+    /// changed
+    fn update_profile(&mut self) {
+        self.profile = self.hmap.profile(&self.measure_points, self.interpolation);
+    }
+
+    fn export_profile(&self) {
+        if self.profile.is_empty() {
+            println!("No profile to export, ctrl+click the terrain to add points");
+            return;
+        }
+
+        match profile::write_csv(&self.profile_file, &self.profile) {
+            Ok(()) => println!("Wrote {}", self.profile_file.display()),
+            Err(e) => println!("Failed to write {}: {}", self.profile_file.display(), e),
+        }
+    }
+
+    /// Draw the profile draped over the terrain and its graph
+    fn draw_profile(&mut self) {
+        let color = Point3::new(0.0, 1.0, 1.0);
+        let points: Vec<Point3<f32>> = self
+            .profile
+            .iter()
+            .map(|s| {
+                self.hmap
+                    .pixel_to_mesh(s.pixel.0 as f32, s.pixel.1 as f32, s.elevation)
+            })
+            .collect();
+        for pair in points.windows(2) {
+            self.win.draw_line(&pair[0], &pair[1], &color);
+        }
+
+        if self.ortho_view.is_visible() {
+            for pair in points.windows(2) {
+                self.ortho_view
+                    .draw_line(&mut self.win, &pair[0], &pair[1], &color);
+            }
+            for p in &self.measure_points {
+                self.ortho_view.draw_marker(&mut self.win, p, &color);
+            }
+        }
+
+        self.profile_view.draw(&mut self.win, &self.profile);
+    }
+
+    pub fn set_interpolation(&mut self, interp: Interpolation) {
+        self.interpolation = interp;
+    }
+
+    pub fn set_contour_interval(&mut self, interval: f32) {
+        self.contour_interval = interval;
+        self.update_contours();
+    }
+
+    /// Retrace the contours after the terrain changed, only while shown
+    fn update_contours(&mut self) {
+        self.contour_lines_scale = None;
+        if !self.show_contours {
+            self.contours = Vec::new();
+            return;
+        }
+
+        match self.hmap.contours(self.contour_interval, 0.0) {
+            Ok(contours) => self.contours = contours,
+            Err(e) => {
+                println!("{}", e);
+                self.contours = Vec::new();
+                self.show_contours = false;
+            }
+        }
+    }
+
+    /// Draw the contours on the terrain, and in the ortho view when visible.
+    /// The lines are placed in the scene once per height scale, but kiss3d
+    /// draws lines immediately, so every segment is submitted each frame.
+    fn draw_contours(&mut self) {
+        let scale = (self.hmap.height_scale(), self.hmap.height_offset());
+        if self.contour_lines_scale != Some(scale) {
+            let hmap = &self.hmap;
+            self.contour_lines = self
+                .contours
+                .iter()
+                .map(|c| {
+                    c.points
+                        .iter()
+                        .map(|p| hmap.pixel_to_mesh(p.0, p.1, c.level))
+                        .collect()
+                })
+                .collect();
+            self.contour_lines_scale = Some(scale);
+        }
+
+        let color = Point3::new(1.0, 0.6, 0.2);
+        let ortho = self.ortho_view.is_visible();
+        for points in &self.contour_lines {
+            for pair in points.windows(2) {
+                self.win.draw_line(&pair[0], &pair[1], &color);
+                if ortho {
+                    self.ortho_view
+                        .draw_line(&mut self.win, &pair[0], &pair[1], &color);
+                }
+            }
+        }
+    }
+
+    /// Move the color ramp min/max by steps of 5% of the elevation range
+    fn adjust_ramp_range(&mut self, min_steps: f32, max_steps: f32) {
+        let (low, high) = self.hmap.value_range();
+        let step = (high - low) * 0.05;
+        let (min, max) = self.hmap.ramp_range();
+        self.hmap
+            .set_ramp_range(min + min_steps * step, max + max_steps * step);
+    }
+
+    /// Show the alphamap, regenerated from the rules file with U
+    pub fn preview_alphamap_rules(&mut self, rules_file: PathBuf) {
+        self.alphamap_rules = Some(rules_file);
+        self.set_terrain_mode(TerrainMode::Alphamap);
+    }
+
+    /// Regenerate the alphamap from the rules file and current terrain, the
+    /// alphamap file is left untouched
+    fn generate_alphamap(&mut self) {
+        let rules_file = match &self.alphamap_rules {
+            Some(f) => f,
+            None => return,
+        };
+        let rules = match Rules::from_file(rules_file) {
+            Ok(rules) => rules,
+            Err(e) => {
+                println!("Failed to read alphamap rules: {:?}", e);
+                return;
+            }
+        };
+
+        println!("Generating alphamap from {}", rules_file.display());
+        let (w, h) = self.hmap.dimensions();
+        let img = rules.generate(&Attributes::from_heightmap(&self.hmap), w, h);
+        self.amap.set_image(img);
+    }
+
+    /// Flatten the outlined region to its mean elevation, two points
+    /// outline a rectangle
+    fn flatten_region(&mut self) {
+        let points: Vec<(f64, f64)> = self
+            .flatten_points
+            .iter()
+            .map(|p| self.hmap.mesh_to_pixel(p))
+            .collect();
+        let region = match points.len() {
+            0 | 1 => return,
+            2 => Region::Rect(points[0], points[1]),
+            _ => Region::Polygon(points),
+        };
+        let params = FlattenParams {
+            target: None,
+            margin: 8.0,
+        };
+
+        let value_range = self.hmap.value_range();
+        if let Some(bounds) = self.hmap.flatten_pixels(&region, &params) {
+            println!("Flattened pixels {:?}", bounds);
+            // A new value range moves the base of every tile
+            if self.hmap.value_range() == value_range {
+                MeshManager::get_global_manager(|mm| self.hmap.update_mesh_tiles_in(mm, bounds));
+            } else {
+                MeshManager::get_global_manager(|mm| self.hmap.update_mesh_tiles(mm));
+            }
+            self.update_contours();
+            self.update_profile();
+        }
+        self.flatten_points.clear();
+    }
+
+    fn draw_flatten_outline(&mut self) {
+        let color = Point3::new(1.0, 0.0, 1.0);
+        let points = &self.flatten_points;
+        let outline = if points.len() == 2 {
+            let (a, b) = (points[0], points[1]);
+            vec![a, Point3::new(b.x, a.y, a.z), b, Point3::new(a.x, b.y, b.z)]
+        } else {
+            points.clone()
+        };
+
+        let ortho = self.ortho_view.is_visible();
+        for i in 0..outline.len() {
+            let (a, b) = (&outline[i], &outline[(i + 1) % outline.len()]);
+            self.win.draw_line(a, b, &color);
+            if ortho {
+                self.ortho_view.draw_line(&mut self.win, a, b, &color);
+            }
+        }
+        if ortho {
+            for p in points {
+                self.ortho_view.draw_marker(&mut self.win, p, &color);
+            }
+        }
+    }
+
+    /// Draw the camera field of view in the ortho view, out to where the view
+    /// direction hits the terrain
+    fn draw_ortho_camera(&mut self) {
+        if !self.ortho_view.is_visible() {
+            return;
+        }
+
+        let eye = self.cam.eye();
+        let eye_dir = self.cam.eye_dir();
+        let aspect = self.win.width() as f32 / self.win.height().max(1) as f32;
+        let hfov = 2.0 * ((CAMERA_FOV / 2.0).tan() * aspect).atan();
+        let target = self
+            .hmap
+            .ray_intersection(&eye, &eye_dir, self.interpolation);
+        let height = self.camera_height();
+
+        self.ortho_view
+            .draw_camera(&mut self.win, &eye, &eye_dir, hfov, target.as_ref(), height);
+    }
+
+    /// Height of the camera above the terrain under it, None outside of the
+    /// terrain
+    fn camera_height(&self) -> Option<f32> {
+        let eye = self.cam.eye();
+        self.hmap
+            .elevation_at(eye.x, eye.z, self.interpolation)
+            .map(|e| eye.y - e)
+    }
+
+    /// Outline the mesh tiles in the ortho view, named when there's room
+    fn draw_tile_grid(&mut self) {
+        if !self.ortho_view.is_visible() || !self.show_tile_grid {
+            return;
+        }
+
+        let color = Point3::new(0.8, 0.8, 0.8);
+        let ground = self.hmap.value_range().0;
+        let show_names = TILE_SIZE as f32 * self.ortho_view.units_to_planar() >= 40.0;
+
+        for tile in self.hmap.tiles() {
+            let (x, y) = (tile.start().0 as f32, tile.start().1 as f32);
+            let (w, h) = (tile.dimensions().0 as f32, tile.dimensions().1 as f32);
+            let corners: Vec<Point3<f32>> = [(0.0, 0.0), (w, 0.0), (w, h), (0.0, h)]
+                .iter()
+                .map(|(dx, dy)| self.hmap.pixel_to_mesh(x + dx - 0.5, y + dy - 0.5, ground))
+                .collect();
+
+            for i in 0..4 {
+                self.ortho_view.draw_line(
+                    &mut self.win,
+                    &corners[i],
+                    &corners[(i + 1) % 4],
+                    &color,
+                );
+            }
+
+            if show_names {
+                let center = self
+                    .hmap
+                    .pixel_to_mesh(x + w / 2.0 - 0.5, y + h / 2.0 - 0.5, ground);
+                self.ortho_view
+                    .draw_label(&mut self.win, &center, tile.name(), 25.0, &color);
+            }
+        }
+    }
+
+    /// Elevations at even steps over the ramp range, highest first, each in
+    /// its ramp color
+    fn render_color_ramp_legend(
+        &mut self,
+        font_pos: &mut Point2<f32>,
+        font_size: f32,
+        next_font: f32,
+    ) {
+        let font_color = Point3::new(1.0, 1.0, 0.0);
+        let ramp = self.hmap.color_ramp();
+        let (min, max) = self.hmap.ramp_range();
+        let steps = 5;
+
+        font_pos.y += next_font;
+        self.win.draw_text(
+            &format!("Color Ramp: {:?}", ramp),
+            font_pos,
+            font_size,
+            &Font::default(),
+            &font_color,
+        );
+
+        for i in (0..=steps).rev() {
+            let t = i as f32 / steps as f32;
+            let c = ramp.color_f32(t);
+            font_pos.y += next_font;
+            self.win.draw_text(
+                &format!("  {:.2}", min + t * (max - min)),
+                font_pos,
+                font_size,
+                &Font::default(),
+                &Point3::new(c[0], c[1], c[2]),
+            );
+        }
+    }
+
+    /// Slope classes, steepest first, each in its class color
+    fn render_slope_legend(&mut self, font_pos: &mut Point2<f32>, font_size: f32, next_font: f32) {
+        let classes = self.hmap.slope_classes().clone();
+
+        for class in (0..classes.count()).rev() {
+            let c = classes.color(class);
+            font_pos.y += next_font;
+            self.win.draw_text(
+                &format!("  {}", classes.label(class)),
+                font_pos,
+                font_size,
+                &Font::default(),
+                &Point3::new(
+                    f32::from(c[0]) / 255.0,
+                    f32::from(c[1]) / 255.0,
+                    f32::from(c[2]) / 255.0,
+                ),
+            );
+        }
     }
 
     fn render_scene_info_text(&mut self) {
@@ -166,6 +863,19 @@ impl Gui {
             &font_color,
         );
 
+        font_pos.y += next_font;
+        self.win.draw_text(
+            &format!(
+                "Camera Mode: {:?} Speed: {:.2}",
+                self.cam.mode(),
+                self.cam.speed()
+            ),
+            &font_pos,
+            font_size,
+            &Font::default(),
+            &font_color,
+        );
+
         font_pos.y += next_font;
         self.win.draw_text(
             &format!("Height Scale: {}", self.hmap.height_scale()),
@@ -192,5 +902,117 @@ impl Gui {
             &Font::default(),
             &font_color,
         );
+
+        let p = *self.origin_model.position();
+        let height = self.hmap.elevation_at(p.x, p.z, self.interpolation);
+        let slope = self.hmap.slope_at(p.x, p.z, self.interpolation);
+        if let (Some(height), Some(slope)) = (height, slope) {
+            font_pos.y += next_font;
+            self.win.draw_text(
+                &format!("Terrain Height: {:.2} Slope: {:.1} deg", height, slope),
+                &font_pos,
+                font_size,
+                &Font::default(),
+                &font_color,
+            );
+        }
+
+        if let Some(height) = self.camera_height() {
+            font_pos.y += next_font;
+            self.win.draw_text(
+                &format!("Camera Height: {:.2} above terrain", height),
+                &font_pos,
+                font_size,
+                &Font::default(),
+                &font_color,
+            );
+        }
+
+        if self.hmap.terrain_mode() == TerrainMode::ColorRamp {
+            self.render_color_ramp_legend(&mut font_pos, font_size, next_font);
+        } else if self.hmap.terrain_mode() == TerrainMode::Slope {
+            self.render_slope_legend(&mut font_pos, font_size, next_font);
+        } else if self.hmap.terrain_mode() == TerrainMode::Viewshed {
+            let params = self.hmap.viewshed_params();
+            font_pos.y += next_font;
+            self.win.draw_text(
+                &format!(
+                    "Pilot Eye Height: {} Flight Height: {:.2}",
+                    params.eye_height, params.flight_height
+                ),
+                &font_pos,
+                font_size,
+                &Font::default(),
+                &font_color,
+            );
+        }
+
+        if !self.flatten_points.is_empty() {
+            font_pos.y += next_font;
+            self.win.draw_text(
+                &format!("Flatten Points: {}", self.flatten_points.len()),
+                &font_pos,
+                font_size,
+                &Font::default(),
+                &font_color,
+            );
+        }
+
+        if let Some(best) = self.landing_spots.first() {
+            font_pos.y += next_font;
+            self.win.draw_text(
+                &format!(
+                    "Landing Spots: {} Best: {:.1} m, {:.1} deg, {:.0} m away",
+                    self.landing_spots.len(),
+                    best.size,
+                    best.mean_slope,
+                    best.distance
+                ),
+                &font_pos,
+                font_size,
+                &Font::default(),
+                &font_color,
+            );
+        }
+
+        if !self.measure_points.is_empty() {
+            let m = Measurement::from_profile(&self.profile, self.hmap.meters_per_unit());
+            font_pos.y += next_font;
+            self.win.draw_text(
+                &format!(
+                    "Measure Points: {} Distance: {:.1} m (3D {:.1} m) Elevation: {:+.2} m",
+                    self.measure_points.len(),
+                    m.horizontal,
+                    m.distance_3d,
+                    m.elevation_diff
+                ),
+                &font_pos,
+                font_size,
+                &Font::default(),
+                &font_color,
+            );
+        }
+
+        if self.ortho_view.is_visible() && self.ortho_view.zoom() > 1.0 {
+            font_pos.y += next_font;
+            self.win.draw_text(
+                &format!("Ortho View Zoom: {:.1}x", self.ortho_view.zoom()),
+                &font_pos,
+                font_size,
+                &Font::default(),
+                &font_color,
+            );
+        }
+
+        if let Some(filter) = self.last_filter {
+            font_pos.y += next_font;
+            self.win.draw_text(
+                &format!("Last Filter: {}", filter),
+                &font_pos,
+                font_size,
+                &Font::default(),
+                &font_color,
+            );
+        }
     }
 }
@@ -1,33 +1,91 @@
 // TODO - verify channel depth, hard-coded to 8 bits
 
+use crate::ascii_grid;
+use crate::color_ramp::ColorRamp;
+use crate::contour::{self, Contour};
+use crate::erosion::{self, HydraulicParams, ThermalParams};
+use crate::filter::{self, Filter};
+use crate::flatten::{self, Bounds, FlattenParams, Region};
+use crate::geo_transform::GeoTransform;
+use crate::geotiff;
+use crate::hgt;
+use crate::interpolation::Interpolation;
+use crate::landing::{self, LandingParams, LandingSpot};
+use crate::lightmap::{self, LightmapParams};
+use crate::normal_map::{self, NormalSpace};
+use crate::profile::ProfileSample;
+use crate::raster::Raster;
+use crate::raw::{self, ByteOrder, RawFormat, RawLayout};
+use crate::slope::SlopeClasses;
 use crate::terrain_mode::TerrainMode;
-use image::{DynamicImage, GenericImage, ImageError, Pixel};
+use crate::textures;
+use crate::viewshed::{self, ViewshedParams};
+use image::{
+    ColorType, DynamicImage, GenericImage, GrayImage, ImageBuffer, ImageError, Luma, Rgb, RgbImage,
+};
 use kiss3d::resource::{Mesh, MeshManager, TextureManager};
 use kiss3d::scene::SceneNode;
 use kiss3d::window::Window;
-use nalgebra::{normalize, Point2, Point3, Translation3, Vector3};
+use nalgebra::{Point2, Point3, Translation3, Vector3};
 use std::cell::RefCell;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use tiff::TiffError;
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum Error {
     InvalidFile,
     FileNotSupported,
+    InvalidDimensions,
 }
 
 pub struct Heightmap {
     src_path: PathBuf,
-    src_img: DynamicImage,
+    /// Row-major source elevations, top row first
+    elevations: Vec<f32>,
     width: usize,
     height: usize,
+    geo_transform: GeoTransform,
+    /// Source elevations mapped to black/white in the source texture
+    value_range: (f32, f32),
+    /// Source elevation units per mesh unit, one mesh unit per pixel
+    units_per_pixel: f32,
+    /// Elevations are normalized to [0, 1] rather than real-world units,
+    /// i.e. image heightmaps
+    normalized: bool,
+    /// Layout used to load a raw source file, kept for reloading
+    raw_layout: Option<RawLayout>,
+    /// Bits per sample of the source file, None for text formats and
+    /// generated heightmaps
+    bit_depth: Option<u8>,
     height_scale: f32,
     height_offset: f32,
     terrain_mode: TerrainMode,
+    color_ramp: ColorRamp,
+    slope_classes: SlopeClasses,
+    viewshed: ViewshedParams,
+    /// Source elevations mapped to the ends of the color ramp
+    ramp_range: (f32, f32),
+    /// The ramp range was set explicitly and is kept on reload
+    ramp_range_set: bool,
     tiles: Vec<Tile>,
+    bakes: Bakes,
+    /// The mode texture is out of date, see `refresh_mode_texture`
+    mode_texture_stale: bool,
 }
 
-const TILE_SIZE: usize = 128;
+/// Textures behind the terrain modes that are slow to compute, kept until
+/// the elevations or the height scale change
+#[derive(Default)]
+struct Bakes {
+    lightmap: Option<GrayImage>,
+    hillshade: Option<GrayImage>,
+    /// See `viewshed_heights`, also kept until the pilot moves
+    viewshed_heights: Option<Vec<f32>>,
+}
+
+/// Size of the terrain mesh tiles, in pixels
+pub const TILE_SIZE: usize = 128;
 
 pub struct Tile {
     name: String,
@@ -42,38 +100,187 @@ impl Tile {
     pub fn name(&self) -> &str {
         &self.name
     }
+
+    /// Pixel coordinates of the top left corner
+    pub fn start(&self) -> (usize, usize) {
+        (self.start_x, self.start_y)
+    }
+
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
 }
 
 impl Heightmap {
+    /// Load a heightmap, the format is selected by the file extension
+    pub fn from_file(file_path: &Path) -> Result<Self, Error> {
+        let ext = file_path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase());
+
+        match ext.as_deref() {
+            Some("png") => Self::from_png_file(file_path),
+            Some("tif") | Some("tiff") => Self::from_geotiff_file(file_path),
+            Some("asc") => Self::from_ascii_grid_file(file_path),
+            Some("hgt") => Self::from_hgt_file(file_path),
+            Some("raw") | Some("r16") | Some("r32") => {
+                Self::from_raw_file(file_path, RawLayout::default())
+            }
+            _ => Err(Error::FileNotSupported),
+        }
+    }
+
     pub fn from_png_file(file_path: &Path) -> Result<Self, Error> {
         let src_img = image::open(file_path)?;
 
         let (src_width, src_height) = src_img.dimensions();
+        let bit_depth = match src_img.color() {
+            ColorType::Gray(bits)
+            | ColorType::RGB(bits)
+            | ColorType::Palette(bits)
+            | ColorType::GrayA(bits)
+            | ColorType::RGBA(bits) => bits,
+        };
 
-        // TODO - make this not necessary
-        // Enforce mesh tiling constraints
-        assert_eq!(
-            src_width as usize % TILE_SIZE,
-            0,
-            "Only mod {} dimensions are supported",
-            TILE_SIZE
-        );
-        assert_eq!(
-            src_height as usize % TILE_SIZE,
-            0,
-            "Only mod {} dimensions are supported",
-            TILE_SIZE
-        );
+        let elevations = src_img
+            .to_luma()
+            .into_raw()
+            .into_iter()
+            .map(|p| f32::from(p) / 255.0)
+            .collect();
+
+        let mut hmap =
+            Self::from_normalized(file_path, src_width as _, src_height as _, elevations)?;
+        hmap.bit_depth = Some(bit_depth);
+
+        Ok(hmap)
+    }
+
+    /// Load a headerless 16 bit (.raw/.r16) or 32 bit float (.r32) file
+    ///
+    /// 16 bit samples span the full height range like a PNG heightmap,
+    /// float samples are kept as-is at one unit per pixel.
+    pub fn from_raw_file(file_path: &Path, layout: RawLayout) -> Result<Self, Error> {
+        let format = RawFormat::from_path(file_path).ok_or(Error::FileNotSupported)?;
+        let (width, height, data) = raw::read_file(file_path, format, layout)?;
+
+        let mut hmap = match format {
+            RawFormat::U16 => Self::from_normalized(file_path, width, height, data)?,
+            RawFormat::F32 => Self::from_raster(
+                file_path,
+                Raster {
+                    width,
+                    height,
+                    data,
+                    geo_transform: GeoTransform::default(),
+                    nodata: None,
+                    bit_depth: None,
+                },
+            )?,
+        };
+        hmap.raw_layout = Some(layout);
+        hmap.bit_depth = Some(match format {
+            RawFormat::U16 => 16,
+            RawFormat::F32 => 32,
+        });
+
+        Ok(hmap)
+    }
+
+    /// Load a single channel (int/float) GeoTIFF DEM, elevations are
+    /// kept as-is and the mesh is generated at real-world scale
+    pub fn from_geotiff_file(file_path: &Path) -> Result<Self, Error> {
+        let raster = geotiff::read_file(file_path)?;
+        Self::from_raster(file_path, raster)
+    }
+
+    /// Load an ESRI ASCII grid DEM
+    pub fn from_ascii_grid_file(file_path: &Path) -> Result<Self, Error> {
+        let raster = ascii_grid::read_file(file_path)?;
+        Self::from_raster(file_path, raster)
+    }
+
+    /// Load an SRTM tile, the file name must be the tile name (N37W122.hgt)
+    pub fn from_hgt_file(file_path: &Path) -> Result<Self, Error> {
+        let raster = hgt::read_file(file_path)?;
+        Self::from_raster(file_path, raster)
+    }
+
+    /// Elevations in [0, 1], scaled by the height scale
+    pub fn from_normalized(
+        file_path: &Path,
+        width: usize,
+        height: usize,
+        elevations: Vec<f32>,
+    ) -> Result<Self, Error> {
+        check_dimensions(width, height)?;
 
         Ok(Self {
             src_path: file_path.to_path_buf(),
-            src_img,
-            width: src_width as _,
-            height: src_height as _,
+            elevations,
+            width,
+            height,
+            geo_transform: GeoTransform::default(),
+            value_range: (0.0, 1.0),
+            units_per_pixel: 1.0,
+            normalized: true,
+            raw_layout: None,
+            bit_depth: None,
             height_scale: 10.0,
             height_offset: 0.0,
             terrain_mode: TerrainMode::Textured,
+            color_ramp: ColorRamp::Terrain,
+            slope_classes: SlopeClasses::default(),
+            viewshed: ViewshedParams {
+                pilot: (width as f64 / 2.0, height as f64 / 2.0),
+                eye_height: 0.01,
+                flight_height: 0.05,
+            },
+            ramp_range: (0.0, 1.0),
+            ramp_range_set: false,
+            tiles: Vec::new(),
+            bakes: Bakes::default(),
+            mode_texture_stale: false,
+        })
+    }
+
+    fn from_raster(file_path: &Path, mut raster: Raster) -> Result<Self, Error> {
+        check_dimensions(raster.width, raster.height)?;
+
+        if !raster.fill_nodata() {
+            return Err(Error::InvalidFile);
+        }
+
+        let (min, max) = raster.min_max();
+        let (pw, ph) = raster.geo_transform.pixel_size_meters(raster.height);
+
+        Ok(Self {
+            src_path: file_path.to_path_buf(),
+            elevations: raster.data,
+            width: raster.width,
+            height: raster.height,
+            geo_transform: raster.geo_transform,
+            value_range: (min, max),
+            units_per_pixel: ((pw + ph) / 2.0) as f32,
+            normalized: false,
+            raw_layout: None,
+            bit_depth: raster.bit_depth,
+            height_scale: 1.0,
+            height_offset: 0.0,
+            terrain_mode: TerrainMode::Textured,
+            color_ramp: ColorRamp::Terrain,
+            slope_classes: SlopeClasses::default(),
+            viewshed: ViewshedParams {
+                pilot: (raster.width as f64 / 2.0, raster.height as f64 / 2.0),
+                eye_height: 1.8,
+                flight_height: 30.0,
+            },
+            ramp_range: (min, max),
+            ramp_range_set: false,
             tiles: Vec::new(),
+            bakes: Bakes::default(),
+            mode_texture_stale: false,
         })
     }
 
@@ -81,14 +288,123 @@ impl Heightmap {
         (self.width, self.height)
     }
 
-    /*
-    pub fn src_image(&self) -> &DynamicImage {
-        &self.src_img
+    /// Write the heightmap, the format is selected by the file extension
+    ///
+    /// PNG and 16 bit raw files span the source value range, .r32 files
+    /// contain the source elevations as-is.
+    pub fn save_file(&self, file_path: &Path, byte_order: ByteOrder) -> Result<(), Error> {
+        if let Some(format) = RawFormat::from_path(file_path) {
+            match format {
+                RawFormat::U16 => raw::write_file(
+                    file_path,
+                    format,
+                    byte_order,
+                    (0..self.elevations.len()).map(|i| self.normalized_elevation(i)),
+                ),
+                RawFormat::F32 => raw::write_file(
+                    file_path,
+                    format,
+                    byte_order,
+                    self.elevations.iter().cloned(),
+                ),
+            }
+        } else {
+            match file_path.extension().and_then(|e| e.to_str()) {
+                Some(e) if e.eq_ignore_ascii_case("png") => {
+                    self.grayscale_image().save(file_path)?;
+                    Ok(())
+                }
+                _ => Err(Error::FileNotSupported),
+            }
+        }
     }
-    */
 
+    /// Grayscale texture of the source elevations
     pub fn src_texture(&self) -> DynamicImage {
-        DynamicImage::ImageRgb8(self.src_img.to_rgb())
+        DynamicImage::ImageRgb8(DynamicImage::ImageLuma8(self.grayscale_image()).to_rgb())
+    }
+
+    fn grayscale_image(&self) -> ImageBuffer<Luma<u8>, Vec<u8>> {
+        ImageBuffer::from_fn(self.width as _, self.height as _, |x, y| {
+            let v = self.normalized_elevation(x as usize + y as usize * self.width);
+            Luma([(v * 255.0).round() as u8])
+        })
+    }
+
+    /// Elevation at the given index mapped from the value range to [0, 1]
+    fn normalized_elevation(&self, index: usize) -> f32 {
+        let (min, max) = self.value_range;
+        let range = if max > min { max - min } else { 1.0 };
+        ((self.elevations[index] - min) / range).clamp(0.0, 1.0)
+    }
+
+    /// Source elevations mapped from the value range to [0, 1]
+    pub fn normalized_elevations(&self) -> Vec<f32> {
+        (0..self.elevations.len())
+            .map(|i| self.normalized_elevation(i))
+            .collect()
+    }
+
+    /// Slope angle of every pixel in degrees, from the terrain mesh normals at
+    /// the current height scale
+    pub fn slope_degrees(&self) -> Vec<f32> {
+        self.surface_normals()
+            .iter()
+            .map(|n| n.y.clamp(-1.0, 1.0).acos().to_degrees())
+            .collect()
+    }
+
+    /// Vertex normal of every pixel, the normals of the terrain mesh at the
+    /// current height scale
+    pub fn surface_normals(&self) -> Vec<Vector3<f32>> {
+        let bounds = (0, 0, self.width - 1, self.height - 1);
+        self.mesh_normals(bounds, self.height_scale)
+    }
+
+    /// Vertex normals of the pixels within `bounds`, in image order,
+    /// triangulated like the terrain mesh with one mesh unit between pixels
+    /// and the elevations scaled by `height_scale`
+    fn mesh_normals(&self, bounds: Bounds, height_scale: f32) -> Vec<Vector3<f32>> {
+        let (x0, y0, x1, y1) = bounds;
+        // One more pixel around the bounds for the triangles along the edges
+        let (cx0, cy0) = (x0.saturating_sub(1), y0.saturating_sub(1));
+        let (cx1, cy1) = ((x1 + 1).min(self.width - 1), (y1 + 1).min(self.height - 1));
+        let (cw, ch) = (cx1 - cx0 + 1, cy1 - cy0 + 1);
+
+        let mut elevations = Vec::with_capacity(cw * ch);
+        for y in cy0..=cy1 {
+            for x in cx0..=cx1 {
+                elevations.push(self.mesh_elevation(x, y) * height_scale);
+            }
+        }
+        let normals = normal_map::grid_normals(&elevations, cw, ch, (1.0, 1.0));
+
+        let mut cropped = Vec::with_capacity((x1 - x0 + 1) * (y1 - y0 + 1));
+        for y in y0..=y1 {
+            cropped.extend_from_slice(
+                &normals[(x0 - cx0) + (y - cy0) * cw..=(x1 - cx0) + (y - cy0) * cw],
+            );
+        }
+        cropped
+    }
+
+    /// Negative laplacian of the displayed elevations, positive on ridges and
+    /// negative in valleys
+    pub fn curvature(&self) -> Vec<f32> {
+        let (w, h) = (self.width, self.height);
+        let e = |x: usize, y: usize| self.mesh_elevation(x, y) * self.height_scale;
+
+        let mut curvature = Vec::with_capacity(w * h);
+        for y in 0..h {
+            for x in 0..w {
+                let sum = e(x.saturating_sub(1), y)
+                    + e((x + 1).min(w - 1), y)
+                    + e(x, y.saturating_sub(1))
+                    + e(x, (y + 1).min(h - 1));
+                curvature.push(4.0 * e(x, y) - sum);
+            }
+        }
+        curvature
     }
 
     pub fn height_scale(&self) -> f32 {
@@ -99,6 +415,18 @@ impl Heightmap {
         self.height_offset
     }
 
+    /// Meters per source elevation unit, DEMs are in meters and normalized
+    /// heightmaps span the height scale in meters, one meter per pixel
+    pub fn meters_per_unit(&self) -> f32 {
+        if self.normalized {
+            self.height_scale
+        } else {
+            1.0
+        }
+    }
+
+    /// Set the height scale, the mode texture is only updated by
+    /// `refresh_mode_texture` as it may need to be baked again
     pub fn set_height_scale(&mut self, scale: f32) {
         let scale = if scale < 1.0 { 1.0 } else { scale };
 
@@ -108,7 +436,28 @@ impl Heightmap {
             }
         }
 
-        self.height_scale = scale;
+        if scale != self.height_scale {
+            self.height_scale = scale;
+            // DEM viewsheds are in real-world units, independent of the
+            // height scale
+            let viewshed_heights = if self.normalized {
+                None
+            } else {
+                self.bakes.viewshed_heights.take()
+            };
+            self.bakes = Bakes {
+                viewshed_heights,
+                ..Bakes::default()
+            };
+            self.mode_texture_stale = true;
+        }
+    }
+
+    /// Update the mode texture after height scale changes
+    pub fn refresh_mode_texture(&mut self) {
+        if self.mode_texture_stale {
+            self.update_mode_texture();
+        }
     }
 
     pub fn set_height_offset(&mut self, offset: f32) {
@@ -121,6 +470,108 @@ impl Heightmap {
         self.height_offset = offset;
     }
 
+    /// Source elevations, row-major with the top row first
+    pub fn elevations(&self) -> &[f32] {
+        &self.elevations
+    }
+
+    pub fn bit_depth(&self) -> Option<u8> {
+        self.bit_depth
+    }
+
+    /// Generated mesh tiles, empty before `generate_mesh_tiles`
+    pub fn tiles(&self) -> &[Tile] {
+        &self.tiles
+    }
+
+    /// Number of (tiles, vertices, triangles) of the terrain mesh
+    pub fn mesh_counts(&self) -> (usize, usize, usize) {
+        self.tile_layout()
+            .iter()
+            .fold((0, 0, 0), |(t, v, i), tile| {
+                (
+                    t + 1,
+                    v + tile.width * tile.height,
+                    i + (tile.width - 1) * (tile.height - 1) * 2,
+                )
+            })
+    }
+
+    /// Source elevations mapped to black/white in the source texture
+    pub fn value_range(&self) -> (f32, f32) {
+        self.value_range
+    }
+
+    pub fn color_ramp(&self) -> ColorRamp {
+        self.color_ramp
+    }
+
+    pub fn set_color_ramp(&mut self, ramp: ColorRamp) {
+        self.color_ramp = ramp;
+        self.update_mode_texture();
+    }
+
+    pub fn ramp_range(&self) -> (f32, f32) {
+        self.ramp_range
+    }
+
+    /// Set the ramp range, it is kept when the heightmap is reloaded
+    pub fn set_ramp_range(&mut self, min: f32, max: f32) {
+        // Keep a valid range
+        let max = if max > min { max } else { min + f32::EPSILON };
+        self.ramp_range = (min, max);
+        self.ramp_range_set = true;
+        self.update_mode_texture();
+    }
+
+    pub fn slope_classes(&self) -> &SlopeClasses {
+        &self.slope_classes
+    }
+
+    pub fn set_slope_classes(&mut self, classes: SlopeClasses) {
+        self.slope_classes = classes;
+        self.update_mode_texture();
+    }
+
+    pub fn viewshed_params(&self) -> ViewshedParams {
+        self.viewshed
+    }
+
+    /// Set the viewshed parameters, changing only the flight height
+    /// recolors the viewshed without casting the rays again
+    pub fn set_viewshed_params(&mut self, params: ViewshedParams) {
+        if params.pilot != self.viewshed.pilot || params.eye_height != self.viewshed.eye_height {
+            self.bakes.viewshed_heights = None;
+        }
+        self.viewshed = params;
+        self.update_mode_texture();
+    }
+
+    /// Lowest height above the terrain, in source elevation units, a heli is
+    /// visible at from the pilot's eye for every pixel, at the real-world
+    /// pixel size and `meters_per_unit`
+    pub fn viewshed_heights(&self) -> Vec<f32> {
+        let scale = self.meters_per_unit();
+        let elevations: Vec<f32> = self.elevations.iter().map(|e| e * scale).collect();
+        let (psx, psy) = self.geo_transform.pixel_size_meters(self.height);
+        let pilot = (
+            self.viewshed.pilot.0.round().max(0.0) as usize,
+            self.viewshed.pilot.1.round().max(0.0) as usize,
+        );
+
+        viewshed::min_visible_heights(
+            &elevations,
+            self.width,
+            self.height,
+            (psx as f32, psy as f32),
+            pilot,
+            self.viewshed.eye_height * scale,
+        )
+        .into_iter()
+        .map(|h| h / scale)
+        .collect()
+    }
+
     pub fn terrain_mode(&self) -> TerrainMode {
         self.terrain_mode
     }
@@ -130,7 +581,9 @@ impl Heightmap {
         let point_size = 3.0;
         let line_width = 1.0;
         let default_texture = TextureManager::get_global_manager(|tm| tm.get_default());
+        let color_texture = color_texture_name();
         self.terrain_mode = mode;
+        self.update_mode_texture();
 
         for tile in &mut self.tiles {
             if let Some(ref mut node) = tile.mesh_node {
@@ -168,7 +621,7 @@ impl Heightmap {
                         node.set_surface_rendering_activation(true);
                         node.set_points_size(0.0);
                         node.set_lines_width(0.0);
-                        node.set_texture_with_name("heightmap");
+                        node.set_texture_with_name(color_texture);
                     }
                     TerrainMode::Alphamap => {
                         node.set_color(1.0, 1.0, 1.0);
@@ -178,11 +631,125 @@ impl Heightmap {
                         node.set_lines_width(0.0);
                         node.set_texture_with_name("alphamap_src");
                     }
+                    TerrainMode::Lightmap => {
+                        node.set_color(1.0, 1.0, 1.0);
+                        node.enable_backface_culling(true);
+                        node.set_surface_rendering_activation(true);
+                        node.set_points_size(0.0);
+                        node.set_lines_width(0.0);
+                        node.set_texture_with_name("lightmap");
+                    }
+                    TerrainMode::ColorRamp => {
+                        node.set_color(1.0, 1.0, 1.0);
+                        node.enable_backface_culling(true);
+                        node.set_surface_rendering_activation(true);
+                        node.set_points_size(0.0);
+                        node.set_lines_width(0.0);
+                        node.set_texture_with_name("color_ramp");
+                    }
+                    TerrainMode::Slope => {
+                        node.set_color(1.0, 1.0, 1.0);
+                        node.enable_backface_culling(true);
+                        node.set_surface_rendering_activation(true);
+                        node.set_points_size(0.0);
+                        node.set_lines_width(0.0);
+                        node.set_texture_with_name("slope");
+                    }
+                    TerrainMode::Viewshed => {
+                        node.set_color(1.0, 1.0, 1.0);
+                        node.enable_backface_culling(true);
+                        node.set_surface_rendering_activation(true);
+                        node.set_points_size(0.0);
+                        node.set_lines_width(0.0);
+                        node.set_texture_with_name("viewshed");
+                    }
                 }
             }
         }
     }
 
+    /// Name of the texture the terrain mode shows, the heightmap image for the
+    /// modes without one
+    pub fn mode_texture_name(&self) -> &'static str {
+        match self.terrain_mode {
+            TerrainMode::Wireframe | TerrainMode::Points | TerrainMode::Solid => "heightmap",
+            TerrainMode::Textured => color_texture_name(),
+            TerrainMode::Alphamap => "alphamap_src",
+            TerrainMode::Lightmap => "lightmap",
+            TerrainMode::ColorRamp => "color_ramp",
+            TerrainMode::Slope => "slope",
+            TerrainMode::Viewshed => "viewshed",
+        }
+    }
+
+    /// Regenerate the texture of the terrain mode, if it's derived from the
+    /// elevations
+    fn update_mode_texture(&mut self) {
+        if self.tiles.is_empty() {
+            return;
+        }
+        self.mode_texture_stale = false;
+
+        match self.terrain_mode {
+            TerrainMode::Lightmap => {
+                if self.bakes.lightmap.is_none() {
+                    let params = LightmapParams::default();
+                    self.bakes.lightmap =
+                        Some(self.bake_lightmap(lightmap::Kind::Combined, &params));
+                }
+                if let Some(img) = &self.bakes.lightmap {
+                    textures::set_texture_image(DynamicImage::ImageLuma8(img.clone()), "lightmap");
+                }
+            }
+            TerrainMode::ColorRamp => {
+                let img = self.color_ramp_image();
+                textures::set_texture_image(DynamicImage::ImageRgb8(img), "color_ramp");
+            }
+            TerrainMode::Slope => {
+                let img = self.slope_image();
+                textures::set_texture_image(DynamicImage::ImageRgb8(img), "slope");
+            }
+            TerrainMode::Viewshed => {
+                if self.bakes.hillshade.is_none() {
+                    let params = LightmapParams::default();
+                    self.bakes.hillshade =
+                        Some(self.bake_lightmap(lightmap::Kind::Hillshade, &params));
+                }
+                if self.bakes.viewshed_heights.is_none() {
+                    self.bakes.viewshed_heights = Some(self.viewshed_heights());
+                }
+                if let (Some(shading), Some(heights)) =
+                    (&self.bakes.hillshade, &self.bakes.viewshed_heights)
+                {
+                    let img = viewshed::overlay(heights, shading, self.viewshed.flight_height);
+                    textures::set_texture_image(DynamicImage::ImageRgb8(img), "viewshed");
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Source elevations colored by the color ramp over the ramp range
+    fn color_ramp_image(&self) -> RgbImage {
+        let (min, max) = self.ramp_range;
+        let range = if max > min { max - min } else { 1.0 };
+
+        ImageBuffer::from_fn(self.width as _, self.height as _, |x, y| {
+            let e = self.elevations[x as usize + y as usize * self.width];
+            Rgb(self.color_ramp.color((e - min) / range))
+        })
+    }
+
+    /// Slope angles colored by slope class
+    fn slope_image(&self) -> RgbImage {
+        let slopes = self.slope_degrees();
+
+        ImageBuffer::from_fn(self.width as _, self.height as _, |x, y| {
+            let slope = slopes[x as usize + y as usize * self.width];
+            Rgb(self.slope_classes.color(self.slope_classes.class(slope)))
+        })
+    }
+
     /// Add the generated mesh tiles to a window
     pub fn create_mesh_tile_scene_nodes(&mut self, win: &mut Window) {
         assert_ne!(
@@ -207,67 +774,457 @@ impl Heightmap {
     pub fn generate_mesh_tiles(&mut self, mm: &mut MeshManager) {
         assert_eq!(self.tiles.len(), 0, "Should only call this once");
 
-        // Split up the grid into TILE_SIZE x TILE_SIZE meshes
-        let num_tiles_x = self.width / TILE_SIZE;
-        let num_tiles_y = self.height / TILE_SIZE;
+        for tile in self.tile_layout() {
+            // Generate mesh from tile parameters
+            let mesh = self.generate_mesh(&tile);
+            let mesh = Rc::new(RefCell::new(mesh));
 
-        for ty in 0..num_tiles_y {
-            for tx in 0..num_tiles_x {
-                // Create tile meta data
-                let tile = Tile {
-                    name: format!("{} {}", tx, ty),
-                    start_x: (tx * TILE_SIZE),
-                    start_y: (ty * TILE_SIZE),
-                    width: TILE_SIZE,
-                    height: TILE_SIZE,
-                    mesh_node: None,
-                };
+            // Add mesh to the global mesh manager
+            mm.add(mesh.clone(), tile.name());
 
-                // Generate mesh from tile parameters
-                let mesh = self.generate_mesh(&tile);
-                let mesh = Rc::new(RefCell::new(mesh));
+            self.tiles.push(tile);
+        }
+    }
 
-                // Add mesh to the global mesh manager
-                mm.add(mesh.clone(), tile.name());
+    /// Tile meta data, without mesh nodes
+    fn tile_layout(&self) -> Vec<Tile> {
+        let mut tiles = Vec::new();
 
-                self.tiles.push(tile);
+        // Split up the grid into TILE_SIZE x TILE_SIZE meshes, tiles along
+        // the right/bottom edges are smaller when the dimensions are
+        // not a multiple of TILE_SIZE
+        for (ty, &(start_y, height)) in tile_spans(self.height).iter().enumerate() {
+            for (tx, &(start_x, width)) in tile_spans(self.width).iter().enumerate() {
+                tiles.push(Tile {
+                    name: format!("{} {}", tx, ty),
+                    start_x,
+                    start_y,
+                    width,
+                    height,
+                    mesh_node: None,
+                });
             }
         }
+
+        tiles
     }
 
     pub fn reload(&mut self, mm: &mut MeshManager) {
-        // Reload source image
-        let new_img = if let Ok(img) = image::open(&self.src_path) {
-            img
+        // Reload source file
+        let new_hmap = if let Some(layout) = self.raw_layout {
+            Heightmap::from_raw_file(&self.src_path, layout)
+        } else {
+            Heightmap::from_file(&self.src_path)
+        };
+        let new_hmap = if let Ok(hmap) = new_hmap {
+            hmap
         } else {
             println!(
-                "Failed to reload source heightmap image {}",
+                "Failed to reload source heightmap file {}",
                 self.src_path.display()
             );
             return;
         };
 
-        // TODO - WIP, not working yet
-        self.src_img = new_img;
+        if new_hmap.dimensions() != self.dimensions() {
+            println!("Heightmap dimensions changed, restart to reload");
+            return;
+        }
 
-        // Regenerate mesh tiles
-        for tile in &self.tiles {
-            if let Some(_) = mm.get(tile.name()) {
-                // Remove old mesh
-                mm.remove(tile.name());
+        self.elevations = new_hmap.elevations;
+        self.bakes = Bakes::default();
+        self.geo_transform = new_hmap.geo_transform;
+        self.value_range = new_hmap.value_range;
+        if !self.ramp_range_set {
+            self.ramp_range = new_hmap.ramp_range;
+        }
+        self.units_per_pixel = new_hmap.units_per_pixel;
+
+        self.update_mesh_tiles(mm);
 
-                // TODO - need to update the texture too
+        // Reset terrain mode
+        self.set_terrain_mode(self.terrain_mode);
+    }
 
-                let new_mesh = self.generate_mesh(tile);
-                let new_mesh = Rc::new(RefCell::new(new_mesh));
+    /// Elevation of pixel (x, y) in mesh units, before height scale/offset
+    fn mesh_elevation(&self, x: usize, y: usize) -> f32 {
+        (self.elevations[x + y * self.width] - self.value_range.0) / self.units_per_pixel
+    }
 
-                // Add mesh to the global mesh manager
-                mm.add(new_mesh.clone(), tile.name());
+    /// Apply a processing filter to the source elevations, call
+    /// `update_mesh_tiles` to regenerate the meshes
+    pub fn apply_filter(&mut self, filter: &Filter) {
+        self.bakes = Bakes::default();
+        filter::apply(
+            filter,
+            &mut self.elevations,
+            self.width,
+            self.height,
+            self.value_range,
+        );
+    }
+
+    /// Run the particle based hydraulic erosion simulation
+    pub fn erode_hydraulic(&mut self, params: &HydraulicParams, seed: u64) {
+        self.bakes = Bakes::default();
+        erosion::hydraulic(
+            &mut self.elevations,
+            self.width,
+            self.height,
+            self.value_range,
+            params,
+            seed,
+        );
+    }
+
+    /// Run the thermal erosion simulation, the talus angle is relative to
+    /// the terrain at the current height scale
+    pub fn erode_thermal(&mut self, params: &ThermalParams) {
+        self.bakes = Bakes::default();
+        erosion::thermal(
+            &mut self.elevations,
+            self.width,
+            self.height,
+            self.height_scale / self.units_per_pixel,
+            params,
+        );
+    }
+
+    /// Bake a normal map at `scale` times the heightmap resolution, at the
+    /// current height scale and real-world pixel size
+    pub fn bake_normal_map(&self, space: NormalSpace, scale: usize, flip_green: bool) -> RgbImage {
+        let scale = scale.max(1);
+        let (w, h) = (self.width * scale, self.height * scale);
+        let (sx, sy) = self.pixel_spacing();
+        let spacing = (sx / scale as f32, sy / scale as f32);
+
+        let src = self.displayed_elevations();
+        let e = |x: usize, y: usize| src[x + y * self.width];
+        let mut elevations = Vec::with_capacity(w * h);
+        for y in 0..h {
+            for x in 0..w {
+                // Bilinear, samples at the centers of the baked pixels
+                let sx = ((x as f32 + 0.5) / scale as f32 - 0.5).max(0.0);
+                let sy = ((y as f32 + 0.5) / scale as f32 - 0.5).max(0.0);
+                let (x0, y0) = (sx as usize, sy as usize);
+                let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+                let (u, v) = (sx - x0 as f32, sy - y0 as f32);
+                elevations.push(
+                    (e(x0, y0) * (1.0 - u) + e(x1, y0) * u) * (1.0 - v)
+                        + (e(x0, y1) * (1.0 - u) + e(x1, y1) * u) * v,
+                );
             }
         }
 
-        // Reset terrain mode
-        self.set_terrain_mode(self.terrain_mode);
+        normal_map::bake(&elevations, w, h, spacing, space, flip_green)
+    }
+
+    /// Bake a hillshade/ambient occlusion lightmap at the current height
+    /// scale and real-world pixel size
+    pub fn bake_lightmap(&self, kind: lightmap::Kind, params: &LightmapParams) -> GrayImage {
+        lightmap::bake(
+            &self.displayed_elevations(),
+            self.width,
+            self.height,
+            self.pixel_spacing(),
+            kind,
+            params,
+        )
+    }
+
+    /// Elevations in mesh units at the current height scale
+    fn displayed_elevations(&self) -> Vec<f32> {
+        let mut elevations = Vec::with_capacity(self.elevations.len());
+        for y in 0..self.height {
+            for x in 0..self.width {
+                elevations.push(self.mesh_elevation(x, y) * self.height_scale);
+            }
+        }
+        elevations
+    }
+
+    /// Real-world (x, y) pixel size in mesh units
+    fn pixel_spacing(&self) -> (f32, f32) {
+        let (psx, psy) = self.geo_transform.pixel_size_meters(self.height);
+        (
+            psx as f32 / self.units_per_pixel,
+            psy as f32 / self.units_per_pixel,
+        )
+    }
+
+    /// Flatten a region, in world coordinates, returns the bounds of the
+    /// modified pixels
+    pub fn flatten(&mut self, region: &Region, params: &FlattenParams) -> Option<Bounds> {
+        let geo = self.geo_transform;
+        self.flatten_pixels(&region.map(|x, y| geo.world_to_pixel(x, y)), params)
+    }
+
+    /// Flatten a region in pixel coordinates, the value range grows to
+    /// include a target outside of it
+    pub fn flatten_pixels(&mut self, region: &Region, params: &FlattenParams) -> Option<Bounds> {
+        self.bakes = Bakes::default();
+        let bounds = flatten::flatten(
+            &mut self.elevations,
+            self.width,
+            self.height,
+            region,
+            params,
+        )?;
+
+        let (x0, y0, x1, y1) = bounds;
+        for y in y0..=y1 {
+            for &e in &self.elevations[x0 + y * self.width..=x1 + y * self.width] {
+                self.value_range = (self.value_range.0.min(e), self.value_range.1.max(e));
+            }
+        }
+        Some(bounds)
+    }
+
+    /// Trace contour lines at every multiple of `interval`, offset by `base`,
+    /// in source elevation units
+    pub fn contours(&self, interval: f32, base: f32) -> Result<Vec<Contour>, String> {
+        contour::trace(&self.elevations, self.width, self.height, interval, base)
+    }
+
+    pub fn geo_transform(&self) -> &GeoTransform {
+        &self.geo_transform
+    }
+
+    /// Point in the scene at pixel coordinates and a source elevation, at
+    /// the current height scale and offset
+    pub fn pixel_to_mesh(&self, x: f32, y: f32, elevation: f32) -> Point3<f32> {
+        let mesh_y = (elevation - self.value_range.0) / self.units_per_pixel;
+        Point3::new(
+            (self.width - 1) as f32 / 2.0 - x,
+            mesh_y * self.height_scale + self.height_offset,
+            (self.height - 1) as f32 / 2.0 - y,
+        )
+    }
+
+    /// Height of the displayed terrain at a point (x, z) in the scene, at the
+    /// current height scale and offset, None outside of the terrain
+    pub fn elevation_at(&self, x: f32, z: f32, interp: Interpolation) -> Option<f32> {
+        let (px, py) = self.mesh_to_pixel(&Point3::new(x, 0.0, z));
+        if px < 0.0 || py < 0.0 || px > (self.width - 1) as f64 || py > (self.height - 1) as f64 {
+            return None;
+        }
+
+        let e = interp.sample(
+            &self.elevations,
+            self.width,
+            self.height,
+            px as f32,
+            py as f32,
+        );
+        let mesh_y = (e - self.value_range.0) / self.units_per_pixel;
+        Some(mesh_y * self.height_scale + self.height_offset)
+    }
+
+    /// Normal of the displayed terrain at a point (x, z) in the scene, from
+    /// central differences one mesh unit apart
+    pub fn normal_at(&self, x: f32, z: f32, interp: Interpolation) -> Option<Vector3<f32>> {
+        let h = |x: f32, z: f32| self.elevation_at(x, z, interp);
+        let center = h(x, z)?;
+
+        // One sided along the edges of the terrain
+        let diff = |before: Option<f32>, after: Option<f32>| match (before, after) {
+            (Some(b), Some(a)) => (a - b) / 2.0,
+            (Some(b), None) => center - b,
+            (None, Some(a)) => a - center,
+            (None, None) => 0.0,
+        };
+        let dx = diff(h(x - 1.0, z), h(x + 1.0, z));
+        let dz = diff(h(x, z - 1.0), h(x, z + 1.0));
+
+        Some(Vector3::new(-dx, 1.0, -dz).normalize())
+    }
+
+    /// Slope angle of the displayed terrain at a point (x, z) in the scene,
+    /// in degrees
+    pub fn slope_at(&self, x: f32, z: f32, interp: Interpolation) -> Option<f32> {
+        self.normal_at(x, z, interp)
+            .map(|n| n.y.clamp(-1.0, 1.0).acos().to_degrees())
+    }
+
+    /// Smallest and largest scene coordinates of the displayed terrain, over
+    /// the value range
+    pub fn mesh_bounds(&self) -> (Point3<f32>, Point3<f32>) {
+        let (low, high) = self.value_range;
+        let a = self.pixel_to_mesh(0.0, 0.0, low);
+        let b = self.pixel_to_mesh((self.width - 1) as f32, (self.height - 1) as f32, high);
+        (
+            Point3::new(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+            Point3::new(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+        )
+    }
+
+    /// First point where a ray from `origin` hits the displayed terrain
+    pub fn ray_intersection(
+        &self,
+        origin: &Point3<f32>,
+        dir: &Vector3<f32>,
+        interp: Interpolation,
+    ) -> Option<Point3<f32>> {
+        let dir = dir.normalize();
+        let below = |t: f32| {
+            let p = origin + dir * t;
+            self.elevation_at(p.x, p.z, interp)
+                .is_some_and(|e| p.y <= e)
+        };
+
+        // Only march the part of the ray inside the terrain bounds, rays
+        // into the sky miss them entirely
+        let (min, max) = self.mesh_bounds();
+        let (mut start, mut end) = (0.0f32, f32::INFINITY);
+        for i in 0..3 {
+            if dir[i] == 0.0 {
+                if origin[i] < min[i] || origin[i] > max[i] {
+                    return None;
+                }
+            } else {
+                let (a, b) = ((min[i] - origin[i]) / dir[i], (max[i] - origin[i]) / dir[i]);
+                start = start.max(a.min(b));
+                end = end.min(a.max(b));
+            }
+        }
+        if start > end {
+            return None;
+        }
+
+        // March in half pixel steps, then refine the crossing
+        let step = 0.5;
+        let mut t = start;
+        while t <= end + step {
+            if below(t) {
+                let (mut above_t, mut below_t) = ((t - step).max(start), t);
+                for _ in 0..16 {
+                    let mid = (above_t + below_t) / 2.0;
+                    if below(mid) {
+                        below_t = mid;
+                    } else {
+                        above_t = mid;
+                    }
+                }
+                let p = origin + dir * below_t;
+                return Some(Point3::new(p.x, self.elevation_at(p.x, p.z, interp)?, p.z));
+            }
+            t += step;
+        }
+        None
+    }
+
+    /// Elevation profile along a polyline of points in the scene, sampled at
+    /// least once per pixel
+    pub fn profile(&self, points: &[Point3<f32>], interp: Interpolation) -> Vec<ProfileSample> {
+        let (psx, psy) = self.geo_transform.pixel_size_meters(self.height);
+        let sample = |pixel: (f64, f64), distance: f64, vertex: bool| ProfileSample {
+            distance,
+            pixel,
+            world: self.geo_transform.pixel_to_world(pixel.0, pixel.1),
+            elevation: interp.sample(
+                &self.elevations,
+                self.width,
+                self.height,
+                pixel.0 as f32,
+                pixel.1 as f32,
+            ),
+            vertex,
+        };
+
+        let pixels: Vec<(f64, f64)> = points.iter().map(|p| self.mesh_to_pixel(p)).collect();
+        let mut samples = Vec::new();
+        if let Some(first) = pixels.first() {
+            samples.push(sample(*first, 0.0, true));
+        }
+
+        let mut distance = 0.0;
+        for w in pixels.windows(2) {
+            let ((x0, y0), (x1, y1)) = (w[0], w[1]);
+            let (dx, dy) = (x1 - x0, y1 - y0);
+            let length = ((dx * psx).powi(2) + (dy * psy).powi(2)).sqrt();
+            let steps = dx.abs().max(dy.abs()).ceil().max(1.0) as usize;
+
+            for s in 1..=steps {
+                let f = s as f64 / steps as f64;
+                samples.push(sample(
+                    (x0 + dx * f, y0 + dy * f),
+                    distance + length * f,
+                    s == steps,
+                ));
+            }
+            distance += length;
+        }
+        samples
+    }
+
+    /// Flat spots to land on, at the current height scale, `origin` is in
+    /// pixel coordinates
+    pub fn landing_spots(&self, params: &LandingParams, origin: (f64, f64)) -> Vec<LandingSpot> {
+        let (psx, psy) = self.geo_transform.pixel_size_meters(self.height);
+        landing::find(
+            &self.slope_degrees(),
+            &self.elevations,
+            self.width,
+            self.height,
+            (psx as f32, psy as f32),
+            origin,
+            params,
+        )
+    }
+
+    /// Pixel coordinates of a point in the scene
+    pub fn mesh_to_pixel(&self, p: &Point3<f32>) -> (f64, f64) {
+        // Inverse of the vertex placement in generate_mesh_vectors
+        let mx = f64::from(p.x) + (self.width - 1) as f64 / 2.0;
+        let mz = f64::from(p.z) + (self.height - 1) as f64 / 2.0;
+        ((self.width - 1) as f64 - mx, (self.height - 1) as f64 - mz)
+    }
+
+    /// Regenerate the mesh tiles and texture from the current elevations
+    ///
+    /// The meshes are updated in place, the scene nodes share them with the
+    /// mesh manager.
+    pub fn update_mesh_tiles(&mut self, mm: &mut MeshManager) {
+        self.update_mesh_tiles_in(mm, (0, 0, self.width - 1, self.height - 1));
+    }
+
+    /// Regenerate the mesh tiles covering the given pixel bounds, and the
+    /// texture
+    pub fn update_mesh_tiles_in(&mut self, mm: &mut MeshManager, bounds: Bounds) {
+        // Tiles are laid out mirrored, see generate_mesh_vectors, and the
+        // normals depend on the neighboring pixels
+        let min_x = (self.width - 1 - bounds.2).saturating_sub(1);
+        let max_x = self.width - 1 - bounds.0 + 1;
+        let min_y = (self.height - 1 - bounds.3).saturating_sub(1);
+        let max_y = self.height - 1 - bounds.1 + 1;
+
+        for tile in &self.tiles {
+            let overlaps = tile.start_x <= max_x
+                && tile.start_x + tile.width > min_x
+                && tile.start_y <= max_y
+                && tile.start_y + tile.height > min_y;
+            if !overlaps {
+                continue;
+            }
+            if let Some(mesh) = mm.get(tile.name()) {
+                self.update_mesh(tile, &mesh.borrow());
+            }
+        }
+
+        textures::set_texture_image(self.src_texture(), "heightmap");
+        self.update_mode_texture();
+    }
+
+    fn update_mesh(&self, tile: &Tile, mesh: &Mesh) {
+        let mut vertices: Vec<Point3<f32>> = vec![];
+        let mut normals: Vec<Vector3<f32>> = vec![];
+        let mut indices: Vec<Point3<u16>> = vec![];
+        let mut uvs: Vec<Point2<f32>> = vec![];
+
+        self.generate_mesh_vectors(tile, &mut vertices, &mut normals, &mut indices, &mut uvs);
+
+        *mesh.coords().write().unwrap().data_mut() = Some(vertices);
+        *mesh.normals().write().unwrap().data_mut() = Some(normals);
     }
 
     fn generate_mesh(&self, tile: &Tile) -> Mesh {
@@ -300,11 +1257,7 @@ impl Heightmap {
         for y in tile.start_y..(tile.start_y + tile.height) {
             for x in tile.start_x..(tile.start_x + tile.width) {
                 // Invert x/y to align uvs/vertices
-                let pixel = self.src_img.get_pixel(
-                    (self.src_img.width() - 1) - x as u32,
-                    (self.src_img.height() - 1) - y as u32,
-                );
-                let elevation: f32 = pixel.to_luma().data[0] as f32 / 255.0;
+                let elevation = self.mesh_elevation((self.width - 1) - x, (self.height - 1) - y);
 
                 let s = x as f32 / twidth;
                 let t = y as f32 / theight;
@@ -337,9 +1290,26 @@ impl Heightmap {
 
                 // Construct uv texture coordinates
                 uvs.push(Point2::new(1.0 - s, 1.0 - t));
+            }
+        }
 
-                // Push empty normal vector
-                normals.push(Vector3::new(0_f32, 0_f32, 0_f32));
+        // Normals of the whole terrain, so they match across tile edges and
+        // the slope analysis, the mesh is scaled to the height scale
+        let (x0, y0) = (
+            self.width - tile.start_x - tile.width,
+            self.height - tile.start_y - tile.height,
+        );
+        let bounds = (
+            x0,
+            y0,
+            self.width - 1 - tile.start_x,
+            self.height - 1 - tile.start_y,
+        );
+        let tile_normals = self.mesh_normals(bounds, 1.0);
+        for y in tile.start_y..(tile.start_y + tile.height) {
+            for x in tile.start_x..(tile.start_x + tile.width) {
+                let (ix, iy) = (self.width - 1 - x - x0, self.height - 1 - y - y0);
+                normals.push(tile_normals[ix + iy * tile.width]);
             }
         }
 
@@ -363,42 +1333,136 @@ impl Heightmap {
                 ));
             }
         }
+    }
+}
 
-        // Generate the normals for the nbo
-        for ibo_idx in (0..indices.len()).step_by(3) {
-            let v0 = vertices[indices[ibo_idx].x as usize];
-            let v1 = vertices[indices[ibo_idx].y as usize];
-            let v2 = vertices[indices[ibo_idx].z as usize];
-
-            let a = v1 - v0;
-            let b = v2 - v0;
-
-            let normal = normalize(&a.cross(&b));
+/// (start, length) of the tiles along an axis of `size` pixels, a single
+/// pixel left over at the end can't form any quads on its own so it's added
+/// to the last tile
+fn tile_spans(size: usize) -> Vec<(usize, usize)> {
+    let mut spans: Vec<(usize, usize)> = (0..size)
+        .step_by(TILE_SIZE)
+        .map(|start| (start, TILE_SIZE.min(size - start)))
+        .collect();
 
-            normals[indices[ibo_idx].x as usize] += normal;
-            normals[indices[ibo_idx].y as usize] += normal;
-            normals[indices[ibo_idx].z as usize] += normal;
+    if spans.len() > 1 && spans[spans.len() - 1].1 < 2 {
+        let (_, len) = spans.pop().unwrap();
+        if let Some(last) = spans.last_mut() {
+            last.1 += len;
         }
+    }
+    spans
+}
 
-        // Normalize the normals
-        for n in normals.iter_mut() {
-            *n = normalize(n);
-        }
+/// Drape the color texture when one is loaded, the heightmap otherwise
+fn color_texture_name() -> &'static str {
+    if TextureManager::get_global_manager(|tm| tm.get("texture")).is_some() {
+        "texture"
+    } else {
+        "heightmap"
+    }
+}
+
+fn check_dimensions(width: usize, height: usize) -> Result<(), Error> {
+    if width < 2 || height < 2 {
+        Err(Error::InvalidDimensions)
+    } else {
+        Ok(())
     }
 }
 
 impl From<std::io::Error> for Error {
-    fn from(e: std::io::Error) -> Error {
-        match e {
-            _ => Error::InvalidFile,
-        }
+    fn from(_: std::io::Error) -> Error {
+        Error::InvalidFile
     }
 }
 
 impl From<ImageError> for Error {
-    fn from(e: ImageError) -> Error {
+    fn from(_: ImageError) -> Error {
+        Error::FileNotSupported
+    }
+}
+
+impl From<TiffError> for Error {
+    fn from(e: TiffError) -> Error {
         match e {
+            TiffError::IoError(_) => Error::InvalidFile,
             _ => Error::FileNotSupported,
         }
     }
 }
+
+impl From<las::Error> for Error {
+    fn from(_: las::Error) -> Error {
+        Error::FileNotSupported
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn flat_heightmap() -> Heightmap {
+        let elevations = vec![0.5; 9 * 9];
+        Heightmap::from_normalized(Path::new("flat.png"), 9, 9, elevations).unwrap()
+    }
+
+    #[test]
+    fn ray_hits_the_terrain_from_above() {
+        let hmap = flat_heightmap();
+        let hit = hmap.ray_intersection(
+            &Point3::new(1.0, 20.0, 1.0),
+            &Vector3::new(0.0, -1.0, 0.0),
+            Interpolation::Bilinear,
+        );
+        let hit = hit.unwrap();
+        assert!((hit.y - 0.5 * hmap.height_scale()).abs() < 1e-3);
+        assert_eq!((hit.x, hit.z), (1.0, 1.0));
+    }
+
+    #[test]
+    fn ray_into_the_sky_misses() {
+        let hmap = flat_heightmap();
+        let origin = Point3::new(0.0, 20.0, 0.0);
+        for dir in &[Vector3::new(0.0, 1.0, 0.0), Vector3::new(1.0, 0.0, 0.0)] {
+            assert_eq!(
+                hmap.ray_intersection(&origin, dir, Interpolation::Bilinear),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn tile_spans_cover_the_axis() {
+        assert_eq!(tile_spans(TILE_SIZE), vec![(0, TILE_SIZE)]);
+        assert_eq!(
+            tile_spans(TILE_SIZE + 2),
+            vec![(0, TILE_SIZE), (TILE_SIZE, 2)]
+        );
+    }
+
+    #[test]
+    fn tile_spans_extend_last_tile_by_single_pixel() {
+        assert_eq!(tile_spans(TILE_SIZE + 1), vec![(0, TILE_SIZE + 1)]);
+        assert_eq!(
+            tile_spans(2 * TILE_SIZE + 1),
+            vec![(0, TILE_SIZE), (TILE_SIZE, TILE_SIZE + 1)]
+        );
+    }
+
+    #[test]
+    fn flatten_grows_the_value_range_to_the_target() {
+        let mut hmap = flat_heightmap();
+        let params = FlattenParams {
+            target: Some(2.0),
+            margin: 0.0,
+        };
+        let region = Region::Rect((2.0, 2.0), (4.0, 4.0));
+        assert!(hmap.flatten_pixels(&region, &params).is_some());
+
+        assert_eq!(hmap.value_range(), (0.0, 2.0));
+        // Exported at full value instead of clipped
+        assert_eq!(hmap.normalized_elevation(3 + 3 * 9), 1.0);
+        assert_eq!(hmap.normalized_elevation(0), 0.25);
+    }
+}
@@ -0,0 +1,96 @@
+use crate::geo_transform::GeoTransform;
+use crate::heightmap::Error;
+use crate::raster::Raster;
+use std::fs;
+use std::path::Path;
+
+/// Void value used by SRTM tiles
+const HGT_NODATA: i16 = -32768;
+
+/// Read an SRTM (.hgt) tile
+///
+/// Tiles are square grids of big-endian i16 meters, 1201 (3 arc-second)
+/// or 3601 (1 arc-second) samples wide. The location is taken from the file
+/// name, which names the south-west corner, i.e. `N37W122.hgt`.
+pub fn read_file(file_path: &Path) -> Result<Raster, Error> {
+    let bytes = fs::read(file_path)?;
+
+    let samples = bytes.len() / 2;
+    let size = (samples as f64).sqrt() as usize;
+    if bytes.len() % 2 != 0 || size * size != samples || size < 2 {
+        return Err(Error::InvalidFile);
+    }
+
+    let data = bytes
+        .chunks(2)
+        .map(|b| f32::from(i16::from_be_bytes([b[0], b[1]])))
+        .collect();
+
+    let (lat, lon) = file_path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .and_then(parse_tile_name)
+        .ok_or(Error::InvalidFile)?;
+
+    // Samples are on the tile edges, the first/last rows and columns
+    // overlap the adjacent tiles
+    let cell_size = 1.0 / (size - 1) as f64;
+
+    Ok(Raster {
+        width: size,
+        height: size,
+        data,
+        geo_transform: GeoTransform {
+            origin_x: lon - cell_size / 2.0,
+            origin_y: lat + 1.0 + cell_size / 2.0,
+            pixel_width: cell_size,
+            pixel_height: -cell_size,
+            geographic: true,
+        },
+        nodata: Some(f32::from(HGT_NODATA)),
+        bit_depth: Some(16),
+    })
+}
+
+/// Parse the south-west corner (lat, lon) from a tile name like `N37W122`
+fn parse_tile_name(name: &str) -> Option<(f64, f64)> {
+    let name = name.to_uppercase();
+    if name.len() < 7 || !name.is_ascii() {
+        return None;
+    }
+
+    let lat: f64 = name[1..3].parse().ok()?;
+    let lon: f64 = name[4..7].parse().ok()?;
+
+    let lat = match &name[0..1] {
+        "N" => lat,
+        "S" => -lat,
+        _ => return None,
+    };
+    let lon = match &name[3..4] {
+        "E" => lon,
+        "W" => -lon,
+        _ => return None,
+    };
+
+    Some((lat, lon))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tile_names() {
+        assert_eq!(parse_tile_name("N37W122"), Some((37.0, -122.0)));
+        assert_eq!(parse_tile_name("s12e004"), Some((-12.0, 4.0)));
+    }
+
+    #[test]
+    fn rejects_invalid_tile_names() {
+        assert_eq!(parse_tile_name("heightmap"), None);
+        assert_eq!(parse_tile_name("N37W12"), None);
+        assert_eq!(parse_tile_name("X37W122"), None);
+        assert_eq!(parse_tile_name("N37Q122"), None);
+    }
+}
@@ -0,0 +1,204 @@
+use crate::alphamap::{Alphamap, ChannelCoverage};
+use crate::heightmap::{Heightmap, TILE_SIZE};
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+/// Below this many distinct levels the heightmap is likely 8 bit and shows
+/// terracing
+const TERRACING_LEVELS: usize = 256;
+
+/// Width of the widest histogram bar, in characters
+const HISTOGRAM_WIDTH: usize = 50;
+
+/// Statistics of a heightmap and its alphamap
+pub struct Info {
+    pub width: usize,
+    pub height: usize,
+    pub bit_depth: Option<u8>,
+    pub min: f32,
+    pub max: f32,
+    pub mean: f32,
+    /// Pixel counts of even bins over [min, max]
+    pub histogram: Vec<usize>,
+    pub distinct_levels: usize,
+    pub tiles: usize,
+    pub vertices: usize,
+    pub triangles: usize,
+    pub alphamap: Vec<ChannelCoverage>,
+}
+
+impl Info {
+    pub fn new(hmap: &Heightmap, amap: Option<&Alphamap>, bins: usize) -> Self {
+        let (width, height) = hmap.dimensions();
+        let elevations = hmap.elevations();
+
+        let (min, max, sum) = elevations
+            .iter()
+            .fold((f32::MAX, f32::MIN, 0.0), |(min, max, sum), e| {
+                (min.min(*e), max.max(*e), sum + f64::from(*e))
+            });
+
+        let bins = bins.max(1);
+        let mut histogram = vec![0; bins];
+        let range = if max > min { max - min } else { 1.0 };
+        for e in elevations {
+            let bin = ((e - min) / range * bins as f32) as usize;
+            histogram[bin.min(bins - 1)] += 1;
+        }
+
+        let distinct_levels = elevations
+            .iter()
+            .map(|e| e.to_bits())
+            .collect::<HashSet<u32>>()
+            .len();
+
+        let (tiles, vertices, triangles) = hmap.mesh_counts();
+
+        Self {
+            width,
+            height,
+            bit_depth: hmap.bit_depth(),
+            min,
+            max,
+            mean: (sum / elevations.len() as f64) as f32,
+            histogram,
+            distinct_levels,
+            tiles,
+            vertices,
+            triangles,
+            alphamap: amap.map(|a| a.channel_coverage()).unwrap_or_default(),
+        }
+    }
+
+    pub fn terracing(&self) -> bool {
+        self.distinct_levels <= TERRACING_LEVELS
+    }
+
+    pub fn print(&self) {
+        println!("Dimensions:      {}x{}", self.width, self.height);
+        match self.bit_depth {
+            Some(bits) => println!("Bit depth:       {}", bits),
+            None => println!("Bit depth:       n/a"),
+        }
+        println!("Min elevation:   {}", self.min);
+        println!("Max elevation:   {}", self.max);
+        println!("Mean elevation:  {}", self.mean);
+        println!(
+            "Distinct levels: {}{}",
+            self.distinct_levels,
+            if self.terracing() {
+                " (terracing likely, see the terrace filter)"
+            } else {
+                ""
+            }
+        );
+        println!(
+            "Mesh:            {} tiles of {}x{}, {} vertices, {} triangles",
+            self.tiles, TILE_SIZE, TILE_SIZE, self.vertices, self.triangles
+        );
+
+        if !self.alphamap.is_empty() {
+            println!("Alphamap:");
+            for c in &self.alphamap {
+                println!(
+                    "  {:<6} mean {:>5.1}%, painted {:>5.1}%",
+                    c.name,
+                    c.mean * 100.0,
+                    c.painted * 100.0
+                );
+            }
+        }
+
+        println!("Histogram:");
+        let bins = self.histogram.len();
+        let largest = self.histogram.iter().cloned().max().unwrap_or(0).max(1);
+        for (i, count) in self.histogram.iter().enumerate() {
+            let low = self.min + (self.max - self.min) * i as f32 / bins as f32;
+            let bar = count * HISTOGRAM_WIDTH / largest;
+            println!("  {:>12.3} {:>9} {}", low, count, "#".repeat(bar));
+        }
+    }
+
+    pub fn write_json<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let bit_depth = self
+            .bit_depth
+            .map_or_else(|| "null".to_string(), |b| b.to_string());
+        let histogram: Vec<String> = self.histogram.iter().map(|c| c.to_string()).collect();
+        let alphamap: Vec<String> = self
+            .alphamap
+            .iter()
+            .map(|c| {
+                format!(
+                    r#"{{"channel":"{}","mean":{},"painted":{}}}"#,
+                    c.name, c.mean, c.painted
+                )
+            })
+            .collect();
+
+        writeln!(w, "{{")?;
+        writeln!(w, r#"  "width": {},"#, self.width)?;
+        writeln!(w, r#"  "height": {},"#, self.height)?;
+        writeln!(w, r#"  "bit_depth": {},"#, bit_depth)?;
+        writeln!(w, r#"  "min": {},"#, self.min)?;
+        writeln!(w, r#"  "max": {},"#, self.max)?;
+        writeln!(w, r#"  "mean": {},"#, self.mean)?;
+        writeln!(w, r#"  "histogram": [{}],"#, histogram.join(","))?;
+        writeln!(w, r#"  "distinct_levels": {},"#, self.distinct_levels)?;
+        writeln!(w, r#"  "terracing": {},"#, self.terracing())?;
+        writeln!(w, r#"  "tile_size": {},"#, TILE_SIZE)?;
+        writeln!(w, r#"  "tiles": {},"#, self.tiles)?;
+        writeln!(w, r#"  "vertices": {},"#, self.vertices)?;
+        writeln!(w, r#"  "triangles": {},"#, self.triangles)?;
+        writeln!(w, r#"  "alphamap": [{}]"#, alphamap.join(","))?;
+        writeln!(w, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    /// 4x4 heightmap with four rows of 0, 0.25, 0.5 and 1
+    fn rows() -> Heightmap {
+        let elevations = [0.0, 0.25, 0.5, 1.0]
+            .iter()
+            .flat_map(|e| vec![*e; 4])
+            .collect();
+        Heightmap::from_normalized(Path::new("rows.png"), 4, 4, elevations).unwrap()
+    }
+
+    #[test]
+    fn elevation_statistics_and_histogram() {
+        let hmap = rows();
+        let info = Info::new(&hmap, None, 4);
+
+        assert_eq!((info.width, info.height), (4, 4));
+        assert_eq!((info.min, info.max), (0.0, 1.0));
+        assert_eq!(info.mean, 0.4375);
+        // The maximum falls into the last bin
+        assert_eq!(info.histogram, vec![4, 4, 4, 4]);
+        assert_eq!(Info::new(&hmap, None, 2).histogram, vec![8, 8]);
+        assert_eq!(info.distinct_levels, 4);
+        assert!(info.terracing());
+        assert!(info.alphamap.is_empty());
+    }
+
+    #[test]
+    fn alphamap_coverage() {
+        let hmap = rows();
+        let amap = Alphamap::from_png_file(None::<&Path>, 4, 4).unwrap();
+        let info = Info::new(&hmap, Some(&amap), 4);
+
+        // The default alphamap paints red only
+        let coverage: Vec<(&str, f32, f32)> = info
+            .alphamap
+            .iter()
+            .map(|c| (c.name, c.mean, c.painted))
+            .collect();
+        assert_eq!(
+            coverage,
+            vec![("red", 1.0, 1.0), ("green", 0.0, 0.0), ("blue", 0.0, 0.0)]
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use std::str::FromStr;
+
+/// How grids are sampled between pixel centers
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Interpolation {
+    Bilinear,
+    /// Catmull-Rom, smooth but may overshoot next to sharp changes
+    Bicubic,
+}
+
+impl Interpolation {
+    /// Sample a width x height grid at pixel coordinates, pixel centers are
+    /// at whole numbers and samples outside the grid are clamped to its edges
+    pub fn sample(self, data: &[f32], width: usize, height: usize, x: f32, y: f32) -> f32 {
+        let x = x.max(0.0).min((width - 1) as f32);
+        let y = y.max(0.0).min((height - 1) as f32);
+        let (x0, y0) = (x.floor() as isize, y.floor() as isize);
+        let (u, v) = (x - x0 as f32, y - y0 as f32);
+
+        let e = |x: isize, y: isize| {
+            let x = x.max(0).min(width as isize - 1) as usize;
+            let y = y.max(0).min(height as isize - 1) as usize;
+            data[x + y * width]
+        };
+
+        match self {
+            Interpolation::Bilinear => {
+                let top = e(x0, y0) * (1.0 - u) + e(x0 + 1, y0) * u;
+                let bottom = e(x0, y0 + 1) * (1.0 - u) + e(x0 + 1, y0 + 1) * u;
+                top * (1.0 - v) + bottom * v
+            }
+            Interpolation::Bicubic => {
+                let row =
+                    |y: isize| catmull_rom(e(x0 - 1, y), e(x0, y), e(x0 + 1, y), e(x0 + 2, y), u);
+                catmull_rom(row(y0 - 1), row(y0), row(y0 + 1), row(y0 + 2), v)
+            }
+        }
+    }
+}
+
+/// Cubic through p1 at t = 0 and p2 at t = 1
+fn catmull_rom(p0: f32, p1: f32, p2: f32, p3: f32, t: f32) -> f32 {
+    let a = -0.5 * p0 + 1.5 * p1 - 1.5 * p2 + 0.5 * p3;
+    let b = p0 - 2.5 * p1 + 2.0 * p2 - 0.5 * p3;
+    let c = -0.5 * p0 + 0.5 * p2;
+    ((a * t + b) * t + c) * t + p1
+}
+
+impl FromStr for Interpolation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "bilinear" => Ok(Interpolation::Bilinear),
+            "bicubic" => Ok(Interpolation::Bicubic),
+            _ => Err(format!(
+                "Invalid interpolation '{}', use bilinear or bicubic",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 3x2 grid, rows 0 1 2 and 3 4 5
+    const GRID: [f32; 6] = [0.0, 1.0, 2.0, 3.0, 4.0, 5.0];
+
+    #[test]
+    fn samples_pixel_centers_exactly() {
+        for interpolation in &[Interpolation::Bilinear, Interpolation::Bicubic] {
+            for y in 0..2 {
+                for x in 0..3 {
+                    let e = interpolation.sample(&GRID, 3, 2, x as f32, y as f32);
+                    assert_eq!(e, GRID[x + y * 3]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn bilinear_between_pixel_centers() {
+        let sample = |x, y| Interpolation::Bilinear.sample(&GRID, 3, 2, x, y);
+        assert_eq!(sample(0.5, 0.0), 0.5);
+        assert_eq!(sample(1.0, 0.5), 2.5);
+        assert_eq!(sample(1.5, 0.5), 3.0);
+    }
+
+    #[test]
+    fn clamps_to_the_edges() {
+        for interpolation in &[Interpolation::Bilinear, Interpolation::Bicubic] {
+            let sample = |x, y| interpolation.sample(&GRID, 3, 2, x, y);
+            assert_eq!(sample(-1.0, -1.0), 0.0);
+            assert_eq!(sample(2.0, 1.0), 5.0);
+            assert_eq!(sample(10.0, 0.0), 2.0);
+            assert_eq!(sample(0.0, 10.0), 3.0);
+        }
+    }
+
+    #[test]
+    fn catmull_rom_passes_through_the_inner_points() {
+        assert_eq!(catmull_rom(5.0, 1.0, 2.0, -3.0, 0.0), 1.0);
+        assert_eq!(catmull_rom(5.0, 1.0, 2.0, -3.0, 1.0), 2.0);
+        // Linear data stays linear
+        assert_eq!(catmull_rom(0.0, 1.0, 2.0, 3.0, 0.5), 1.5);
+    }
+
+    #[test]
+    fn parses_names() {
+        assert_eq!("Bicubic".parse(), Ok(Interpolation::Bicubic));
+        assert_eq!("bilinear".parse(), Ok(Interpolation::Bilinear));
+        assert!("nearest".parse::<Interpolation>().is_err());
+    }
+}
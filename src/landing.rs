@@ -0,0 +1,335 @@
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LandingParams {
+    /// Steepest slope in degrees
+    pub max_slope: f32,
+    /// Largest deviation of a pixel from the mean of its neighbors, in
+    /// source elevation units
+    pub max_roughness: f32,
+    /// Smallest side of a square spot, in meters
+    pub min_size: f32,
+    /// Number of spots kept after ranking
+    pub count: usize,
+    pub ranking: Ranking,
+}
+
+impl LandingParams {
+    /// Defaults for a heightmap, the roughness threshold is relative to its
+    /// elevation range
+    pub fn new(value_range: (f32, f32)) -> Self {
+        Self {
+            max_slope: 5.0,
+            max_roughness: (value_range.1 - value_range.0) / 400.0,
+            min_size: 10.0,
+            count: 10,
+            ranking: Ranking::Size,
+        }
+    }
+}
+
+/// How the spots are ordered, best first
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Ranking {
+    /// Largest spots first
+    Size,
+    /// Lowest mean slope first
+    Flatness,
+    /// Closest to the origin first
+    Distance,
+}
+
+/// Square area flat enough to land on
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LandingSpot {
+    /// Pixel coordinates of the center
+    pub center: (usize, usize),
+    /// Half the side of the square, in pixels
+    pub half_size: usize,
+    /// Side of the square in meters
+    pub size: f32,
+    /// Mean slope over the square in degrees
+    pub mean_slope: f32,
+    /// Distance of the center from the origin in meters
+    pub distance: f32,
+}
+
+/// Find non-overlapping square spots in a width x height grid where every
+/// pixel is under the slope and roughness thresholds
+///
+/// `slopes` are in degrees, `elevations` in source units, `pixel_size` in
+/// meters and `origin` in pixel coordinates.
+pub fn find(
+    slopes: &[f32],
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    pixel_size: (f32, f32),
+    origin: (f64, f64),
+    params: &LandingParams,
+) -> Vec<LandingSpot> {
+    let flat: Vec<bool> = (0..width * height)
+        .map(|i| {
+            slopes[i] <= params.max_slope
+                && roughness(elevations, width, height, i) <= params.max_roughness
+        })
+        .collect();
+    let dist = flat_distance(&flat, width, height);
+
+    // Largest squares first, each claims the area any later, no larger, square
+    // would have to be centered in to overlap it
+    let pixel_meters = (pixel_size.0 + pixel_size.1) / 2.0;
+    let min_half = ((params.min_size / pixel_meters - 1.0) / 2.0)
+        .ceil()
+        .max(0.0) as usize;
+    let origin_distance = |i: usize| {
+        let (dx, dy) = (
+            ((i % width) as f64 - origin.0) * f64::from(pixel_size.0),
+            ((i / width) as f64 - origin.1) * f64::from(pixel_size.1),
+        );
+        dx * dx + dy * dy
+    };
+    let mut candidates: Vec<usize> = (0..width * height)
+        .filter(|i| flat[*i] && dist[*i] > min_half)
+        .collect();
+    // Of equally large squares the one closest to the origin claims its area
+    candidates.sort_by(|a, b| {
+        dist[*b]
+            .cmp(&dist[*a])
+            .then_with(|| cmp_f64(origin_distance(*a), origin_distance(*b)))
+    });
+
+    let mut claimed = vec![false; width * height];
+    let mut spots = Vec::new();
+    for i in candidates {
+        if claimed[i] {
+            continue;
+        }
+        let (cx, cy) = (i % width, i / width);
+        let half_size = dist[i] - 1;
+
+        let claim = 2 * half_size;
+        for y in cy.saturating_sub(claim)..=(cy + claim).min(height - 1) {
+            for x in cx.saturating_sub(claim)..=(cx + claim).min(width - 1) {
+                claimed[x + y * width] = true;
+            }
+        }
+
+        let mut slope_sum = 0.0;
+        for y in cy - half_size..=cy + half_size {
+            for x in cx - half_size..=cx + half_size {
+                slope_sum += slopes[x + y * width];
+            }
+        }
+        let side = 2 * half_size + 1;
+        let (dx, dy) = (
+            (cx as f64 - origin.0) as f32 * pixel_size.0,
+            (cy as f64 - origin.1) as f32 * pixel_size.1,
+        );
+
+        spots.push(LandingSpot {
+            center: (cx, cy),
+            half_size,
+            size: side as f32 * pixel_meters,
+            mean_slope: slope_sum / (side * side) as f32,
+            distance: (dx * dx + dy * dy).sqrt(),
+        });
+    }
+
+    // Ties are broken by size, then distance, so the nearest of the largest
+    // flat spots wins
+    let size = |a: &LandingSpot, b: &LandingSpot| cmp_f64(b.size.into(), a.size.into());
+    let flatness =
+        |a: &LandingSpot, b: &LandingSpot| cmp_f64(a.mean_slope.into(), b.mean_slope.into());
+    let distance = |a: &LandingSpot, b: &LandingSpot| cmp_f64(a.distance.into(), b.distance.into());
+    spots.sort_by(|a, b| match params.ranking {
+        Ranking::Size => size(a, b)
+            .then_with(|| distance(a, b))
+            .then_with(|| flatness(a, b)),
+        Ranking::Flatness => flatness(a, b)
+            .then_with(|| size(a, b))
+            .then_with(|| distance(a, b)),
+        Ranking::Distance => distance(a, b)
+            .then_with(|| size(a, b))
+            .then_with(|| flatness(a, b)),
+    });
+    spots.truncate(params.count);
+    spots
+}
+
+fn cmp_f64(a: f64, b: f64) -> Ordering {
+    a.partial_cmp(&b).unwrap_or(Ordering::Equal)
+}
+
+/// Absolute difference between a pixel and the mean of its 4 neighbors
+fn roughness(elevations: &[f32], width: usize, height: usize, i: usize) -> f32 {
+    let (x, y) = (i % width, i / width);
+    let e = |x: usize, y: usize| elevations[x + y * width];
+    let mean = (e(x.saturating_sub(1), y)
+        + e((x + 1).min(width - 1), y)
+        + e(x, y.saturating_sub(1))
+        + e(x, (y + 1).min(height - 1)))
+        / 4.0;
+    (elevations[i] - mean).abs()
+}
+
+/// Chessboard distance of every pixel to the closest pixel that isn't flat,
+/// or the edge of the grid, 0 for pixels that aren't flat
+fn flat_distance(flat: &[bool], width: usize, height: usize) -> Vec<usize> {
+    let mut dist: Vec<usize> = flat
+        .iter()
+        .map(|f| if *f { usize::MAX } else { 0 })
+        .collect();
+
+    // Outside of the grid is at distance 0
+    let get = |dist: &[usize], x: isize, y: isize| {
+        if x < 0 || y < 0 || x >= width as isize || y >= height as isize {
+            0
+        } else {
+            dist[x as usize + y as usize * width]
+        }
+    };
+
+    for y in 0..height as isize {
+        for x in 0..width as isize {
+            let i = x as usize + y as usize * width;
+            if dist[i] == 0 {
+                continue;
+            }
+            let n = [(-1, -1), (0, -1), (1, -1), (-1, 0)]
+                .iter()
+                .map(|(dx, dy)| get(&dist, x + dx, y + dy))
+                .min()
+                .unwrap_or(0);
+            dist[i] = dist[i].min(n + 1);
+        }
+    }
+    for y in (0..height as isize).rev() {
+        for x in (0..width as isize).rev() {
+            let i = x as usize + y as usize * width;
+            if dist[i] == 0 {
+                continue;
+            }
+            let n = [(1, 1), (0, 1), (-1, 1), (1, 0)]
+                .iter()
+                .map(|(dx, dy)| get(&dist, x + dx, y + dy))
+                .min()
+                .unwrap_or(0);
+            dist[i] = dist[i].min(n + 1);
+        }
+    }
+    dist
+}
+
+impl FromStr for Ranking {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "size" => Ok(Ranking::Size),
+            "flatness" => Ok(Ranking::Flatness),
+            "distance" => Ok(Ranking::Distance),
+            _ => Err(format!(
+                "Invalid ranking '{}', use size, flatness or distance",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(ranking: Ranking) -> LandingParams {
+        LandingParams {
+            max_slope: 5.0,
+            max_roughness: 0.1,
+            min_size: 1.0,
+            count: 100,
+            ranking,
+        }
+    }
+
+    #[test]
+    fn flat_distance_to_edges_and_obstacles() {
+        #[rustfmt::skip]
+        let flat = [
+            true, true, true, true, true,
+            true, true, true, true, true,
+            true, true, true, true, true,
+            true, true, true, true, false,
+        ];
+        #[rustfmt::skip]
+        let expected = vec![
+            1, 1, 1, 1, 1,
+            1, 2, 2, 2, 1,
+            1, 2, 2, 1, 1,
+            1, 1, 1, 1, 0,
+        ];
+        assert_eq!(flat_distance(&flat, 5, 4), expected);
+    }
+
+    #[test]
+    fn spots_do_not_overlap() {
+        // Flat everywhere except for a scattering of steep pixels
+        let (w, h) = (40, 30);
+        let slopes: Vec<f32> = (0..w * h)
+            .map(|i| if (i * 7919) % 23 == 0 { 30.0 } else { 0.0 })
+            .collect();
+        let elevations = vec![0.0; w * h];
+        let spots = find(
+            &slopes,
+            &elevations,
+            w,
+            h,
+            (1.0, 1.0),
+            (0.0, 0.0),
+            &params(Ranking::Size),
+        );
+
+        assert!(spots.len() > 1);
+        for (i, a) in spots.iter().enumerate() {
+            for b in &spots[i + 1..] {
+                let reach = (a.half_size + b.half_size) as isize;
+                let dx = (a.center.0 as isize - b.center.0 as isize).abs();
+                let dy = (a.center.1 as isize - b.center.1 as isize).abs();
+                assert!(dx > reach || dy > reach, "{:?} overlaps {:?}", a, b);
+            }
+        }
+        // Every pixel of every spot is flat
+        for spot in &spots {
+            let (cx, cy, r) = (spot.center.0, spot.center.1, spot.half_size);
+            for y in cy - r..=cy + r {
+                for x in cx - r..=cx + r {
+                    assert_eq!(slopes[x + y * w], 0.0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn nearest_of_equal_spots_wins() {
+        // Two 5x5 flat squares separated by a steep column
+        let (w, h) = (11, 5);
+        let slopes: Vec<f32> = (0..w * h)
+            .map(|i| if i % w == 5 { 30.0 } else { 0.0 })
+            .collect();
+        let elevations = vec![0.0; w * h];
+        let find_from = |origin| {
+            find(
+                &slopes,
+                &elevations,
+                w,
+                h,
+                (1.0, 1.0),
+                origin,
+                &params(Ranking::Size),
+            )
+        };
+
+        assert_eq!(find_from((10.0, 2.0))[0].center, (8, 2));
+        assert_eq!(find_from((0.0, 2.0))[0].center, (2, 2));
+    }
+}
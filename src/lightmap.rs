@@ -0,0 +1,219 @@
+use image::{GrayImage, ImageBuffer, Luma};
+use std::f32;
+use std::str::FromStr;
+
+/// Which lighting is baked
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Kind {
+    Hillshade,
+    AmbientOcclusion,
+    /// Hillshade multiplied by ambient occlusion
+    Combined,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LightmapParams {
+    /// Sun direction in degrees clockwise from north, the top of the
+    /// heightmap
+    pub sun_azimuth: f32,
+    /// Sun angle above the horizon in degrees
+    pub sun_elevation: f32,
+    /// Number of directions the ambient occlusion horizon is searched in
+    pub ao_directions: usize,
+    /// Distance, in pixels, the ambient occlusion horizon is searched to
+    pub ao_radius: f32,
+}
+
+impl Default for LightmapParams {
+    fn default() -> Self {
+        Self {
+            sun_azimuth: 315.0,
+            sun_elevation: 45.0,
+            ao_directions: 16,
+            ao_radius: 32.0,
+        }
+    }
+}
+
+/// Bake a lightmap from a width x height grid of elevations, elevations and
+/// the (x, y) pixel `spacing` are in the same units
+pub fn bake(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    spacing: (f32, f32),
+    kind: Kind,
+    params: &LightmapParams,
+) -> GrayImage {
+    let light = match kind {
+        Kind::Hillshade => hillshade(elevations, width, height, spacing, params),
+        Kind::AmbientOcclusion => ambient_occlusion(elevations, width, height, spacing, params),
+        Kind::Combined => {
+            let ao = ambient_occlusion(elevations, width, height, spacing, params);
+            hillshade(elevations, width, height, spacing, params)
+                .iter()
+                .zip(ao.iter())
+                .map(|(h, a)| h * a)
+                .collect()
+        }
+    };
+
+    ImageBuffer::from_fn(width as _, height as _, |x, y| {
+        let v = light[x as usize + y as usize * width];
+        Luma([(v.clamp(0.0, 1.0) * 255.0).round() as u8])
+    })
+}
+
+/// Lambertian shading from the sun direction, in [0, 1]
+pub fn hillshade(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    spacing: (f32, f32),
+    params: &LightmapParams,
+) -> Vec<f32> {
+    // Image frame, x east, y north, z up
+    let (az, el) = (
+        params.sun_azimuth.to_radians(),
+        params.sun_elevation.to_radians(),
+    );
+    let sun = (az.sin() * el.cos(), az.cos() * el.cos(), el.sin());
+
+    let e = |x: usize, y: usize| elevations[x + y * width];
+    let mut shade = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let (x0, x1) = (x.saturating_sub(1), (x + 1).min(width - 1));
+            let (y0, y1) = (y.saturating_sub(1), (y + 1).min(height - 1));
+            let dx = (e(x1, y) - e(x0, y)) / ((x1 - x0).max(1) as f32 * spacing.0);
+            // Rows run north to south
+            let dy = (e(x, y0) - e(x, y1)) / ((y1 - y0).max(1) as f32 * spacing.1);
+
+            let len = (dx * dx + dy * dy + 1.0).sqrt();
+            let n_dot_l = (-dx * sun.0 - dy * sun.1 + sun.2) / len;
+            shade.push(n_dot_l.max(0.0));
+        }
+    }
+    shade
+}
+
+/// Horizon based ambient occlusion, the fraction of the sky visible above
+/// the horizon in each direction, in [0, 1]
+pub fn ambient_occlusion(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    spacing: (f32, f32),
+    params: &LightmapParams,
+) -> Vec<f32> {
+    let directions: Vec<(f32, f32)> = (0..params.ao_directions.max(1))
+        .map(|i| {
+            let a = i as f32 / params.ao_directions.max(1) as f32 * 2.0 * f32::consts::PI;
+            (a.cos(), a.sin())
+        })
+        .collect();
+
+    // Sample distances grow further out, nearby terrain matters most
+    let mut distances = Vec::new();
+    let mut d = 1.0;
+    while d <= params.ao_radius {
+        distances.push(d);
+        d = (d + 1.0).max(d * 1.2);
+    }
+
+    let mut ao = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for x in 0..width {
+            let h0 = elevations[x + y * width];
+            let mut occlusion = 0.0;
+
+            for (dx, dy) in &directions {
+                let mut max_tan: f32 = 0.0;
+                for d in &distances {
+                    let sx = (x as f32 + dx * d).round();
+                    let sy = (y as f32 + dy * d).round();
+                    if sx < 0.0 || sy < 0.0 || sx >= width as f32 || sy >= height as f32 {
+                        break;
+                    }
+                    let rise = elevations[sx as usize + sy as usize * width] - h0;
+                    let run = ((dx * d * spacing.0).powi(2) + (dy * d * spacing.1).powi(2)).sqrt();
+                    max_tan = max_tan.max(rise / run);
+                }
+                // Sine of the horizon angle
+                occlusion += max_tan / (1.0 + max_tan * max_tan).sqrt();
+            }
+
+            ao.push(1.0 - occlusion / directions.len() as f32);
+        }
+    }
+    ao
+}
+
+impl FromStr for Kind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "hillshade" => Ok(Kind::Hillshade),
+            "ao" => Ok(Kind::AmbientOcclusion),
+            "combined" => Ok(Kind::Combined),
+            _ => Err(format!(
+                "Invalid lightmap '{}', use hillshade, ao or combined",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plane(width: usize, height: usize, f: impl Fn(usize, usize) -> f32) -> Vec<f32> {
+        (0..width * height)
+            .map(|i| f(i % width, i / width))
+            .collect()
+    }
+
+    #[test]
+    fn flat_terrain_is_evenly_lit_and_unoccluded() {
+        let elevations = vec![10.0; 8 * 8];
+        let params = LightmapParams::default();
+
+        let shade = hillshade(&elevations, 8, 8, (1.0, 1.0), &params);
+        let expected = params.sun_elevation.to_radians().sin();
+        assert!(shade.iter().all(|s| (s - expected).abs() < 1e-6));
+
+        let ao = ambient_occlusion(&elevations, 8, 8, (1.0, 1.0), &params);
+        assert!(ao.iter().all(|a| *a == 1.0));
+    }
+
+    #[test]
+    fn slope_facing_the_sun_is_brighter() {
+        // Sun in the east
+        let params = LightmapParams {
+            sun_azimuth: 90.0,
+            ..LightmapParams::default()
+        };
+        let facing = plane(8, 8, |x, _| -(x as f32));
+        let away = plane(8, 8, |x, _| x as f32);
+
+        let lit = hillshade(&facing, 8, 8, (1.0, 1.0), &params);
+        let shadowed = hillshade(&away, 8, 8, (1.0, 1.0), &params);
+        for (l, s) in lit.iter().zip(&shadowed) {
+            assert!(l > s);
+        }
+        // 45 degree slope facing away from a 45 degree sun is unlit
+        assert!(shadowed[4 + 4 * 8] < 1e-6);
+    }
+
+    #[test]
+    fn pits_are_occluded() {
+        let elevations = plane(9, 9, |x, y| {
+            let (dx, dy) = (x as f32 - 4.0, y as f32 - 4.0);
+            dx * dx + dy * dy
+        });
+        let ao = ambient_occlusion(&elevations, 9, 9, (1.0, 1.0), &LightmapParams::default());
+        assert!(ao[4 + 4 * 9] < 0.5);
+    }
+}
@@ -1,62 +1,611 @@
-use std::env;
-use std::path::Path;
+use structopt::StructOpt;
 
 mod alphamap;
+mod alphamap_rules;
+mod ascii_grid;
+mod camera_mode;
+mod camera_rig;
+mod classify;
+mod color_ramp;
+mod contour;
+mod erosion;
+mod filter;
+mod flatten;
+mod generate;
+mod geo_transform;
+mod geotiff;
 mod ground_plane;
 mod gui;
 mod heightmap;
+mod hgt;
+mod info;
+mod interpolation;
+mod landing;
+mod lightmap;
+mod normal_map;
+mod opts;
 mod origin_model;
 mod ortho_view;
+mod point_cloud;
+mod profile;
+mod profile_view;
+mod raster;
+mod raw;
+mod slope;
 mod terrain_mode;
+mod textures;
+mod top_down;
+mod viewshed;
 
 use crate::alphamap::Alphamap;
+use crate::alphamap_rules::{Attributes, Rules};
+use crate::classify::{Method, MAX_CLASSES};
+use crate::geo_transform::GeoTransform;
 use crate::gui::Gui;
-use crate::heightmap::Heightmap;
+use crate::heightmap::{Error, Heightmap};
+use crate::info::Info;
+use crate::opts::{Command, Opts};
+use crate::point_cloud::RasterizeParams;
+use crate::raw::{ByteOrder, RawFormat};
+use crate::terrain_mode::TerrainMode;
+use crate::viewshed::ViewshedParams;
+use image::{DynamicImage, FilterType};
 
 fn main() {
-    // TODO - add cli/opts lib to handle args/etc
-    // - point to a src image file, construct the rest
-    let app_name = env::args()
-        .nth(0)
-        .unwrap_or(String::from("heli-x-scene3d-tool"));
-    let usage = format!("Usage: {} /path/to/project/res/", app_name);
-    let resource_root: String = if let Some(p) = env::args().nth(1) {
-        p
-    } else {
-        panic!("Invalid arguments\n{}", usage);
-    };
-    let resource_root_path = Path::new(&resource_root);
+    let opts = Opts::from_args();
+    let resource_root_path = opts.resource_root.as_path();
+
+    // New projects start from an empty resource root
+    if let Some(Command::Generate(_)) = opts.command {
+        std::fs::create_dir_all(resource_root_path)
+            .expect("Failed to create resource root directory");
+    }
 
     if !resource_root_path.exists() {
-        panic!(
-            "Path {} does not exist\n{}",
-            resource_root_path.display(),
-            usage
-        );
+        panic!("Path {} does not exist", resource_root_path.display());
     }
 
     if !resource_root_path.is_dir() {
-        panic!(
-            "Path {} is not a directory\n{}",
-            resource_root_path.display(),
-            usage
-        );
+        panic!("Path {} is not a directory", resource_root_path.display());
     }
 
-    let hmap_file = resource_root_path.join("heightmap.png");
-    let amap_file = resource_root_path.join("alphamap.png");
+    match &opts.command {
+        None => run_viewer(&opts, load_heightmap(&opts)),
+        Some(Command::Export { output, byte_order }) => {
+            let hmap = load_heightmap(&opts);
+            hmap.save_file(output, *byte_order)
+                .expect("Failed to export Heightmap");
+            println!("Wrote {}", output.display());
+        }
+        Some(Command::Info { bins, json }) => {
+            let hmap = load_heightmap(&opts);
+            let (w, h) = hmap.dimensions();
+
+            let amap_file = opts.alphamap_file();
+            let amap = if amap_file.exists() {
+                Some(
+                    Alphamap::from_png_file(Some(&amap_file), w, h)
+                        .expect("Failed to create Alphamap"),
+                )
+            } else {
+                None
+            };
+
+            let info = Info::new(&hmap, amap.as_ref(), *bins);
+            if *json {
+                info.write_json(&mut std::io::stdout())
+                    .expect("Failed to write info");
+            } else {
+                info.print();
+            }
+        }
+        Some(Command::Filter {
+            filters,
+            output,
+            byte_order,
+        }) => {
+            let mut hmap = load_heightmap(&opts);
+            for f in filters {
+                println!("Applying filter {}", f);
+                hmap.apply_filter(f);
+            }
+            hmap.save_file(output, *byte_order)
+                .expect("Failed to write Heightmap");
+            println!("Wrote {}", output.display());
+        }
+        Some(Command::Erode(erode)) => {
+            let mut hmap = load_heightmap(&opts);
+
+            if erode.iterations != 0 {
+                println!("Simulating {} droplets", erode.iterations);
+                hmap.erode_hydraulic(&erode.hydraulic_params(), erode.seed);
+            }
+            if erode.thermal_iterations != 0 {
+                println!("Simulating {} thermal iterations", erode.thermal_iterations);
+                hmap.erode_thermal(&erode.thermal_params());
+            }
+
+            if let Some(output) = &erode.output {
+                hmap.save_file(output, erode.byte_order)
+                    .expect("Failed to write Heightmap");
+                println!("Wrote {}", output.display());
+            }
+
+            if erode.view {
+                run_viewer(&opts, hmap);
+            }
+        }
+        Some(Command::Flatten(flat)) => {
+            let mut hmap = load_heightmap(&opts);
+
+            let bounds = if flat.pixel_coords {
+                hmap.flatten_pixels(&flat.region, &flat.params())
+            } else {
+                hmap.flatten(&flat.region, &flat.params())
+            };
+            match bounds {
+                Some((x0, y0, x1, y1)) => {
+                    println!("Flattened pixels ({}, {}) to ({}, {})", x0, y0, x1, y1)
+                }
+                None => {
+                    // Without georeferencing pixel rows run along -Y
+                    let hint = if flat.pixel_coords {
+                        ""
+                    } else if *hmap.geo_transform() == GeoTransform::default() {
+                        ", world Y is the negated pixel row, or use --pixel-coords"
+                    } else {
+                        ", or use --pixel-coords"
+                    };
+                    let (w, h) = hmap.dimensions();
+                    eprintln!(
+                        "Error: the region does not cover the {}x{} heightmap{}",
+                        w, h, hint
+                    );
+                    std::process::exit(1);
+                }
+            }
+
+            if let Some(output) = &flat.output {
+                hmap.save_file(output, flat.byte_order)
+                    .expect("Failed to write Heightmap");
+                println!("Wrote {}", output.display());
+            }
+
+            if flat.view {
+                run_viewer(&opts, hmap);
+            }
+        }
+        Some(Command::AlphamapRules {
+            rules,
+            output,
+            force,
+            view,
+        }) => {
+            let amap_file = output.clone().unwrap_or_else(|| opts.alphamap_file());
+            if amap_file.exists() && !*force {
+                panic!(
+                    "{} already exists, use --force to overwrite",
+                    amap_file.display()
+                );
+            }
+
+            let hmap = load_heightmap(&opts);
+            let (w, h) = hmap.dimensions();
+            let rules = Rules::from_file(rules).expect("Failed to read alphamap rules");
+
+            rules
+                .generate(&Attributes::from_heightmap(&hmap), w, h)
+                .save(&amap_file)
+                .expect("Failed to write Alphamap");
+            println!("Wrote {}", amap_file.display());
+
+            if *view {
+                let opts = Opts {
+                    alphamap: Some(amap_file),
+                    ..opts
+                };
+                run_viewer(&opts, hmap);
+            }
+        }
+        Some(Command::Contours {
+            output,
+            interval,
+            base,
+        }) => {
+            let hmap = load_heightmap(&opts);
+            let (w, h) = hmap.dimensions();
+            let interval = interval.unwrap_or_else(|| contour::auto_interval(hmap.value_range()));
+            let contours = match hmap.contours(interval, *base) {
+                Ok(contours) => contours,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    std::process::exit(1);
+                }
+            };
+
+            let ext = output
+                .extension()
+                .and_then(|e| e.to_str())
+                .map(|e| e.to_lowercase());
+            match ext.as_deref() {
+                Some("svg") => contour::write_svg(output, &contours, interval, w, h),
+                Some("geojson") | Some("json") => {
+                    contour::write_geojson(output, &contours, interval, hmap.geo_transform())
+                }
+                _ => panic!(
+                    "Unsupported contour file {}, use .svg or .geojson",
+                    output.display()
+                ),
+            }
+            .expect("Failed to write contours");
+            println!(
+                "Wrote {} ({} lines, interval {})",
+                output.display(),
+                contours.len(),
+                interval
+            );
+        }
+        Some(Command::NormalMap {
+            output,
+            space,
+            scale,
+            height_scale,
+            flip_green,
+        }) => {
+            let mut hmap = load_heightmap(&opts);
+            if let Some(s) = height_scale {
+                hmap.set_height_scale(*s);
+            }
+
+            let img = hmap.bake_normal_map(*space, *scale, *flip_green);
+            img.save(output).expect("Failed to write normal map");
+            println!(
+                "Wrote {} ({}x{})",
+                output.display(),
+                img.width(),
+                img.height()
+            );
+        }
+        Some(Command::SlopeReport { height_scale }) => {
+            let mut hmap = load_heightmap(&opts);
+            if let Some(s) = height_scale {
+                hmap.set_height_scale(*s);
+            }
+
+            let classes = &opts.slope_thresholds;
+            let slopes = hmap.slope_degrees();
+            let (w, h) = hmap.dimensions();
+            let (psx, psy) = hmap.geo_transform().pixel_size_meters(h);
+            let total_area = psx * psy * (w * h) as f64;
+
+            println!("{:<14} {:>14} {:>8}", "Slope", "Area (m2)", "Percent");
+            for (class, fraction) in classes.fractions(&slopes).iter().enumerate() {
+                println!(
+                    "{:<14} {:>14.1} {:>7.2}%",
+                    classes.label(class),
+                    total_area * f64::from(*fraction),
+                    fraction * 100.0
+                );
+            }
+        }
+        Some(Command::Viewshed(vs)) => {
+            let mut hmap = load_heightmap(&opts);
+            if let Some(s) = vs.height_scale {
+                hmap.set_height_scale(s);
+            }
+
+            let (w, h) = hmap.dimensions();
+            let pilot = if vs.pixel_coords {
+                vs.pilot
+            } else {
+                hmap.geo_transform().world_to_pixel(vs.pilot.0, vs.pilot.1)
+            };
+            if pilot.0 < 0.0
+                || pilot.1 < 0.0
+                || pilot.0 > (w - 1) as f64
+                || pilot.1 > (h - 1) as f64
+            {
+                panic!("Pilot position is outside the heightmap");
+            }
+
+            let defaults = hmap.viewshed_params();
+            let params = ViewshedParams {
+                pilot,
+                eye_height: vs.eye_height.unwrap_or(defaults.eye_height),
+                flight_height: vs.flight_height.unwrap_or(defaults.flight_height),
+            };
+            hmap.set_viewshed_params(params);
+
+            let heights = hmap.viewshed_heights();
+            let visible = |height: f32| {
+                heights.iter().filter(|h| **h <= height).count() as f32 / heights.len() as f32
+            };
+            viewshed::mask(&heights, w, h, params.flight_height)
+                .save(&vs.output)
+                .expect("Failed to write viewshed");
+            println!("Wrote {}", vs.output.display());
+            println!("Terrain visible: {:.1}%", visible(0.0) * 100.0);
+            println!(
+                "Heli visible at {}: {:.1}%",
+                params.flight_height,
+                visible(params.flight_height) * 100.0
+            );
+
+            if vs.view {
+                hmap.set_terrain_mode(TerrainMode::Viewshed);
+                run_viewer(&opts, hmap);
+            }
+        }
+        Some(Command::LandingSpots(land)) => {
+            let mut hmap = load_heightmap(&opts);
+            if let Some(s) = land.height_scale {
+                hmap.set_height_scale(s);
+            }
+
+            let (w, h) = hmap.dimensions();
+            let origin = match land.origin {
+                Some((x, y)) => hmap.geo_transform().world_to_pixel(x, y),
+                None => ((w - 1) as f64 / 2.0, (h - 1) as f64 / 2.0),
+            };
+            let params = land.params(hmap.value_range());
+            let spots = hmap.landing_spots(&params, origin);
+
+            println!(
+                "{:>4} {:>14} {:>14} {:>9} {:>10} {:>12}",
+                "Rank", "X", "Y", "Size (m)", "Slope", "Distance (m)"
+            );
+            for (rank, spot) in spots.iter().enumerate() {
+                let (x, y) = hmap
+                    .geo_transform()
+                    .pixel_to_world(spot.center.0 as f64, spot.center.1 as f64);
+                println!(
+                    "{:>4} {:>14.2} {:>14.2} {:>9.1} {:>6.2} deg {:>12.1}",
+                    rank + 1,
+                    x,
+                    y,
+                    spot.size,
+                    spot.mean_slope,
+                    spot.distance
+                );
+            }
+            if spots.is_empty() {
+                println!("No spots found, try a smaller --min-size or larger thresholds");
+            }
+
+            if land.view {
+                let mut gui = viewer(&opts, hmap);
+                gui.set_landing(params, spots);
+                while gui.render() {}
+            }
+        }
+        Some(Command::Lightmap(light)) => {
+            let mut hmap = load_heightmap(&opts);
+            if let Some(s) = light.height_scale {
+                hmap.set_height_scale(s);
+            }
+
+            hmap.bake_lightmap(light.kind, &light.params())
+                .save(&light.output)
+                .expect("Failed to write lightmap");
+            println!("Wrote {}", light.output.display());
+        }
+        Some(Command::Texture {
+            image,
+            size,
+            output,
+        }) => {
+            let mut img = image::open(image).expect("Failed to open image");
+            if let Some((w, h)) = size {
+                img = img.resize_exact(*w as _, *h as _, FilterType::Triangle);
+            }
+
+            let tex_file = output.clone().unwrap_or_else(|| opts.texture_file());
+            DynamicImage::ImageRgb8(img.to_rgb())
+                .save(&tex_file)
+                .expect("Failed to write texture");
+            println!("Wrote {}", tex_file.display());
+        }
+        Some(Command::AlphamapClassify {
+            image,
+            classes,
+            colors,
+            softness,
+            seed,
+            output,
+            force,
+            view,
+        }) => {
+            let amap_file = output.clone().unwrap_or_else(|| opts.alphamap_file());
+            if amap_file.exists() && !*force {
+                panic!(
+                    "{} already exists, use --force to overwrite",
+                    amap_file.display()
+                );
+            }
+
+            let mut hmap = load_heightmap(&opts);
+            let (w, h) = hmap.dimensions();
+            let img = image::open(image).expect("Failed to open image");
+
+            let method = if colors.is_empty() {
+                Method::KMeans {
+                    classes: *classes,
+                    seed: *seed,
+                }
+            } else {
+                if colors.len() > MAX_CLASSES {
+                    println!("Only the first {} colors are used", MAX_CLASSES);
+                }
+                Method::Reference(colors.clone())
+            };
+            let (amap_img, class_colors) = classify::classify(&img, w, h, &method, *softness);
+
+            let channels = ["red", "green", "blue", "alpha"];
+            for (channel, color) in channels.iter().zip(class_colors.iter()) {
+                println!("Class {}: {}", channel, color);
+            }
+
+            amap_img.save(&amap_file).expect("Failed to write Alphamap");
+            println!("Wrote {}", amap_file.display());
+
+            if *view {
+                let opts = Opts {
+                    alphamap: Some(amap_file),
+                    ..opts
+                };
+                hmap.set_terrain_mode(TerrainMode::Alphamap);
+                run_viewer(&opts, hmap);
+            }
+        }
+        Some(Command::Generate(gen)) => {
+            let hmap_file = resource_root_path.join(&gen.output);
+            let amap_file = resource_root_path.join("alphamap.png");
+            for f in &[&hmap_file, &amap_file] {
+                if f.exists() && !gen.force {
+                    panic!("{} already exists, use --force to overwrite", f.display());
+                }
+            }
+
+            let params = gen.params();
+            let elevations = generate::generate(&params);
+            let hmap =
+                Heightmap::from_normalized(&hmap_file, params.width, params.height, elevations)
+                    .expect("Failed to create Heightmap");
+            hmap.save_file(&hmap_file, ByteOrder::default())
+                .expect("Failed to write Heightmap");
+            println!(
+                "Wrote {} ({}x{})",
+                hmap_file.display(),
+                params.width,
+                params.height
+            );
+
+            Alphamap::default_image(params.width, params.height)
+                .save(&amap_file)
+                .expect("Failed to write Alphamap");
+            println!("Wrote {}", amap_file.display());
+
+            if gen.view {
+                let opts = Opts {
+                    heightmap: Some(hmap_file),
+                    ..opts
+                };
+                run_viewer(&opts, hmap);
+            }
+        }
+        Some(Command::FromLas {
+            input,
+            resolution,
+            classes,
+            bin_mode,
+            texture,
+            output,
+        }) => {
+            let params = RasterizeParams {
+                resolution: *resolution,
+                classes: *classes,
+                bin_mode: *bin_mode,
+                texture: *texture,
+            };
+            let (raster, texture) = match point_cloud::rasterize(input, &params) {
+                Ok(r) => r,
+                Err(Error::InvalidDimensions) => {
+                    eprintln!(
+                        "Error: a --resolution of {} m needs more than {} grid cells, use a larger one",
+                        resolution,
+                        point_cloud::MAX_CELLS
+                    );
+                    std::process::exit(1);
+                }
+                Err(e) => panic!("Failed to rasterize point cloud: {:?}", e),
+            };
+
+            let hmap_file = output
+                .clone()
+                .unwrap_or_else(|| resource_root_path.join("heightmap.asc"));
+            ascii_grid::write_file(&hmap_file, &raster).expect("Failed to write heightmap");
+            println!(
+                "Wrote {} ({}x{})",
+                hmap_file.display(),
+                raster.width,
+                raster.height
+            );
+
+            // heightmap.asc comes after the image heightmaps in the
+            // defaults, the viewer would load one of those instead
+            let loaded = opts.heightmap_file();
+            if output.is_none() && opts.heightmap.is_none() && loaded != hmap_file {
+                eprintln!(
+                    "Warning: {} is loaded by default instead, remove it or use --heightmap {}",
+                    loaded.display(),
+                    hmap_file.display()
+                );
+            }
+
+            if let Some(img) = texture {
+                let tex_file = hmap_file.with_file_name("texture.png");
+                img.save(&tex_file).expect("Failed to write texture");
+                println!("Wrote {}", tex_file.display());
+            }
+        }
+    }
+}
+
+fn load_heightmap(opts: &Opts) -> Heightmap {
+    let hmap_file = opts.heightmap_file();
+
+    // Raw files are headerless, use the layout from the options
+    let hmap = if RawFormat::from_path(&hmap_file).is_some() {
+        Heightmap::from_raw_file(&hmap_file, opts.raw_layout())
+    } else {
+        Heightmap::from_file(&hmap_file)
+    };
+
+    hmap.expect("Failed to create Heightmap")
+}
+
+fn run_viewer(opts: &Opts, hmap: Heightmap) {
+    let mut gui = viewer(opts, hmap);
+    while gui.render() {}
+}
+
+/// Open the viewer with the files and settings from the options
+fn viewer(opts: &Opts, mut hmap: Heightmap) -> Gui {
+    let (min, max) = hmap.ramp_range();
+    hmap.set_color_ramp(opts.color_ramp);
+    hmap.set_slope_classes(opts.slope_thresholds.clone());
+    if opts.ramp_min.is_some() || opts.ramp_max.is_some() {
+        hmap.set_ramp_range(opts.ramp_min.unwrap_or(min), opts.ramp_max.unwrap_or(max));
+    }
+
+    let amap_file = opts.alphamap_file();
     let some_amap_file = if amap_file.exists() {
         Some(&amap_file)
     } else {
         None
     };
 
-    let hmap = Heightmap::from_png_file(&hmap_file).expect("Failed to create Heightmap");
-
     let (dw, dh) = hmap.dimensions();
     let amap = Alphamap::from_png_file(some_amap_file, dw, dh).expect("Failed to create Alphamap");
 
-    let mut gui = Gui::new(hmap, amap);
+    let tex_file = opts.texture_file();
+    let texture = if tex_file.exists() {
+        Some(image::open(&tex_file).expect("Failed to open texture"))
+    } else {
+        None
+    };
 
-    while gui.render() {}
+    let mut gui = Gui::new(hmap, amap, texture);
+
+    gui.set_interpolation(opts.interpolation);
+    gui.set_profile_file(opts.resource_root.join("profile.csv"));
+    if let Some(interval) = opts.contour_interval {
+        gui.set_contour_interval(interval);
+    }
+
+    if let Some(Command::AlphamapRules { rules, .. }) = &opts.command {
+        gui.preview_alphamap_rules(rules.clone());
+    }
+
+    gui
 }
@@ -0,0 +1,118 @@
+use image::{ImageBuffer, Rgb, RgbImage};
+use nalgebra::{Point3, Vector3};
+use std::str::FromStr;
+
+/// Coordinate frame of the baked normals
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum NormalSpace {
+    /// Relative to the texture, red is right, green is up and blue is out of
+    /// the surface
+    Tangent,
+    /// Terrain mesh frame, Y-up
+    Object,
+}
+
+/// Sum the face normal of each triangle into its vertices, then normalize
+pub fn vertex_normals<I: Iterator<Item = [usize; 3]>>(
+    vertices: &[Point3<f32>],
+    triangles: I,
+    normals: &mut [Vector3<f32>],
+) {
+    for [i0, i1, i2] in triangles {
+        let v0 = vertices[i0];
+        let v1 = vertices[i1];
+        let v2 = vertices[i2];
+
+        let a = v1 - v0;
+        let b = v2 - v0;
+
+        let normal = a.cross(&b).normalize();
+
+        normals[i0] += normal;
+        normals[i1] += normal;
+        normals[i2] += normal;
+    }
+
+    for n in normals.iter_mut() {
+        *n = n.normalize();
+    }
+}
+
+/// Vertex normals of a width x height grid of elevations, in image order
+///
+/// Elevations and `spacing`, the (x, y) distance between pixels, are in the
+/// same units. The grid is triangulated the same way as the terrain mesh.
+pub fn grid_normals(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    spacing: (f32, f32),
+) -> Vec<Vector3<f32>> {
+    // Vertices in mesh order, mirrored like the terrain tiles
+    let mut vertices = Vec::with_capacity(width * height);
+    for my in 0..height {
+        for mx in 0..width {
+            let e = elevations[(width - 1 - mx) + (height - 1 - my) * width];
+            vertices.push(Point3::new(mx as f32 * spacing.0, e, my as f32 * spacing.1));
+        }
+    }
+
+    let triangles = (0..(height - 1)).flat_map(|y| {
+        (0..(width - 1)).flat_map(move |x| {
+            let index = y * width + x;
+            vec![
+                [index, index + width + 1, index + 1],
+                [index, index + width, index + width + 1],
+            ]
+        })
+    });
+
+    let mut normals = vec![Vector3::new(0.0, 0.0, 0.0); vertices.len()];
+    vertex_normals(&vertices, triangles, &mut normals);
+
+    // Mesh order is the image mirrored on both axes
+    normals.reverse();
+    normals
+}
+
+/// Bake a normal map from a width x height grid of elevations, see
+/// `grid_normals`
+pub fn bake(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    spacing: (f32, f32),
+    space: NormalSpace,
+    flip_green: bool,
+) -> RgbImage {
+    let normals = grid_normals(elevations, width, height, spacing);
+
+    ImageBuffer::from_fn(width as _, height as _, |x, y| {
+        let n = normals[x as usize + y as usize * width];
+
+        // Image right is mesh -X and image up is mesh +Z
+        let (r, g, b) = match space {
+            NormalSpace::Tangent => (-n.x, n.z, n.y),
+            NormalSpace::Object => (n.x, n.y, n.z),
+        };
+        let g = if flip_green { -g } else { g };
+
+        let c = |v: f32| ((v * 0.5 + 0.5) * 255.0).round() as u8;
+        Rgb([c(r), c(g), c(b)])
+    })
+}
+
+impl FromStr for NormalSpace {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "tangent" => Ok(NormalSpace::Tangent),
+            "object" => Ok(NormalSpace::Object),
+            _ => Err(format!(
+                "Invalid normal space '{}', use tangent or object",
+                s
+            )),
+        }
+    }
+}
@@ -0,0 +1,697 @@
+use crate::classify::Color;
+use crate::color_ramp::ColorRamp;
+use crate::contour;
+use crate::erosion::{HydraulicParams, ThermalParams};
+use crate::filter::Filter;
+use crate::flatten::{FlattenParams, Region};
+use crate::generate::{self, Algorithm, GenerateParams};
+use crate::geo_transform;
+use crate::interpolation::Interpolation;
+use crate::landing::{LandingParams, Ranking};
+use crate::lightmap::{Kind as LightmapKind, LightmapParams};
+use crate::normal_map::NormalSpace;
+use crate::point_cloud::{self, BinMode, PointClasses, TextureSource};
+use crate::raw::{self, ByteOrder, RawLayout};
+use crate::slope::SlopeClasses;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+/// Heightmap files looked for in the resource root, in order of preference
+pub const HEIGHTMAP_FILES: &[&str] = &[
+    "heightmap.png",
+    "heightmap.tif",
+    "heightmap.tiff",
+    "heightmap.asc",
+    "heightmap.r16",
+    "heightmap.raw",
+    "heightmap.r32",
+];
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "heli-x-scene3d-tool",
+    about = "Interactively view and generate Heli-X Scene3D projects"
+)]
+pub struct Opts {
+    /// Project resource directory, i.e. /path/to/project/res/
+    #[structopt(parse(from_os_str))]
+    pub resource_root: PathBuf,
+
+    /// Heightmap file (.png, .tif, .asc, .hgt, .raw/.r16, .r32), the format
+    /// is selected by the file extension.
+    /// Defaults to the first heightmap.{png,tif,tiff,asc,r16,raw,r32}
+    /// found in the resource root
+    #[structopt(long = "heightmap", parse(from_os_str))]
+    pub heightmap: Option<PathBuf>,
+
+    /// Dimensions of a raw heightmap file (WIDTHxHEIGHT), assumed to be
+    /// square when not provided
+    #[structopt(long = "raw-size", parse(try_from_str = raw::parse_dimensions))]
+    pub raw_size: Option<(usize, usize)>,
+
+    /// Byte order of a raw heightmap file (little, big)
+    #[structopt(long = "raw-byte-order", default_value = "little")]
+    pub raw_byte_order: ByteOrder,
+
+    /// Alphamap file, defaults to alphamap.png in the resource root
+    #[structopt(long = "alphamap", parse(from_os_str))]
+    pub alphamap: Option<PathBuf>,
+
+    /// Color texture (i.e. an orthophoto) draped over the terrain, defaults
+    /// to texture.png in the resource root
+    #[structopt(long = "texture", parse(from_os_str))]
+    pub texture: Option<PathBuf>,
+
+    /// Color ramp of the elevation terrain mode (terrain, viridis,
+    /// diverging, grayscale)
+    #[structopt(long = "color-ramp", default_value = "terrain")]
+    pub color_ramp: ColorRamp,
+
+    /// Elevation mapped to the low end of the color ramp, defaults to the
+    /// lowest elevation
+    #[structopt(long = "ramp-min")]
+    pub ramp_min: Option<f32>,
+
+    /// Elevation mapped to the high end of the color ramp, defaults to the
+    /// highest elevation
+    #[structopt(long = "ramp-max")]
+    pub ramp_max: Option<f32>,
+
+    /// Contour line interval in source elevation units, picked from the
+    /// elevation range by default
+    #[structopt(
+        long = "contour-interval",
+        parse(try_from_str = contour::parse_interval)
+    )]
+    pub contour_interval: Option<f32>,
+
+    /// Interpolation of terrain queries between pixels (bilinear, bicubic)
+    #[structopt(long = "interpolation", default_value = "bilinear")]
+    pub interpolation: Interpolation,
+
+    /// Slope angles, in degrees, separating the classes of the slope terrain
+    /// mode and report
+    #[structopt(long = "slope-thresholds", default_value = "5,15,30")]
+    pub slope_thresholds: SlopeClasses,
+
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum Command {
+    /// Write the heightmap to another format (.png, .raw/.r16, .r32),
+    /// selected by the file extension
+    #[structopt(name = "export")]
+    Export {
+        /// Output file
+        #[structopt(parse(from_os_str))]
+        output: PathBuf,
+
+        /// Byte order of raw output files (little, big)
+        #[structopt(long = "byte-order", default_value = "little")]
+        byte_order: ByteOrder,
+    },
+
+    /// Print heightmap statistics, mesh size and alphamap coverage
+    #[structopt(name = "info")]
+    Info {
+        /// Number of histogram bins
+        #[structopt(long = "bins", default_value = "16")]
+        bins: usize,
+
+        /// Print JSON instead of text
+        #[structopt(long = "json")]
+        json: bool,
+    },
+
+    /// Apply processing filters, in the order given, and write the result
+    ///
+    /// Filters: blur:SIGMA, despike:RADIUS[:K], levels:LOW:HIGH[:CURVE],
+    /// terrace:RADIUS.
+    /// Levels are normalized to the heightmap value range, CURVE is linear,
+    /// smooth or a gamma value
+    #[structopt(name = "filter")]
+    Filter {
+        /// Filters to apply
+        #[structopt(required = true)]
+        filters: Vec<Filter>,
+
+        /// Output file (.png, .raw/.r16, .r32)
+        #[structopt(long = "output", parse(from_os_str))]
+        output: PathBuf,
+
+        /// Byte order of raw output files (little, big)
+        #[structopt(long = "byte-order", default_value = "little")]
+        byte_order: ByteOrder,
+    },
+
+    /// Run hydraulic and thermal erosion simulations
+    #[structopt(name = "erode")]
+    Erode(ErodeOpts),
+
+    /// Flatten a region to a target height, i.e. for runways and helipads
+    #[structopt(name = "flatten")]
+    Flatten(FlattenOpts),
+
+    /// Generate the alphamap from slope, elevation and curvature rules
+    ///
+    /// Rules, one per line: CHANNEL ATTRIBUTE:MIN:MAX[:BLEND] ...
+    /// CHANNEL is red, green, blue or alpha, ATTRIBUTE is elevation
+    /// (normalized), slope (degrees) or curvature. Later rules are painted
+    /// over earlier ones
+    #[structopt(name = "alphamap-rules")]
+    AlphamapRules {
+        /// Rules file
+        #[structopt(parse(from_os_str))]
+        rules: PathBuf,
+
+        /// Output file, defaults to the alphamap file
+        #[structopt(long = "output", parse(from_os_str))]
+        output: Option<PathBuf>,
+
+        /// Overwrite an existing output file
+        #[structopt(long = "force")]
+        force: bool,
+
+        /// Open the result in the viewer, edit the rules file and press U
+        /// to regenerate
+        #[structopt(long = "view")]
+        view: bool,
+    },
+
+    /// Export contour lines as SVG (pixel coordinates) or GeoJSON (world
+    /// coordinates), selected by the file extension
+    #[structopt(name = "contours")]
+    Contours {
+        /// Output file (.svg, .geojson/.json)
+        #[structopt(parse(from_os_str))]
+        output: PathBuf,
+
+        /// Contour interval in source elevation units, picked from the
+        /// elevation range by default
+        #[structopt(long = "interval", parse(try_from_str = contour::parse_interval))]
+        interval: Option<f32>,
+
+        /// Elevation the contour levels are offset from
+        #[structopt(long = "base", default_value = "0")]
+        base: f32,
+    },
+
+    /// Bake a normal map PNG from the heightmap
+    #[structopt(name = "normal-map")]
+    NormalMap {
+        /// Output PNG file
+        #[structopt(parse(from_os_str))]
+        output: PathBuf,
+
+        /// Normal map space (tangent, object)
+        #[structopt(long = "space", default_value = "tangent")]
+        space: NormalSpace,
+
+        /// Resolution multiplier, relative to the heightmap
+        #[structopt(long = "scale", default_value = "1")]
+        scale: usize,
+
+        /// Height scale, defaults to the viewer's initial height scale
+        #[structopt(long = "height-scale")]
+        height_scale: Option<f32>,
+
+        /// Invert the green channel (DirectX convention)
+        #[structopt(long = "flip-green")]
+        flip_green: bool,
+    },
+
+    /// Report the area of each slope class, see --slope-thresholds
+    #[structopt(name = "slope-report")]
+    SlopeReport {
+        /// Height scale, defaults to the viewer's initial height scale
+        #[structopt(long = "height-scale")]
+        height_scale: Option<f32>,
+    },
+
+    /// Compute what's visible from a pilot position and write it as a PNG
+    /// mask, white where the heli is visible
+    #[structopt(name = "viewshed")]
+    Viewshed(ViewshedOpts),
+
+    /// Find flat spots to land on, for helipads and pilot positions
+    #[structopt(name = "landing-spots")]
+    LandingSpots(LandingOpts),
+
+    /// Bake a hillshade and/or ambient occlusion lightmap PNG
+    #[structopt(name = "lightmap")]
+    Lightmap(LightmapOpts),
+
+    /// Write a color image, i.e. an orthophoto, to the project as the
+    /// terrain color texture
+    #[structopt(name = "texture")]
+    Texture {
+        /// Color image covering the heightmap
+        #[structopt(parse(from_os_str))]
+        image: PathBuf,
+
+        /// Resample to WIDTHxHEIGHT, defaults to the image dimensions
+        #[structopt(long = "size", parse(try_from_str = raw::parse_dimensions))]
+        size: Option<(usize, usize)>,
+
+        /// Output file, defaults to the texture file
+        #[structopt(long = "output", parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+
+    /// Generate the alphamap by classifying the colors of an aerial photo
+    ///
+    /// Each pixel gets soft weights for up to 4 classes, one per channel,
+    /// found by k-means clustering or given as reference colors. The image
+    /// is resampled to the heightmap dimensions
+    #[structopt(name = "alphamap-classify")]
+    AlphamapClassify {
+        /// Color image, i.e. an orthophoto covering the heightmap
+        #[structopt(parse(from_os_str))]
+        image: PathBuf,
+
+        /// Number of k-means classes (1-4)
+        #[structopt(long = "classes", default_value = "4")]
+        classes: usize,
+
+        /// Reference color (RRGGBB hex) of a class, in channel order,
+        /// replaces k-means clustering
+        #[structopt(long = "color", number_of_values = 1)]
+        colors: Vec<Color>,
+
+        /// Color distance, in 8 bit units, over which classes blend
+        #[structopt(long = "softness", default_value = "20")]
+        softness: f32,
+
+        /// Random seed for the k-means clustering
+        #[structopt(long = "seed", default_value = "0")]
+        seed: u64,
+
+        /// Output file, defaults to the alphamap file
+        #[structopt(long = "output", parse(from_os_str))]
+        output: Option<PathBuf>,
+
+        /// Overwrite an existing output file
+        #[structopt(long = "force")]
+        force: bool,
+
+        /// Open the result in the viewer
+        #[structopt(long = "view")]
+        view: bool,
+    },
+
+    /// Create a new project procedurally, writes the heightmap and a default
+    /// alphamap to the resource root, which is created if needed
+    #[structopt(name = "generate")]
+    Generate(GenerateOpts),
+
+    /// Rasterize a LAS point cloud into heightmap.asc (and texture.png
+    /// next to it) in the resource root
+    #[structopt(name = "from-las")]
+    FromLas {
+        /// Input LAS file
+        #[structopt(parse(from_os_str))]
+        input: PathBuf,
+
+        /// Grid cell size in meters, the grid can have at most 4096 x 4096
+        /// cells
+        #[structopt(
+            long = "resolution",
+            default_value = "1.0",
+            parse(try_from_str = point_cloud::parse_resolution)
+        )]
+        resolution: f64,
+
+        /// Points used for the elevations (ground, all)
+        #[structopt(long = "classes", default_value = "ground")]
+        classes: PointClasses,
+
+        /// How points within a cell are combined (min, max, mean)
+        #[structopt(long = "bin", default_value = "mean")]
+        bin_mode: BinMode,
+
+        /// Also rasterize a color texture (intensity, rgb)
+        #[structopt(long = "texture")]
+        texture: Option<TextureSource>,
+
+        /// Output heightmap file, defaults to heightmap.asc in the
+        /// resource root
+        #[structopt(long = "output", parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ErodeOpts {
+    /// Number of hydraulic erosion droplets, 0 disables hydraulic erosion
+    #[structopt(long = "iterations", default_value = "50000")]
+    pub iterations: usize,
+
+    /// Initial water volume of each droplet
+    #[structopt(long = "rain", default_value = "1.0")]
+    pub rain: f32,
+
+    /// Sediment capacity of the droplets
+    #[structopt(long = "sediment-capacity", default_value = "4.0")]
+    pub sediment_capacity: f32,
+
+    /// Fraction of free capacity eroded per step
+    #[structopt(long = "erode-speed", default_value = "0.3")]
+    pub erode_speed: f32,
+
+    /// Fraction of excess sediment deposited per step
+    #[structopt(long = "deposit-speed", default_value = "0.3")]
+    pub deposit_speed: f32,
+
+    /// Fraction of water evaporated per step
+    #[structopt(long = "evaporation", default_value = "0.01")]
+    pub evaporation: f32,
+
+    /// Erosion brush radius in pixels
+    #[structopt(long = "radius", default_value = "3")]
+    pub radius: usize,
+
+    /// Number of thermal erosion iterations, 0 disables thermal erosion
+    #[structopt(long = "thermal-iterations", default_value = "50")]
+    pub thermal_iterations: usize,
+
+    /// Talus angle in degrees, material slides down steeper slopes
+    #[structopt(long = "talus-angle", default_value = "35.0")]
+    pub talus_angle: f32,
+
+    /// Random seed, the simulation is deterministic for a given seed
+    #[structopt(long = "seed", default_value = "0")]
+    pub seed: u64,
+
+    /// Output file (.png, .raw/.r16, .r32)
+    #[structopt(long = "output", parse(from_os_str))]
+    pub output: Option<PathBuf>,
+
+    /// Byte order of raw output files (little, big)
+    #[structopt(long = "byte-order", default_value = "little")]
+    pub byte_order: ByteOrder,
+
+    /// Open the result in the viewer
+    #[structopt(long = "view")]
+    pub view: bool,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct FlattenOpts {
+    /// Region in world coordinates, a rectangle X0,Y0,X1,Y1 or a polygon
+    /// X,Y;X,Y;X,Y... World Y is the negated pixel row for heightmaps
+    /// without georeferencing (.png, .raw)
+    #[structopt(allow_hyphen_values = true)]
+    pub region: Region,
+
+    /// Region coordinates are pixels instead of world coordinates
+    #[structopt(long = "pixel-coords")]
+    pub pixel_coords: bool,
+
+    /// Target elevation in source units, defaults to the mean elevation
+    /// within the region
+    #[structopt(long = "height")]
+    pub height: Option<f32>,
+
+    /// Width of the blend to the surrounding terrain, in pixels
+    #[structopt(long = "margin", default_value = "10")]
+    pub margin: f32,
+
+    /// Output file (.png, .raw/.r16, .r32)
+    #[structopt(long = "output", parse(from_os_str))]
+    pub output: Option<PathBuf>,
+
+    /// Byte order of raw output files (little, big)
+    #[structopt(long = "byte-order", default_value = "little")]
+    pub byte_order: ByteOrder,
+
+    /// Open the result in the viewer
+    #[structopt(long = "view")]
+    pub view: bool,
+}
+
+impl FlattenOpts {
+    pub fn params(&self) -> FlattenParams {
+        FlattenParams {
+            target: self.height,
+            margin: self.margin,
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct LightmapOpts {
+    /// Output PNG file
+    #[structopt(parse(from_os_str))]
+    pub output: PathBuf,
+
+    /// Lighting to bake (hillshade, ao, combined)
+    #[structopt(long = "kind", default_value = "combined")]
+    pub kind: LightmapKind,
+
+    /// Sun direction in degrees clockwise from north (the top of the
+    /// heightmap)
+    #[structopt(long = "sun-azimuth", default_value = "315")]
+    pub sun_azimuth: f32,
+
+    /// Sun angle above the horizon in degrees
+    #[structopt(long = "sun-elevation", default_value = "45")]
+    pub sun_elevation: f32,
+
+    /// Number of directions searched for ambient occlusion
+    #[structopt(long = "ao-directions", default_value = "16")]
+    pub ao_directions: usize,
+
+    /// Ambient occlusion search distance in pixels
+    #[structopt(long = "ao-radius", default_value = "32")]
+    pub ao_radius: f32,
+
+    /// Height scale, defaults to the viewer's initial height scale
+    #[structopt(long = "height-scale")]
+    pub height_scale: Option<f32>,
+}
+
+impl LightmapOpts {
+    pub fn params(&self) -> LightmapParams {
+        LightmapParams {
+            sun_azimuth: self.sun_azimuth,
+            sun_elevation: self.sun_elevation,
+            ao_directions: self.ao_directions,
+            ao_radius: self.ao_radius,
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ViewshedOpts {
+    /// Pilot position X,Y in world coordinates
+    #[structopt(parse(try_from_str = geo_transform::parse_position))]
+    pub pilot: (f64, f64),
+
+    /// Output PNG mask
+    #[structopt(parse(from_os_str))]
+    pub output: PathBuf,
+
+    /// The pilot position is in pixel coordinates
+    #[structopt(long = "pixel-coords")]
+    pub pixel_coords: bool,
+
+    /// Pilot eye height above the terrain in source elevation units,
+    /// defaults to 1.8 for DEMs and 1% of the height range otherwise
+    #[structopt(long = "eye-height")]
+    pub eye_height: Option<f32>,
+
+    /// Height above the terrain the heli is flown at, in source elevation
+    /// units, the mask covers the terrain itself when 0. Defaults to 30 for
+    /// DEMs and 5% of the height range otherwise
+    #[structopt(long = "flight-height")]
+    pub flight_height: Option<f32>,
+
+    /// Meters spanned by the height range of normalized heightmaps (.png,
+    /// .raw), defaults to the viewer's initial height scale. DEMs are
+    /// always in meters
+    #[structopt(long = "height-scale")]
+    pub height_scale: Option<f32>,
+
+    /// Open the result in the viewer
+    #[structopt(long = "view")]
+    pub view: bool,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct LandingOpts {
+    /// Steepest slope in degrees
+    #[structopt(long = "max-slope", default_value = "5")]
+    pub max_slope: f32,
+
+    /// Largest deviation of a pixel from the mean of its neighbors, in
+    /// source elevation units, defaults to 0.25% of the height range
+    #[structopt(long = "max-roughness")]
+    pub max_roughness: Option<f32>,
+
+    /// Smallest side of a square spot in meters
+    #[structopt(long = "min-size", default_value = "10")]
+    pub min_size: f32,
+
+    /// Number of spots listed
+    #[structopt(long = "count", default_value = "10")]
+    pub count: usize,
+
+    /// Order of the spots (size, flatness, distance)
+    #[structopt(long = "rank", default_value = "size")]
+    pub ranking: Ranking,
+
+    /// Position X,Y in world coordinates distances are measured from,
+    /// defaults to the center of the heightmap
+    #[structopt(long = "origin", parse(try_from_str = geo_transform::parse_position))]
+    pub origin: Option<(f64, f64)>,
+
+    /// Height scale, defaults to the viewer's initial height scale
+    #[structopt(long = "height-scale")]
+    pub height_scale: Option<f32>,
+
+    /// Show the spots in the viewer
+    #[structopt(long = "view")]
+    pub view: bool,
+}
+
+impl LandingOpts {
+    pub fn params(&self, value_range: (f32, f32)) -> LandingParams {
+        let defaults = LandingParams::new(value_range);
+        LandingParams {
+            max_slope: self.max_slope,
+            max_roughness: self.max_roughness.unwrap_or(defaults.max_roughness),
+            min_size: self.min_size,
+            count: self.count,
+            ranking: self.ranking,
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct GenerateOpts {
+    /// Terrain algorithm (perlin, simplex, ridged, diamond-square)
+    #[structopt(long = "algorithm", default_value = "perlin")]
+    pub algorithm: Algorithm,
+
+    /// Heightmap dimensions (WIDTHxHEIGHT)
+    #[structopt(
+        long = "size",
+        default_value = "1024x1024",
+        parse(try_from_str = raw::parse_dimensions)
+    )]
+    pub size: (usize, usize),
+
+    /// Random seed, the terrain is deterministic for a given seed
+    #[structopt(long = "seed", default_value = "0")]
+    pub seed: u64,
+
+    /// Number of noise octaves
+    #[structopt(long = "octaves", default_value = "6")]
+    pub octaves: usize,
+
+    /// Size of the largest features, in pixels
+    #[structopt(
+        long = "scale",
+        default_value = "256",
+        parse(try_from_str = generate::parse_scale)
+    )]
+    pub scale: f32,
+
+    /// Amplitude multiplier between octaves, higher is rougher
+    #[structopt(long = "persistence", default_value = "0.5")]
+    pub persistence: f32,
+
+    /// Frequency multiplier between octaves
+    #[structopt(long = "lacunarity", default_value = "2.0")]
+    pub lacunarity: f32,
+
+    /// Fraction of the full heightmap range used, in (0, 1]
+    #[structopt(long = "amplitude", default_value = "1.0")]
+    pub amplitude: f32,
+
+    /// Heightmap file name (.png, .raw/.r16, .r32) in the resource root
+    #[structopt(long = "output", default_value = "heightmap.png")]
+    pub output: String,
+
+    /// Overwrite existing heightmap and alphamap files
+    #[structopt(long = "force")]
+    pub force: bool,
+
+    /// Open the result in the viewer
+    #[structopt(long = "view")]
+    pub view: bool,
+}
+
+impl GenerateOpts {
+    pub fn params(&self) -> GenerateParams {
+        GenerateParams {
+            algorithm: self.algorithm,
+            width: self.size.0,
+            height: self.size.1,
+            seed: self.seed,
+            octaves: self.octaves,
+            scale: self.scale,
+            persistence: self.persistence,
+            lacunarity: self.lacunarity,
+            amplitude: self.amplitude,
+        }
+    }
+}
+
+impl ErodeOpts {
+    pub fn hydraulic_params(&self) -> HydraulicParams {
+        HydraulicParams {
+            iterations: self.iterations,
+            rain: self.rain,
+            sediment_capacity: self.sediment_capacity,
+            erode_speed: self.erode_speed,
+            deposit_speed: self.deposit_speed,
+            evaporation: self.evaporation,
+            radius: self.radius,
+            ..HydraulicParams::default()
+        }
+    }
+
+    pub fn thermal_params(&self) -> ThermalParams {
+        ThermalParams {
+            iterations: self.thermal_iterations,
+            talus_angle: self.talus_angle,
+            ..ThermalParams::default()
+        }
+    }
+}
+
+impl Opts {
+    pub fn heightmap_file(&self) -> PathBuf {
+        if let Some(p) = &self.heightmap {
+            p.clone()
+        } else {
+            HEIGHTMAP_FILES
+                .iter()
+                .map(|f| self.resource_root.join(f))
+                .find(|f| f.exists())
+                .unwrap_or_else(|| self.resource_root.join(HEIGHTMAP_FILES[0]))
+        }
+    }
+
+    pub fn raw_layout(&self) -> RawLayout {
+        RawLayout {
+            dimensions: self.raw_size,
+            byte_order: self.raw_byte_order,
+        }
+    }
+
+    pub fn alphamap_file(&self) -> PathBuf {
+        self.alphamap
+            .clone()
+            .unwrap_or_else(|| self.resource_root.join("alphamap.png"))
+    }
+
+    pub fn texture_file(&self) -> PathBuf {
+        self.texture
+            .clone()
+            .unwrap_or_else(|| self.resource_root.join("texture.png"))
+    }
+}
@@ -1,38 +1,41 @@
-// TODO - some marker inidcating view/camera position and orientation
-
 use kiss3d::scene::PlanarSceneNode;
+use kiss3d::text::Font;
 use kiss3d::window::Window;
-use nalgebra::{Point2, Point3, Translation2, UnitComplex, Vector2, Vector3};
-use std::f32;
+use nalgebra::{Point2, Point3, Translation2, Vector2, Vector3};
+
+const MAX_ZOOM: f32 = 32.0;
+
+/// Texture coordinates of the rectangle vertices, the top left is (0, 0)
+const RECT_UVS: [(f32, f32); 4] = [(1.0, 0.0), (0.0, 1.0), (0.0, 0.0), (1.0, 1.0)];
 
 pub struct OrthoView {
     size: Point2<f32>,
     scale: Point2<f32>,
+    /// Distance of the view from the top right corner of the window
+    margin: f32,
+    /// Planar coordinates of the center of the view
+    anchor: Vector2<f32>,
+    /// Window size in physical pixels
+    window_size: Vector2<f32>,
+    hidpi: f32,
+    zoom: f32,
+    /// Texture coordinates shown at the center of the view
+    center: Point2<f32>,
+    /// Where a drag started and whether the cursor moved since, in planar
+    /// coordinates
+    drag: Option<(Point2<f32>, bool)>,
+    origin: Point3<f32>,
     hmap_rect: PlanarSceneNode,
     origin_rect: PlanarSceneNode,
-    cam_geom: PlanarSceneNode,
-    //loc: Translation2,
 }
 
 impl OrthoView {
     pub fn new(win: &mut Window, size: Point2<f32>, scale: Point2<f32>) -> Self {
-        // TODO - configs, check texture coordinates
         let mut hmap_rect = win.add_rectangle(size.x, size.y);
-        hmap_rect.append_translation(&Translation2::new(0.0, 0.0));
         hmap_rect.set_surface_rendering_activation(true);
         hmap_rect.set_points_size(0.0);
         hmap_rect.set_lines_width(0.0);
         hmap_rect.set_texture_with_name("heightmap");
-        hmap_rect.modify_uvs(&mut |v| {
-            v[0].x = 1.0;
-            v[0].y = 0.0;
-            v[1].x = 0.0;
-            v[1].y = 1.0;
-            v[2].x = 0.0;
-            v[2].y = 0.0;
-            v[3].x = 1.0;
-            v[3].y = 1.0;
-        });
         hmap_rect.set_visible(true);
 
         let ratio_x = 1.0 / scale.x;
@@ -45,26 +48,23 @@ impl OrthoView {
         origin_rect.set_lines_width(1.0);
         origin_rect.set_visible(true);
 
-        let dist = 20.0;
-        let points: Vec<Point2<f32>> = vec![
-            Point2::new(-dist * ratio_x, 0.7 * dist * ratio_y),
-            Point2::new(0.0, 0.0),
-            Point2::new(dist * ratio_x, 0.7 * dist * ratio_y),
-        ];
-        let mut cam_geom = hmap_rect.add_convex_polygon(points, Vector2::new(1.0, 1.0));
-        cam_geom.set_color(0.0, 0.0, 1.0);
-        cam_geom.set_surface_rendering_activation(true);
-        cam_geom.set_points_size(1.0);
-        cam_geom.set_lines_width(1.0);
-        cam_geom.set_visible(true);
-
-        Self {
+        let mut view = Self {
             size,
             scale,
+            margin: 10.0,
+            anchor: Vector2::zeros(),
+            window_size: Vector2::new(win.width() as f32, win.height() as f32),
+            hidpi: win.hidpi_factor() as f32,
+            zoom: 1.0,
+            center: Point2::new(0.5, 0.5),
+            drag: None,
+            origin: Point3::origin(),
             hmap_rect,
             origin_rect,
-            cam_geom,
-        }
+        };
+        view.update_uvs();
+        view.set_window_size(win.width(), win.height(), win.hidpi_factor());
+        view
     }
 
     pub fn is_visible(&self) -> bool {
@@ -74,33 +74,341 @@ impl OrthoView {
     pub fn set_visible(&mut self, visible: bool) {
         self.hmap_rect.set_visible(visible);
         self.origin_rect.set_visible(visible);
-        self.cam_geom.set_visible(visible);
+        self.drag = None;
+    }
+
+    /// Show a named texture, i.e. the one of the terrain mode
+    pub fn set_texture(&mut self, name: &str) {
+        self.hmap_rect.set_texture_with_name(name);
+    }
+
+    /// Keep the view in the top right corner of a window of the given
+    /// physical size
+    pub fn set_window_size(&mut self, width: u32, height: u32, hidpi: f64) {
+        self.window_size = Vector2::new(width as f32, height as f32);
+        self.hidpi = hidpi as f32;
+
+        // Planar coordinates are logical pixels from the center of the window
+        let half = self.window_size / (2.0 * self.hidpi);
+        self.anchor = Vector2::new(
+            half.x - self.size.x / 2.0 - self.margin,
+            half.y - self.size.y / 2.0 - self.margin,
+        );
+        self.hmap_rect
+            .set_local_translation(Translation2::new(self.anchor.x, self.anchor.y));
     }
 
     pub fn set_origin_position(&mut self, p: &Point3<f32>) {
+        self.origin = *p;
         let (x, y) = self.constrained_scale_xy(p.x, p.z);
         self.origin_rect
             .set_local_translation(Translation2::new(x, y));
     }
 
-    pub fn set_cam_position(&mut self, p: &Point3<f32>) {
-        let (x, y) = self.constrained_scale_xy(p.x, p.z);
-        self.cam_geom.set_local_translation(Translation2::new(x, y));
+    /// Planar coordinates of a cursor position in physical window pixels
+    pub fn cursor_to_planar(&self, cursor: &Point2<f32>) -> Point2<f32> {
+        Point2::new(
+            (cursor.x - self.window_size.x / 2.0) / self.hidpi,
+            (self.window_size.y / 2.0 - cursor.y) / self.hidpi,
+        )
+    }
+
+    /// Whether the view is visible and covers a planar point
+    pub fn contains(&self, p: &Point2<f32>) -> bool {
+        let d = p - Point2::from(self.anchor);
+        self.is_visible() && d.x.abs() <= self.size.x / 2.0 && d.y.abs() <= self.size.y / 2.0
+    }
+
+    /// Scene (x, z) coordinates shown at a planar point
+    pub fn planar_to_scene(&self, p: &Point2<f32>) -> (f32, f32) {
+        let d = p - Point2::from(self.anchor);
+        let u = self.center.x + d.x / (self.size.x * self.zoom);
+        let v = self.center.y - d.y / (self.size.y * self.zoom);
+        ((0.5 - u) * self.scale.x, (0.5 - v) * self.scale.y)
+    }
+
+    pub fn zoom(&self) -> f32 {
+        self.zoom
+    }
+
+    /// Zoom in (positive steps) or out, keeping the point under the planar
+    /// point in place
+    pub fn zoom_at(&mut self, p: &Point2<f32>, steps: f32) {
+        let d = p - Point2::from(self.anchor);
+        let fixed = Point2::new(
+            self.center.x + d.x / (self.size.x * self.zoom),
+            self.center.y - d.y / (self.size.y * self.zoom),
+        );
+
+        self.zoom = (self.zoom * 1.25f32.powf(steps)).clamp(1.0, MAX_ZOOM);
+        self.center = Point2::new(
+            fixed.x - d.x / (self.size.x * self.zoom),
+            fixed.y + d.y / (self.size.y * self.zoom),
+        );
+        self.update_uvs();
+    }
+
+    /// Start dragging at a planar point
+    pub fn start_drag(&mut self, p: &Point2<f32>) {
+        self.drag = Some((*p, false));
+    }
+
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
+    /// Pan the view along with the cursor, small moves still count as a click
+    pub fn drag_to(&mut self, p: &Point2<f32>) {
+        if let Some((last, moved)) = self.drag {
+            let d = p - last;
+            if !moved && d.norm() < 3.0 {
+                return;
+            }
+            self.center.x -= d.x / (self.size.x * self.zoom);
+            self.center.y += d.y / (self.size.y * self.zoom);
+            self.drag = Some((*p, true));
+            self.update_uvs();
+        }
+    }
+
+    /// Stop dragging, the scene (x, z) coordinates of the click if the cursor
+    /// didn't move
+    pub fn end_drag(&mut self) -> Option<(f32, f32)> {
+        match self.drag.take() {
+            Some((p, false)) => Some(self.planar_to_scene(&p)),
+            _ => None,
+        }
+    }
+
+    /// Draw a line between two points in the scene, projected onto the view
+    /// and clipped to it
+    pub fn draw_line(
+        &self,
+        win: &mut Window,
+        a: &Point3<f32>,
+        b: &Point3<f32>,
+        color: &Point3<f32>,
+    ) {
+        let a = self.scale_xy(a.x, a.z);
+        let b = self.scale_xy(b.x, b.z);
+        let half = Vector2::new(self.size.x / 2.0, self.size.y / 2.0);
+
+        if let Some((a, b)) = clip(a, b, half) {
+            win.draw_planar_line(&(a + self.anchor), &(b + self.anchor), color);
+        }
+    }
+
+    /// Draw a cross at a point in the scene, when it's within the view
+    pub fn draw_marker(&self, win: &mut Window, p: &Point3<f32>, color: &Point3<f32>) {
+        if let Some(c) = self.project(p) {
+            let r = 4.0;
+            win.draw_planar_line(
+                &Point2::new(c.x - r, c.y - r),
+                &Point2::new(c.x + r, c.y + r),
+                color,
+            );
+            win.draw_planar_line(
+                &Point2::new(c.x - r, c.y + r),
+                &Point2::new(c.x + r, c.y - r),
+                color,
+            );
+        }
+    }
+
+    /// Draw text centered on a point in the scene, when it's within the view
+    pub fn draw_label(
+        &self,
+        win: &mut Window,
+        p: &Point3<f32>,
+        text: &str,
+        font_size: f32,
+        color: &Point3<f32>,
+    ) {
+        if let Some(c) = self.project(p) {
+            draw_planar_text(
+                win,
+                &c,
+                text,
+                font_size,
+                color,
+                self.hidpi,
+                &self.window_size,
+            );
+        }
     }
 
-    pub fn set_cam_orientation(&mut self, eye_dir: &Vector3<f32>) {
-        let angle = eye_dir.z.atan2(eye_dir.x) - f32::consts::FRAC_PI_2;
-        self.cam_geom.set_local_rotation(UnitComplex::new(-angle));
+    /// Draw the camera as a wedge spanning its horizontal field of view, out
+    /// to the terrain point it looks at or the edge of the terrain, with its
+    /// height above the terrain next to it
+    ///
+    /// The camera is kept at the edge of the view when it's outside.
+    pub fn draw_camera(
+        &self,
+        win: &mut Window,
+        eye: &Point3<f32>,
+        eye_dir: &Vector3<f32>,
+        hfov: f32,
+        target: Option<&Point3<f32>>,
+        height: Option<f32>,
+    ) {
+        let color = Point3::new(0.3, 0.6, 1.0);
+
+        let dir = Vector2::new(eye_dir.x, eye_dir.z);
+        if dir.norm() > 1.0e-3 {
+            let dir = dir.normalize();
+            let depth = match target {
+                Some(t) => Vector2::new(t.x - eye.x, t.z - eye.z).norm(),
+                None => self.scale.x.max(self.scale.y),
+            };
+            let half = hfov / 2.0;
+            let edge = |angle: f32| {
+                let (sin, cos) = angle.sin_cos();
+                let d = Vector2::new(dir.x * cos - dir.y * sin, dir.x * sin + dir.y * cos)
+                    * (depth / half.cos());
+                Point3::new(eye.x + d.x, eye.y, eye.z + d.y)
+            };
+            let (left, right) = (edge(half), edge(-half));
+
+            self.draw_line(win, eye, &left, &color);
+            self.draw_line(win, eye, &right, &color);
+            self.draw_line(win, &left, &right, &color);
+        }
+        if let Some(t) = target {
+            self.draw_marker(win, t, &color);
+        }
+
+        let (x, y) = self.constrained_scale_xy(eye.x, eye.z);
+        let c = Point2::new(x, y) + self.anchor;
+        let r = 4.0;
+        let corners = [
+            Point2::new(c.x - r, c.y - r),
+            Point2::new(c.x + r, c.y - r),
+            Point2::new(c.x + r, c.y + r),
+            Point2::new(c.x - r, c.y + r),
+        ];
+        for i in 0..4 {
+            win.draw_planar_line(&corners[i], &corners[(i + 1) % 4], &color);
+        }
+
+        if let Some(height) = height {
+            let pos = Point2::new(c.x, c.y - 3.0 * r);
+            let text = format!("{:.1}", height);
+            draw_planar_text(
+                win,
+                &pos,
+                &text,
+                25.0,
+                &color,
+                self.hidpi,
+                &self.window_size,
+            );
+        }
+    }
+
+    /// Planar length of a scene unit at the current zoom
+    pub fn units_to_planar(&self) -> f32 {
+        self.size.x * self.zoom / self.scale.x
+    }
+
+    /// Planar coordinates of a point in the scene, None outside of the view
+    fn project(&self, p: &Point3<f32>) -> Option<Point2<f32>> {
+        let c = self.scale_xy(p.x, p.z);
+        if c.x.abs() <= self.size.x / 2.0 && c.y.abs() <= self.size.y / 2.0 {
+            Some(c + self.anchor)
+        } else {
+            None
+        }
+    }
+
+    /// Show the zoomed and panned part of the texture
+    fn update_uvs(&mut self) {
+        let half = 0.5 / self.zoom;
+        self.center.x = constrain(self.center.x, half, 1.0 - half);
+        self.center.y = constrain(self.center.y, half, 1.0 - half);
+
+        let (center, zoom) = (self.center, self.zoom);
+        // Only the rectangle, its children have no texture
+        self.hmap_rect.data_mut().modify_uvs(&mut |v| {
+            for (uv, base) in v.iter_mut().zip(RECT_UVS.iter()) {
+                uv.x = center.x + (base.0 - 0.5) / zoom;
+                uv.y = center.y + (base.1 - 0.5) / zoom;
+            }
+        });
+
+        let origin = self.origin;
+        self.set_origin_position(&origin);
+    }
+
+    /// Scene (x, z) coordinates to coordinates relative to the center of the
+    /// view
+    fn scale_xy(&self, x: f32, y: f32) -> Point2<f32> {
+        let u = 0.5 - x / self.scale.x;
+        let v = 0.5 - y / self.scale.y;
+        Point2::new(
+            (u - self.center.x) * self.size.x * self.zoom,
+            (self.center.y - v) * self.size.y * self.zoom,
+        )
     }
 
     fn constrained_scale_xy(&self, x: f32, y: f32) -> (f32, f32) {
-        let mx = x * (self.size.x / self.scale.x);
-        let my = y * (self.size.y / self.scale.y);
+        let p = self.scale_xy(x, y);
+        let hx = self.size.x / 2.0;
+        let hy = self.size.y / 2.0;
 
-        let hx = (self.size.x / self.scale.x) * (self.scale.x / 2.0);
-        let hy = (self.size.y / self.scale.y) * (self.scale.y / 2.0);
+        (constrain(p.x, -hx, hx), constrain(p.y, -hy, hy))
+    }
+}
+
+/// Draw text centered on a planar point
+fn draw_planar_text(
+    win: &mut Window,
+    p: &Point2<f32>,
+    text: &str,
+    font_size: f32,
+    color: &Point3<f32>,
+    hidpi: f32,
+    window_size: &Vector2<f32>,
+) {
+    // Text coordinates are twice the physical pixels from the top left corner
+    // of the window
+    let x = 2.0 * (p.x * hidpi + window_size.x / 2.0);
+    let y = 2.0 * (window_size.y / 2.0 - p.y * hidpi);
+    let pos = Point2::new(
+        x - 0.25 * font_size * text.len() as f32,
+        y - 0.5 * font_size,
+    );
+    win.draw_text(text, &pos, font_size, &Font::default(), color);
+}
+
+/// Clip a line to the rectangle centered at the origin (Liang-Barsky)
+fn clip(a: Point2<f32>, b: Point2<f32>, half: Vector2<f32>) -> Option<(Point2<f32>, Point2<f32>)> {
+    let d = b - a;
+    let (mut t0, mut t1) = (0.0f32, 1.0f32);
 
-        (constrain(-mx, -hx, hx), constrain(my, -hy, hy))
+    for (p, q) in &[
+        (-d.x, a.x + half.x),
+        (d.x, half.x - a.x),
+        (-d.y, a.y + half.y),
+        (d.y, half.y - a.y),
+    ] {
+        if *p == 0.0 {
+            if *q < 0.0 {
+                return None;
+            }
+        } else {
+            let t = q / p;
+            if *p < 0.0 {
+                t0 = t0.max(t);
+            } else {
+                t1 = t1.min(t);
+            }
+        }
+    }
+
+    if t0 > t1 {
+        None
+    } else {
+        Some((a + d * t0, a + d * t1))
     }
 }
 
@@ -113,3 +421,47 @@ fn constrain(v: f32, min: f32, max: f32) -> f32 {
         v
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn half() -> Vector2<f32> {
+        Vector2::new(10.0, 5.0)
+    }
+
+    #[test]
+    fn clip_keeps_lines_inside() {
+        let (a, b) = (Point2::new(-3.0, 1.0), Point2::new(4.0, -2.0));
+        assert_eq!(clip(a, b, half()), Some((a, b)));
+    }
+
+    #[test]
+    fn clip_cuts_lines_at_the_edges() {
+        let clipped = clip(Point2::new(-20.0, 0.0), Point2::new(20.0, 0.0), half());
+        assert_eq!(
+            clipped,
+            Some((Point2::new(-10.0, 0.0), Point2::new(10.0, 0.0)))
+        );
+
+        let clipped = clip(Point2::new(0.0, 0.0), Point2::new(0.0, 10.0), half());
+        assert_eq!(
+            clipped,
+            Some((Point2::new(0.0, 0.0), Point2::new(0.0, 5.0)))
+        );
+    }
+
+    #[test]
+    fn clip_drops_lines_outside() {
+        // Parallel to and beyond an edge
+        assert_eq!(
+            clip(Point2::new(-20.0, 6.0), Point2::new(20.0, 6.0), half()),
+            None
+        );
+        // Diagonal past a corner
+        assert_eq!(
+            clip(Point2::new(8.0, 8.0), Point2::new(14.0, 2.0), half()),
+            None
+        );
+    }
+}
@@ -0,0 +1,390 @@
+use crate::geo_transform::GeoTransform;
+use crate::heightmap::Error;
+use crate::raster::Raster;
+use image::{ImageBuffer, Rgb, RgbImage};
+use las::point::Classification;
+use las::{Read, Reader};
+use std::f32;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Which points contribute to the elevation grid
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PointClasses {
+    /// Only points classified as ground (class 2), bare earth model
+    Ground,
+    /// Every point, surface model including vegetation/buildings
+    All,
+}
+
+/// How the points falling into a grid cell are combined
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BinMode {
+    Min,
+    Max,
+    Mean,
+}
+
+/// Optional color texture rasterized along with the elevations
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum TextureSource {
+    Intensity,
+    Rgb,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RasterizeParams {
+    /// Grid cell size in point cloud units (meters)
+    pub resolution: f64,
+    pub classes: PointClasses,
+    pub bin_mode: BinMode,
+    pub texture: Option<TextureSource>,
+}
+
+/// Largest grid rasterized, 4096 x 4096 cells
+pub const MAX_CELLS: usize = 1 << 24;
+
+/// Point as it's binned, color is None for points without one
+struct Sample {
+    x: f64,
+    y: f64,
+    z: f64,
+    color: Option<[f64; 3]>,
+}
+
+/// Per-cell accumulator, sums are kept in f64 so large absolute elevations
+/// don't lose precision
+#[derive(Copy, Clone, Default)]
+struct Cell {
+    count: u32,
+    z: f64,
+    /// Number of points with a color
+    color_count: u32,
+    channels: [f64; 3],
+}
+
+/// Parse a grid cell size, it has to be positive
+pub fn parse_resolution(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(resolution) if resolution > 0.0 && resolution.is_finite() => Ok(resolution),
+        _ => Err(format!("Invalid resolution '{}', use a positive number", s)),
+    }
+}
+
+/// Bin the points of a LAS file into a grid, empty cells are filled from
+/// their neighbors
+///
+/// Fails with `Error::InvalidDimensions` if the grid would have more than
+/// `MAX_CELLS` cells.
+pub fn rasterize(
+    file_path: &Path,
+    params: &RasterizeParams,
+) -> Result<(Raster, Option<RgbImage>), Error> {
+    if params.resolution.is_nan() || params.resolution <= 0.0 {
+        return Err(Error::InvalidDimensions);
+    }
+
+    let mut reader = Reader::from_path(file_path)?;
+    let bounds = reader.header().bounds();
+
+    let (width, height) = grid_size(
+        (bounds.max.x - bounds.min.x, bounds.max.y - bounds.min.y),
+        params.resolution,
+    )?;
+    let geo_transform = GeoTransform {
+        origin_x: bounds.min.x,
+        origin_y: bounds.max.y,
+        pixel_width: params.resolution,
+        pixel_height: -params.resolution,
+        geographic: false,
+    };
+
+    let samples = reader.points().filter_map(|point| {
+        let point = match point {
+            Ok(point) => point,
+            Err(e) => return Some(Err(Error::from(e))),
+        };
+        if params.classes == PointClasses::Ground && point.classification != Classification::Ground
+        {
+            return None;
+        }
+
+        let color = match params.texture {
+            Some(TextureSource::Intensity) => Some([f64::from(point.intensity), 0.0, 0.0]),
+            Some(TextureSource::Rgb) => point
+                .color
+                .map(|c| [f64::from(c.red), f64::from(c.green), f64::from(c.blue)]),
+            None => None,
+        };
+        Some(Ok(Sample {
+            x: point.x,
+            y: point.y,
+            z: point.z,
+            color,
+        }))
+    });
+
+    let cells = bin(samples, &geo_transform, width, height, params.bin_mode)?;
+    reduce(&cells, width, height, geo_transform, params)
+}
+
+/// Dimensions of a grid of `resolution` sized cells covering an (x, y)
+/// extent
+fn grid_size(extent: (f64, f64), resolution: f64) -> Result<(usize, usize), Error> {
+    let width = (extent.0 / resolution).floor() + 1.0;
+    let height = (extent.1 / resolution).floor() + 1.0;
+    if (width * height).is_nan() || width * height > MAX_CELLS as f64 {
+        return Err(Error::InvalidDimensions);
+    }
+    Ok((width as usize, height as usize))
+}
+
+/// Accumulate the samples into the cells they fall into, samples outside of
+/// the grid go to the closest edge cell
+fn bin(
+    samples: impl Iterator<Item = Result<Sample, Error>>,
+    geo_transform: &GeoTransform,
+    width: usize,
+    height: usize,
+    bin_mode: BinMode,
+) -> Result<Vec<Cell>, Error> {
+    let mut cells = vec![Cell::default(); width * height];
+
+    for sample in samples {
+        let sample = sample?;
+
+        // Rows run north to south
+        let x = ((sample.x - geo_transform.origin_x) / geo_transform.pixel_width) as usize;
+        let y = ((sample.y - geo_transform.origin_y) / geo_transform.pixel_height) as usize;
+        let cell = &mut cells[x.min(width - 1) + y.min(height - 1) * width];
+
+        cell.z = if cell.count == 0 {
+            sample.z
+        } else {
+            match bin_mode {
+                BinMode::Min => cell.z.min(sample.z),
+                BinMode::Max => cell.z.max(sample.z),
+                BinMode::Mean => cell.z + sample.z,
+            }
+        };
+        cell.count += 1;
+
+        if let Some(color) = sample.color {
+            for (sum, c) in cell.channels.iter_mut().zip(color.iter()) {
+                *sum += c;
+            }
+            cell.color_count += 1;
+        }
+    }
+    Ok(cells)
+}
+
+/// Elevation raster and texture from the binned cells, the texture is None
+/// if none of the points has a color
+fn reduce(
+    cells: &[Cell],
+    width: usize,
+    height: usize,
+    geo_transform: GeoTransform,
+    params: &RasterizeParams,
+) -> Result<(Raster, Option<RgbImage>), Error> {
+    if cells.iter().all(|c| c.count == 0) {
+        return Err(Error::InvalidFile);
+    }
+
+    // Empty cells are marked with NaN and filled
+    let band = |f: &dyn Fn(&Cell) -> Option<f64>| -> Raster {
+        let mut r = Raster {
+            width,
+            height,
+            data: cells
+                .iter()
+                .map(|c| f(c).map_or(f32::NAN, |v| v as f32))
+                .collect(),
+            geo_transform,
+            nodata: None,
+            bit_depth: None,
+        };
+        r.fill_nodata();
+        r
+    };
+
+    let elevations = band(&|c| match (c.count, params.bin_mode) {
+        (0, _) => None,
+        (n, BinMode::Mean) => Some(c.z / f64::from(n)),
+        _ => Some(c.z),
+    });
+
+    let has_color = cells.iter().any(|c| c.color_count != 0);
+    let texture = params.texture.filter(|_| has_color).map(|src| {
+        let num_channels = if src == TextureSource::Rgb { 3 } else { 1 };
+        let channels: Vec<Raster> = (0..num_channels)
+            .map(|i| {
+                band(&|c| {
+                    if c.color_count == 0 {
+                        None
+                    } else {
+                        Some(c.channels[i] / f64::from(c.color_count))
+                    }
+                })
+            })
+            .collect();
+
+        // Colors are stored as 16 bit but often only use the lower 8 bits,
+        // stretch to the actual maximum instead
+        let max = channels.iter().map(|r| r.min_max().1).fold(1.0, f32::max);
+
+        ImageBuffer::from_fn(width as _, height as _, |x, y| {
+            let idx = x as usize + y as usize * width;
+            let v = |c: &Raster| ((c.data[idx] / max) * 255.0).round() as u8;
+            if num_channels == 3 {
+                Rgb([v(&channels[0]), v(&channels[1]), v(&channels[2])])
+            } else {
+                let l = v(&channels[0]);
+                Rgb([l, l, l])
+            }
+        })
+    });
+
+    Ok((elevations, texture))
+}
+
+impl FromStr for PointClasses {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ground" => Ok(PointClasses::Ground),
+            "all" => Ok(PointClasses::All),
+            _ => Err(format!("Invalid point classes '{}', use ground or all", s)),
+        }
+    }
+}
+
+impl FromStr for BinMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "min" => Ok(BinMode::Min),
+            "max" => Ok(BinMode::Max),
+            "mean" => Ok(BinMode::Mean),
+            _ => Err(format!("Invalid bin mode '{}', use min, max or mean", s)),
+        }
+    }
+}
+
+impl FromStr for TextureSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "intensity" => Ok(TextureSource::Intensity),
+            "rgb" => Ok(TextureSource::Rgb),
+            _ => Err(format!(
+                "Invalid texture source '{}', use intensity or rgb",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 3x2 grid of 1 m cells with its top left corner at (100, 1000)
+    const GEO_TRANSFORM: GeoTransform = GeoTransform {
+        origin_x: 100.0,
+        origin_y: 1000.0,
+        pixel_width: 1.0,
+        pixel_height: -1.0,
+        geographic: false,
+    };
+
+    fn params(bin_mode: BinMode) -> RasterizeParams {
+        RasterizeParams {
+            resolution: 1.0,
+            classes: PointClasses::All,
+            bin_mode,
+            texture: Some(TextureSource::Rgb),
+        }
+    }
+
+    fn sample(x: f64, y: f64, z: f64, color: Option<[f64; 3]>) -> Result<Sample, Error> {
+        Ok(Sample { x, y, z, color })
+    }
+
+    /// Two points in the top left cell, one in the bottom right, the rest
+    /// are empty
+    fn samples() -> Vec<Result<Sample, Error>> {
+        vec![
+            sample(100.2, 999.5, 4000.25, Some([200.0, 100.0, 0.0])),
+            sample(100.7, 999.9, 4000.75, None),
+            sample(102.5, 998.5, 4002.0, Some([100.0, 100.0, 100.0])),
+        ]
+    }
+
+    fn rasterize_samples(bin_mode: BinMode) -> (Raster, Option<RgbImage>) {
+        let cells = bin(samples().into_iter(), &GEO_TRANSFORM, 3, 2, bin_mode).unwrap();
+        reduce(&cells, 3, 2, GEO_TRANSFORM, &params(bin_mode)).unwrap()
+    }
+
+    #[test]
+    fn bins_points_into_cells() {
+        let cells = bin(samples().into_iter(), &GEO_TRANSFORM, 3, 2, BinMode::Mean).unwrap();
+        let counts: Vec<u32> = cells.iter().map(|c| c.count).collect();
+        assert_eq!(counts, vec![2, 0, 0, 0, 0, 1]);
+        // The point without a color doesn't count towards the color mean
+        assert_eq!(cells[0].color_count, 1);
+    }
+
+    #[test]
+    fn reduces_cells_by_bin_mode() {
+        assert_eq!(rasterize_samples(BinMode::Mean).0.data[0], 4000.5);
+        assert_eq!(rasterize_samples(BinMode::Min).0.data[0], 4000.25);
+        assert_eq!(rasterize_samples(BinMode::Max).0.data[0], 4000.75);
+        assert_eq!(rasterize_samples(BinMode::Max).0.data[5], 4002.0);
+    }
+
+    #[test]
+    fn fills_empty_cells() {
+        let (raster, texture) = rasterize_samples(BinMode::Mean);
+        assert!(raster.data.iter().all(|e| e.is_finite()));
+        // Middle cells average both filled corners
+        assert_eq!(raster.data[1], 4001.25);
+        assert_eq!(raster.data[2], 4002.0);
+        assert_eq!(raster.data[3], 4000.5);
+
+        // Colors are stretched to the brightest channel, without darkening
+        // from the uncolored point
+        let texture = texture.unwrap();
+        assert_eq!(texture.get_pixel(0, 0), &Rgb([255, 128, 0]));
+        assert_eq!(texture.get_pixel(2, 1), &Rgb([128, 128, 128]));
+    }
+
+    #[test]
+    fn no_texture_without_colors() {
+        let samples = vec![sample(100.5, 999.5, 1.0, None)];
+        let cells = bin(samples.into_iter(), &GEO_TRANSFORM, 3, 2, BinMode::Mean).unwrap();
+        let (_, texture) = reduce(&cells, 3, 2, GEO_TRANSFORM, &params(BinMode::Mean)).unwrap();
+        assert!(texture.is_none());
+    }
+
+    #[test]
+    fn rejects_huge_grids() {
+        assert_eq!(grid_size((2.0, 1.5), 1.0), Ok((3, 2)));
+        assert_eq!(
+            grid_size((1000.0, 1000.0), 0.01),
+            Err(Error::InvalidDimensions)
+        );
+    }
+
+    #[test]
+    fn parse_resolution_rejects_non_positive() {
+        assert_eq!(parse_resolution("0.5"), Ok(0.5));
+        assert!(parse_resolution("0").is_err());
+        assert!(parse_resolution("-1").is_err());
+        assert!(parse_resolution("NaN").is_err());
+    }
+}
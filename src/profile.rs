@@ -0,0 +1,136 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+/// Point along an elevation profile
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ProfileSample {
+    /// Horizontal distance from the start, in meters
+    pub distance: f64,
+    pub pixel: (f64, f64),
+    /// World coordinates
+    pub world: (f64, f64),
+    /// Source elevation
+    pub elevation: f32,
+    /// One of the measured points, not a sample in between
+    pub vertex: bool,
+}
+
+/// Distances between the measured points of a profile
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Measurement {
+    /// Along the ground plane, in meters
+    pub horizontal: f64,
+    /// Straight lines between the points, in meters
+    pub distance_3d: f64,
+    /// Last minus first elevation, in meters
+    pub elevation_diff: f32,
+}
+
+impl Measurement {
+    /// Measure between the vertices of a profile, vertical distances are
+    /// source elevations times `meters_per_unit`
+    pub fn from_profile(samples: &[ProfileSample], meters_per_unit: f32) -> Self {
+        let vertices: Vec<&ProfileSample> = samples.iter().filter(|s| s.vertex).collect();
+
+        let distance_3d = vertices
+            .windows(2)
+            .map(|w| {
+                let horizontal = w[1].distance - w[0].distance;
+                let vertical = f64::from((w[1].elevation - w[0].elevation) * meters_per_unit);
+                (horizontal * horizontal + vertical * vertical).sqrt()
+            })
+            .sum();
+
+        Self {
+            horizontal: vertices.last().map_or(0.0, |s| s.distance),
+            distance_3d,
+            elevation_diff: match (vertices.first(), vertices.last()) {
+                (Some(first), Some(last)) => (last.elevation - first.elevation) * meters_per_unit,
+                _ => 0.0,
+            },
+        }
+    }
+}
+
+/// Write a profile as CSV, one sample per line
+pub fn write_csv(file_path: &Path, samples: &[ProfileSample]) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(file_path)?);
+
+    writeln!(w, "distance,x,y,elevation,point")?;
+    for s in samples {
+        writeln!(
+            w,
+            "{:.3},{},{},{},{}",
+            s.distance, s.world.0, s.world.1, s.elevation, s.vertex as u8
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heightmap::Heightmap;
+    use crate::interpolation::Interpolation;
+    use std::path::Path;
+
+    /// 9x9 ramp rising one meter per pixel along x, a 45 degree slope
+    fn ramp() -> Heightmap {
+        let elevations = (0..81).map(|i| (i % 9) as f32 * 0.1).collect();
+        let hmap = Heightmap::from_normalized(Path::new("ramp.png"), 9, 9, elevations).unwrap();
+        assert_eq!(hmap.meters_per_unit(), 10.0);
+        hmap
+    }
+
+    fn measure(hmap: &Heightmap, pixels: &[(f32, f32)]) -> (Vec<ProfileSample>, Measurement) {
+        let points: Vec<_> = pixels
+            .iter()
+            .map(|p| hmap.pixel_to_mesh(p.0, p.1, 0.0))
+            .collect();
+        let samples = hmap.profile(&points, Interpolation::Bilinear);
+        let m = Measurement::from_profile(&samples, hmap.meters_per_unit());
+        (samples, m)
+    }
+
+    #[test]
+    fn measures_up_the_ramp() {
+        let hmap = ramp();
+        let (samples, m) = measure(&hmap, &[(0.0, 4.0), (8.0, 4.0)]);
+
+        assert_eq!(samples.len(), 9);
+        for (i, s) in samples.iter().enumerate() {
+            assert!((s.distance - i as f64).abs() < 1e-6);
+            assert!((s.elevation * 10.0 - i as f32).abs() < 1e-4);
+        }
+
+        assert!((m.horizontal - 8.0).abs() < 1e-6);
+        assert!((m.elevation_diff - 8.0).abs() < 1e-4);
+        assert!((m.distance_3d - 8.0 * 2f64.sqrt()).abs() < 1e-4);
+        let slope = f64::from(m.elevation_diff).atan2(m.horizontal).to_degrees();
+        assert!((slope - 45.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn measures_between_every_point() {
+        let hmap = ramp();
+        // Halfway up the ramp, then across it on level ground
+        let (samples, m) = measure(&hmap, &[(0.0, 4.0), (4.0, 4.0), (4.0, 0.0)]);
+
+        assert_eq!(samples.iter().filter(|s| s.vertex).count(), 3);
+        assert!((m.horizontal - 8.0).abs() < 1e-6);
+        assert!((m.elevation_diff - 4.0).abs() < 1e-4);
+        assert!((m.distance_3d - (4.0 * 2f64.sqrt() + 4.0)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn single_point_measures_nothing() {
+        let hmap = ramp();
+        let (samples, m) = measure(&hmap, &[(2.0, 2.0)]);
+
+        assert_eq!(samples.len(), 1);
+        assert_eq!(m.horizontal, 0.0);
+        assert_eq!(m.distance_3d, 0.0);
+        assert_eq!(m.elevation_diff, 0.0);
+    }
+}
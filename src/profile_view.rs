@@ -0,0 +1,82 @@
+use crate::profile::ProfileSample;
+use kiss3d::window::Window;
+use nalgebra::{Point2, Point3, Vector2};
+
+/// Elevation profile graph along the bottom of the window
+pub struct ProfileView {
+    size: Point2<f32>,
+    margin: f32,
+    /// Window size in logical pixels, the units of planar coordinates
+    window_size: Vector2<f32>,
+}
+
+impl ProfileView {
+    pub fn new(win: &Window, size: Point2<f32>) -> Self {
+        let mut view = Self {
+            size,
+            margin: 20.0,
+            window_size: Vector2::zeros(),
+        };
+        view.set_window_size(win.width(), win.height(), win.hidpi_factor());
+        view
+    }
+
+    /// Keep the graph along the bottom of a window of the given physical
+    /// size
+    pub fn set_window_size(&mut self, width: u32, height: u32, hidpi: f64) {
+        self.window_size = Vector2::new(width as f32, height as f32) / hidpi as f32;
+    }
+
+    /// Draw the profile scaled to fit the graph, with the measured points
+    /// marked by vertical lines
+    pub fn draw(&self, win: &mut Window, samples: &[ProfileSample]) {
+        if samples.len() < 2 {
+            return;
+        }
+
+        // Planar coordinates have the origin at the center of the window
+        let width = self.size.x.min(self.window_size.x - 2.0 * self.margin);
+        let left = -width / 2.0;
+        let bottom = -self.window_size.y / 2.0 + self.margin;
+        let (right, top) = (left + width, bottom + self.size.y);
+
+        let frame_color = Point3::new(0.6, 0.6, 0.6);
+        let corners = [
+            Point2::new(left, bottom),
+            Point2::new(right, bottom),
+            Point2::new(right, top),
+            Point2::new(left, top),
+        ];
+        for i in 0..4 {
+            win.draw_planar_line(&corners[i], &corners[(i + 1) % 4], &frame_color);
+        }
+
+        let length = samples[samples.len() - 1].distance.max(f64::EPSILON);
+        let (min, max) = samples.iter().fold((f32::MAX, f32::MIN), |(min, max), s| {
+            (min.min(s.elevation), max.max(s.elevation))
+        });
+        let range = if max > min { max - min } else { 1.0 };
+
+        let point = |s: &ProfileSample| {
+            Point2::new(
+                left + (s.distance / length) as f32 * width,
+                bottom + (s.elevation - min) / range * self.size.y,
+            )
+        };
+
+        let vertex_color = Point3::new(0.0, 1.0, 1.0);
+        for s in samples.iter().filter(|s| s.vertex) {
+            let p = point(s);
+            win.draw_planar_line(
+                &Point2::new(p.x, bottom),
+                &Point2::new(p.x, top),
+                &vertex_color,
+            );
+        }
+
+        let line_color = Point3::new(1.0, 1.0, 0.0);
+        for pair in samples.windows(2) {
+            win.draw_planar_line(&point(&pair[0]), &point(&pair[1]), &line_color);
+        }
+    }
+}
@@ -0,0 +1,149 @@
+use crate::geo_transform::GeoTransform;
+
+/// Single band elevation raster, as read from a DEM file
+pub struct Raster {
+    pub width: usize,
+    pub height: usize,
+    /// Row-major elevations, top row first
+    pub data: Vec<f32>,
+    pub geo_transform: GeoTransform,
+    pub nodata: Option<f32>,
+    /// Bits per sample in the file, None for text formats
+    pub bit_depth: Option<u8>,
+}
+
+impl Raster {
+    fn is_nodata(&self, v: f32) -> bool {
+        !v.is_finite() || self.nodata == Some(v)
+    }
+
+    /// Fill nodata cells with the average of their valid neighbors,
+    /// growing inwards from the edges of each hole
+    ///
+    /// Returns false if the raster does not contain any valid cells.
+    pub fn fill_nodata(&mut self) -> bool {
+        let (width, height) = (self.width, self.height);
+        let mut valid: Vec<bool> = self.data.iter().map(|&v| !self.is_nodata(v)).collect();
+
+        if !valid.iter().any(|&v| v) {
+            return false;
+        }
+
+        // Each pass fills the holes' cells next to a valid one, then queues
+        // their unfilled neighbors for the next pass
+        let mut queued = valid.clone();
+        let mut frontier: Vec<usize> = (0..self.data.len())
+            .filter(|&i| {
+                !valid[i]
+                    && neighbors(i % width, i / width, width, height)
+                        .any(|(nx, ny)| valid[nx + ny * width])
+            })
+            .collect();
+        for &i in &frontier {
+            queued[i] = true;
+        }
+
+        while !frontier.is_empty() {
+            let filled: Vec<(usize, f32)> = frontier
+                .iter()
+                .map(|&i| {
+                    let mut sum = 0.0;
+                    let mut count = 0;
+                    for (nx, ny) in neighbors(i % width, i / width, width, height) {
+                        let idx = nx + ny * width;
+                        if valid[idx] {
+                            sum += self.data[idx];
+                            count += 1;
+                        }
+                    }
+                    (i, sum / count as f32)
+                })
+                .collect();
+
+            let mut next = Vec::new();
+            for (i, v) in filled {
+                self.data[i] = v;
+                valid[i] = true;
+                for (nx, ny) in neighbors(i % width, i / width, width, height) {
+                    let idx = nx + ny * width;
+                    if !queued[idx] {
+                        queued[idx] = true;
+                        next.push(idx);
+                    }
+                }
+            }
+            frontier = next;
+        }
+
+        self.nodata = None;
+
+        true
+    }
+
+    /// Minimum and maximum elevations
+    pub fn min_max(&self) -> (f32, f32) {
+        self.data
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(min, max), &v| {
+                (min.min(v), max.max(v))
+            })
+    }
+}
+
+/// The 8-connected neighbors of (x, y) within the grid
+pub fn neighbors(
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+) -> impl Iterator<Item = (usize, usize)> {
+    let (x, y) = (x as isize, y as isize);
+    (-1..=1)
+        .flat_map(move |dy| (-1..=1).map(move |dx| (x + dx, y + dy)))
+        .filter(move |&(nx, ny)| {
+            (nx, ny) != (x, y) && nx >= 0 && ny >= 0 && nx < width as _ && ny < height as _
+        })
+        .map(|(nx, ny)| (nx as usize, ny as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raster(width: usize, height: usize, data: Vec<f32>) -> Raster {
+        Raster {
+            width,
+            height,
+            data,
+            geo_transform: GeoTransform::default(),
+            nodata: Some(-9999.0),
+            bit_depth: None,
+        }
+    }
+
+    #[test]
+    fn fill_nodata_grows_inwards() {
+        // A 3 wide hole in a row, the middle cell is filled last from its
+        // already filled neighbors
+        let mut r = raster(5, 1, vec![1.0, -9999.0, -9999.0, -9999.0, 3.0]);
+        assert!(r.fill_nodata());
+        assert_eq!(r.data, vec![1.0, 1.0, 2.0, 3.0, 3.0]);
+        assert_eq!(r.nodata, None);
+    }
+
+    #[test]
+    fn fill_nodata_fills_large_voids() {
+        let (w, h) = (200, 150);
+        let mut data = vec![f32::NAN; w * h];
+        data[0] = 5.0;
+        let mut r = raster(w, h, data);
+        assert!(r.fill_nodata());
+        assert!(r.data.iter().all(|&v| v == 5.0));
+    }
+
+    #[test]
+    fn fill_nodata_without_valid_cells() {
+        let mut r = raster(2, 2, vec![-9999.0; 4]);
+        assert!(!r.fill_nodata());
+    }
+}
@@ -0,0 +1,226 @@
+use crate::heightmap::Error;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum ByteOrder {
+    #[default]
+    Little,
+    Big,
+}
+
+impl FromStr for ByteOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "little" | "le" => Ok(ByteOrder::Little),
+            "big" | "be" => Ok(ByteOrder::Big),
+            _ => Err(format!("Invalid byte order '{}', use little or big", s)),
+        }
+    }
+}
+
+/// Sample format, selected by the file extension
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RawFormat {
+    /// .raw/.r16, unsigned 16 bit samples spanning the full height range
+    U16,
+    /// .r32, 32 bit float samples
+    F32,
+}
+
+impl RawFormat {
+    pub fn from_path(file_path: &Path) -> Option<Self> {
+        let ext = file_path.extension()?.to_str()?.to_lowercase();
+        match ext.as_str() {
+            "raw" | "r16" => Some(RawFormat::U16),
+            "r32" => Some(RawFormat::F32),
+            _ => None,
+        }
+    }
+
+    fn sample_size(self) -> usize {
+        match self {
+            RawFormat::U16 => 2,
+            RawFormat::F32 => 4,
+        }
+    }
+}
+
+/// Raw files are headerless, the layout has to be provided
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct RawLayout {
+    /// (width, height) in samples, a square grid is assumed when not provided
+    pub dimensions: Option<(usize, usize)>,
+    pub byte_order: ByteOrder,
+}
+
+/// Parse "WIDTHxHEIGHT", i.e. 1025x1025
+pub fn parse_dimensions(s: &str) -> Result<(usize, usize), String> {
+    let err = || format!("Invalid dimensions '{}', use WIDTHxHEIGHT", s);
+    let mut parts = s.split(['x', 'X']);
+    let w: usize = parts.next().ok_or_else(err)?.parse().map_err(|_| err())?;
+    let h = parts.next().ok_or_else(err)?.parse().map_err(|_| err())?;
+    if parts.next().is_some() {
+        return Err(err());
+    }
+    // Largest samples are 4 bytes, the file size has to fit as well
+    let bytes = w
+        .checked_mul(h)
+        .and_then(|n| n.checked_mul(RawFormat::F32.sample_size()));
+    if bytes.is_none() {
+        return Err(format!("Dimensions '{}' are too large", s));
+    }
+    Ok((w, h))
+}
+
+/// Read a raw file, returns (width, height, samples)
+///
+/// U16 samples are normalized to [0, 1], F32 samples are returned as-is.
+pub fn read_file(
+    file_path: &Path,
+    format: RawFormat,
+    layout: RawLayout,
+) -> Result<(usize, usize, Vec<f32>), Error> {
+    let bytes = fs::read(file_path)?;
+
+    let samples = bytes.len() / format.sample_size();
+    if bytes.len() % format.sample_size() != 0 {
+        return Err(Error::InvalidFile);
+    }
+
+    let (width, height) = dimensions(samples, layout.dimensions)?;
+    let data = decode(&bytes, format, layout.byte_order);
+
+    Ok((width, height, data))
+}
+
+/// Dimensions of a file holding `samples` samples, the given dimensions or
+/// a square grid
+fn dimensions(samples: usize, dims: Option<(usize, usize)>) -> Result<(usize, usize), Error> {
+    let (width, height) = if let Some(dims) = dims {
+        dims
+    } else {
+        let size = (samples as f64).sqrt().round() as usize;
+        (size, size)
+    };
+    if width.checked_mul(height) != Some(samples) {
+        return Err(Error::InvalidDimensions);
+    }
+    Ok((width, height))
+}
+
+fn decode(bytes: &[u8], format: RawFormat, byte_order: ByteOrder) -> Vec<f32> {
+    bytes
+        .chunks(format.sample_size())
+        .map(|b| match (format, byte_order) {
+            (RawFormat::U16, ByteOrder::Little) => {
+                f32::from(u16::from_le_bytes([b[0], b[1]])) / 65535.0
+            }
+            (RawFormat::U16, ByteOrder::Big) => {
+                f32::from(u16::from_be_bytes([b[0], b[1]])) / 65535.0
+            }
+            (RawFormat::F32, ByteOrder::Little) => {
+                f32::from_bits(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            }
+            (RawFormat::F32, ByteOrder::Big) => {
+                f32::from_bits(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+            }
+        })
+        .collect()
+}
+
+/// Write row-major samples to a raw file
+///
+/// U16 samples are expected to be normalized to [0, 1] and are clamped.
+pub fn write_file(
+    file_path: &Path,
+    format: RawFormat,
+    byte_order: ByteOrder,
+    samples: impl Iterator<Item = f32>,
+) -> Result<(), Error> {
+    let mut bytes = Vec::new();
+
+    for s in samples {
+        match (format, byte_order) {
+            (RawFormat::U16, ByteOrder::Little) => {
+                bytes.extend_from_slice(&to_u16(s).to_le_bytes())
+            }
+            (RawFormat::U16, ByteOrder::Big) => bytes.extend_from_slice(&to_u16(s).to_be_bytes()),
+            (RawFormat::F32, ByteOrder::Little) => {
+                bytes.extend_from_slice(&s.to_bits().to_le_bytes())
+            }
+            (RawFormat::F32, ByteOrder::Big) => bytes.extend_from_slice(&s.to_bits().to_be_bytes()),
+        }
+    }
+
+    fs::write(file_path, bytes)?;
+
+    Ok(())
+}
+
+fn to_u16(v: f32) -> u16 {
+    (v.clamp(0.0, 1.0) * 65535.0).round() as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_dimensions() {
+        assert_eq!(parse_dimensions("1025x513"), Ok((1025, 513)));
+        assert_eq!(parse_dimensions("64X64"), Ok((64, 64)));
+        assert!(parse_dimensions("1025").is_err());
+        assert!(parse_dimensions("1025x513x2").is_err());
+        assert!(parse_dimensions("widexhigh").is_err());
+    }
+
+    #[test]
+    fn infers_square_dimensions() {
+        assert_eq!(dimensions(1025 * 1025, None), Ok((1025, 1025)));
+        assert_eq!(dimensions(4 * 2, Some((4, 2))), Ok((4, 2)));
+    }
+
+    #[test]
+    fn rejects_mismatched_dimensions() {
+        assert_eq!(dimensions(4 * 3, None), Err(Error::InvalidDimensions));
+        assert_eq!(
+            dimensions(4 * 3, Some((4, 4))),
+            Err(Error::InvalidDimensions)
+        );
+    }
+
+    #[test]
+    fn rejects_overflowing_dimensions() {
+        assert_eq!(
+            dimensions(16, Some((usize::MAX, 2))),
+            Err(Error::InvalidDimensions)
+        );
+        let huge = format!("{}x{}", usize::MAX / 2, 3);
+        assert!(parse_dimensions(&huge).is_err());
+        assert_eq!(parse_dimensions("1025x513"), Ok((1025, 513)));
+    }
+
+    #[test]
+    fn decodes_byte_orders() {
+        assert_eq!(
+            decode(&[0xff, 0xff, 0x00, 0x00], RawFormat::U16, ByteOrder::Little),
+            vec![1.0, 0.0]
+        );
+        assert_eq!(
+            decode(&[0x00, 0xff], RawFormat::U16, ByteOrder::Big),
+            decode(&[0xff, 0x00], RawFormat::U16, ByteOrder::Little)
+        );
+        assert_eq!(
+            decode(
+                &1.5f32.to_bits().to_be_bytes(),
+                RawFormat::F32,
+                ByteOrder::Big
+            ),
+            vec![1.5]
+        );
+    }
+}
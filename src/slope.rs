@@ -0,0 +1,150 @@
+use std::str::FromStr;
+
+/// Slope angle thresholds, in degrees, splitting the terrain into classes
+#[derive(Clone, Debug, PartialEq)]
+pub struct SlopeClasses {
+    thresholds: Vec<f32>,
+}
+
+impl Default for SlopeClasses {
+    fn default() -> Self {
+        Self {
+            thresholds: vec![5.0, 15.0, 30.0],
+        }
+    }
+}
+
+impl SlopeClasses {
+    /// Number of classes, one more than the number of thresholds
+    pub fn count(&self) -> usize {
+        self.thresholds.len() + 1
+    }
+
+    /// Class of a slope angle in degrees, 0 is the flattest
+    pub fn class(&self, degrees: f32) -> usize {
+        self.thresholds
+            .iter()
+            .position(|t| degrees < *t)
+            .unwrap_or(self.thresholds.len())
+    }
+
+    /// Green for the flattest class through yellow to red for the steepest
+    pub fn color(&self, class: usize) -> [u8; 3] {
+        const STOPS: [[f32; 3]; 3] = [
+            [46.0, 160.0, 67.0],
+            [240.0, 200.0, 40.0],
+            [200.0, 30.0, 30.0],
+        ];
+
+        let t = class as f32 / (self.count() - 1).max(1) as f32 * 2.0;
+        let i = (t as usize).min(1);
+        let f = t - i as f32;
+        let lerp = |c: usize| (STOPS[i][c] + (STOPS[i + 1][c] - STOPS[i][c]) * f).round() as u8;
+        [lerp(0), lerp(1), lerp(2)]
+    }
+
+    /// Angle range of a class, i.e. "5-15 deg"
+    pub fn label(&self, class: usize) -> String {
+        let n = self.thresholds.len();
+        if n == 0 {
+            "0-90 deg".to_string()
+        } else if class == 0 {
+            format!("< {} deg", self.thresholds[0])
+        } else if class >= n {
+            format!(">= {} deg", self.thresholds[n - 1])
+        } else {
+            format!(
+                "{}-{} deg",
+                self.thresholds[class - 1],
+                self.thresholds[class]
+            )
+        }
+    }
+
+    /// Fraction of the slopes in each class
+    pub fn fractions(&self, slopes: &[f32]) -> Vec<f32> {
+        let mut counts = vec![0usize; self.count()];
+        for s in slopes {
+            counts[self.class(*s)] += 1;
+        }
+        counts
+            .iter()
+            .map(|c| *c as f32 / slopes.len().max(1) as f32)
+            .collect()
+    }
+}
+
+impl FromStr for SlopeClasses {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || {
+            format!(
+                "Invalid slope thresholds '{}', use increasing angles in degrees, i.e. 5,15,30",
+                s
+            )
+        };
+
+        let thresholds = s
+            .split(',')
+            .map(|t| t.trim().parse::<f32>().map_err(|_| err()))
+            .collect::<Result<Vec<f32>, String>>()?;
+
+        let increasing = thresholds.windows(2).all(|w| w[0] < w[1]);
+        let in_range = thresholds.iter().all(|t| *t > 0.0 && *t < 90.0);
+        if !increasing || !in_range {
+            return Err(err());
+        }
+
+        Ok(Self { thresholds })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heightmap::Heightmap;
+    use std::path::Path;
+
+    /// 8x8 plane rising by `dx` per column and `dy` per row, in [0, 1] for the
+    /// default height scale of 10
+    fn plane(dx: f32, dy: f32) -> Heightmap {
+        let elevations = (0..64)
+            .map(|i| (i % 8) as f32 * dx + (i / 8) as f32 * dy)
+            .collect();
+        Heightmap::from_normalized(Path::new("plane.png"), 8, 8, elevations).unwrap()
+    }
+
+    #[test]
+    fn plane_has_a_constant_slope() {
+        for (dx, dy) in &[(0.0, 0.0), (0.1, 0.0), (0.0, 0.05), (0.05, 0.05)] {
+            let hmap = plane(*dx, *dy);
+            let gradient = 10.0 * (dx * dx + dy * dy).sqrt();
+            let expected = gradient.atan().to_degrees();
+
+            for slope in hmap.slope_degrees() {
+                assert!(
+                    (slope - expected).abs() < 1e-3,
+                    "{} instead of {} on ({}, {})",
+                    slope,
+                    expected,
+                    dx,
+                    dy
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn classes_and_fractions() {
+        let classes = SlopeClasses::default();
+        assert_eq!(classes.count(), 4);
+        assert_eq!(classes.class(0.0), 0);
+        assert_eq!(classes.class(5.0), 1);
+        assert_eq!(classes.class(45.0), 3);
+
+        // The 45 degree plane is all in the steepest class
+        let slopes = plane(0.1, 0.0).slope_degrees();
+        assert_eq!(classes.fractions(&slopes), vec![0.0, 0.0, 0.0, 1.0]);
+    }
+}
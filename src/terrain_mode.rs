@@ -6,10 +6,19 @@ pub enum TerrainMode {
     Points,
     /// Solid, colored material
     Solid,
-    /// Solid, color provided by the heightmap image itself
+    /// Solid, color provided by the color texture, or the heightmap image
+    /// itself when there is none
     Textured,
     /// Solid, color provided by the alpha map channels
     Alphamap,
+    /// Solid, color provided by the baked hillshade and ambient occlusion
+    Lightmap,
+    /// Solid, colored by elevation through a color ramp
+    ColorRamp,
+    /// Solid, colored by slope angle class
+    Slope,
+    /// Solid, colored by what's visible from the pilot position
+    Viewshed,
     /* TODO - need to load and blend the alphamap channels
      * Filled, color provided by the color channels in the alpha map texture
      * file
@@ -23,7 +32,11 @@ impl TerrainMode {
             TerrainMode::Points => TerrainMode::Solid,
             TerrainMode::Solid => TerrainMode::Textured,
             TerrainMode::Textured => TerrainMode::Alphamap,
-            TerrainMode::Alphamap => TerrainMode::Wireframe,
+            TerrainMode::Alphamap => TerrainMode::Lightmap,
+            TerrainMode::Lightmap => TerrainMode::ColorRamp,
+            TerrainMode::ColorRamp => TerrainMode::Slope,
+            TerrainMode::Slope => TerrainMode::Viewshed,
+            TerrainMode::Viewshed => TerrainMode::Wireframe,
         }
     }
 }
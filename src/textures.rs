@@ -0,0 +1,36 @@
+use image::DynamicImage;
+use kiss3d::context::Context;
+use kiss3d::resource::TextureManager;
+
+/// Add a named texture, or replace the image of an existing one
+///
+/// The TextureManager keeps the first image added under a name, so the new
+/// image is uploaded to the existing texture and every node using it updates.
+/// Images are converted to RGB, kiss3d only loads RGB and RGBA images.
+pub fn set_texture_image(img: DynamicImage, name: &str) {
+    let img = img.to_rgb();
+    let existing = TextureManager::get_global_manager(|tm| tm.get(name));
+
+    if let Some(tex) = existing {
+        let (w, h) = img.dimensions();
+        let ctxt = Context::get();
+
+        ctxt.active_texture(Context::TEXTURE0);
+        ctxt.bind_texture(Context::TEXTURE_2D, Some(&*tex));
+        ctxt.tex_image2d(
+            Context::TEXTURE_2D,
+            0,
+            Context::RGB as i32,
+            w as i32,
+            h as i32,
+            0,
+            Context::RGB,
+            Some(&img.into_raw()[..]),
+        );
+    } else {
+        let img = DynamicImage::ImageRgb8(img);
+        TextureManager::get_global_manager(|tm| {
+            tm.add_image(img.clone(), name);
+        });
+    }
+}
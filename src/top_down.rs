@@ -0,0 +1,184 @@
+use kiss3d::camera::Camera;
+use kiss3d::event::{Action, Key, MouseButton, WindowEvent};
+use kiss3d::resource::ShaderUniform;
+use kiss3d::window::Canvas;
+use nalgebra::{Isometry3, Matrix4, Orthographic3, Point2, Point3, Vector2, Vector3};
+
+/// Orthographic camera looking straight down, +z is up on the screen like in
+/// the ortho view
+pub struct TopDown {
+    /// Scene (x, z) coordinates at the center of the screen
+    center: Point2<f32>,
+    /// Half the height of the screen, in scene units
+    extent: f32,
+    /// Scene y of the eye, above the terrain
+    height: f32,
+    /// Scene y of the point looked at, below the terrain
+    ground: f32,
+    aspect: f32,
+    /// Fraction of the screen moved per frame while a key is held
+    move_step: f32,
+    last_cursor_pos: Vector2<f32>,
+    proj: Matrix4<f32>,
+    view: Matrix4<f32>,
+    proj_view: Matrix4<f32>,
+    inverse_proj_view: Matrix4<f32>,
+}
+
+impl TopDown {
+    pub fn new() -> Self {
+        let mut cam = Self {
+            center: Point2::origin(),
+            extent: 100.0,
+            height: 100.0,
+            ground: 0.0,
+            aspect: 800.0 / 600.0,
+            move_step: 0.01,
+            last_cursor_pos: Vector2::zeros(),
+            proj: Matrix4::identity(),
+            view: Matrix4::identity(),
+            proj_view: Matrix4::identity(),
+            inverse_proj_view: Matrix4::identity(),
+        };
+        cam.update_projviews();
+        cam
+    }
+
+    /// Show everything between the min and max scene coordinates
+    pub fn fit(&mut self, min: &Point3<f32>, max: &Point3<f32>) {
+        self.center = Point2::new((min.x + max.x) / 2.0, (min.z + max.z) / 2.0);
+        let half = Vector2::new((max.x - min.x) / 2.0, (max.z - min.z) / 2.0);
+        self.extent = half.y.max(half.x / self.aspect) * 1.05;
+        self.fit_depth(min.y, max.y);
+    }
+
+    /// Keep everything between the min and max scene y in the view, i.e.
+    /// after the height scale changes
+    pub fn fit_depth(&mut self, min_y: f32, max_y: f32) {
+        self.height = max_y + 10.0;
+        self.ground = min_y - 10.0;
+        self.update_projviews();
+    }
+
+    /// Center the screen on the (x, z) coordinates of a point
+    pub fn look_at(&mut self, at: Point3<f32>) {
+        self.center = Point2::new(at.x, at.z);
+        self.update_projviews();
+    }
+
+    /// The point below the center of the screen
+    pub fn at(&self) -> Point3<f32> {
+        Point3::new(self.center.x, self.ground, self.center.y)
+    }
+
+    /// Half the height of the screen, in scene units
+    pub fn extent(&self) -> f32 {
+        self.extent
+    }
+
+    pub fn set_move_step(&mut self, step: f32) {
+        self.move_step = step;
+    }
+
+    fn pan(&mut self, dx: f32, dz: f32) {
+        self.center.x += dx;
+        self.center.y += dz;
+        self.update_projviews();
+    }
+
+    fn update_projviews(&mut self) {
+        let (w, h) = (self.extent * self.aspect, self.extent);
+        let projection = Orthographic3::new(-w, w, -h, h, 0.1, self.height - self.ground);
+        let at = self.at();
+        let eye = Point3::new(at.x, self.height, at.z);
+
+        self.view = Isometry3::look_at_rh(&eye, &at, &Vector3::z()).to_homogeneous();
+        self.proj = *projection.as_matrix();
+        self.proj_view = self.proj * self.view;
+        if let Some(inverse) = self.proj_view.try_inverse() {
+            self.inverse_proj_view = inverse;
+        }
+    }
+}
+
+impl Camera for TopDown {
+    fn handle_event(&mut self, canvas: &Canvas, event: &WindowEvent) {
+        match *event {
+            WindowEvent::CursorPos(x, y, _) => {
+                let pos = Vector2::new(x as f32, y as f32);
+                let dragging = canvas.get_mouse_button(MouseButton::Button1) == Action::Press
+                    || canvas.get_mouse_button(MouseButton::Button2) == Action::Press;
+
+                if dragging {
+                    // Keep the point under the cursor in place, the screen x
+                    // axis points along -x
+                    let units = 2.0 * self.extent / canvas.size().1.max(1) as f32;
+                    let d = (pos - self.last_cursor_pos) * units;
+                    self.pan(d.x, d.y);
+                }
+                self.last_cursor_pos = pos;
+            }
+            WindowEvent::Scroll(_, off, _) => {
+                self.extent = (self.extent * 0.9f32.powf(off as f32)).max(1.0);
+                self.update_projviews();
+            }
+            WindowEvent::FramebufferSize(w, h) => {
+                self.aspect = w as f32 / h.max(1) as f32;
+                self.update_projviews();
+            }
+            _ => {}
+        }
+    }
+
+    fn eye(&self) -> Point3<f32> {
+        Point3::new(self.center.x, self.height, self.center.y)
+    }
+
+    fn view_transform(&self) -> Isometry3<f32> {
+        Isometry3::look_at_rh(&self.eye(), &self.at(), &Vector3::z())
+    }
+
+    fn transformation(&self) -> Matrix4<f32> {
+        self.proj_view
+    }
+
+    fn inverse_transformation(&self) -> Matrix4<f32> {
+        self.inverse_proj_view
+    }
+
+    fn clip_planes(&self) -> (f32, f32) {
+        (0.1, self.height - self.ground)
+    }
+
+    fn update(&mut self, canvas: &Canvas) {
+        let pressed = |key| canvas.get_key(key) == Action::Press;
+        let step = self.move_step * self.extent;
+        let mut d = Vector2::zeros();
+
+        if pressed(Key::W) {
+            d.y += step;
+        }
+        if pressed(Key::S) {
+            d.y -= step;
+        }
+        if pressed(Key::A) {
+            d.x += step;
+        }
+        if pressed(Key::D) {
+            d.x -= step;
+        }
+        if d != Vector2::zeros() {
+            self.pan(d.x, d.y);
+        }
+    }
+
+    fn upload(
+        &self,
+        _: usize,
+        proj: &mut ShaderUniform<Matrix4<f32>>,
+        view: &mut ShaderUniform<Matrix4<f32>>,
+    ) {
+        proj.upload(&self.proj);
+        view.upload(&self.view);
+    }
+}
@@ -0,0 +1,142 @@
+use image::{GrayImage, ImageBuffer, Luma, Rgb, RgbImage};
+use std::f32;
+
+/// Where the pilot stands and how high the heli flies, heights are in source
+/// elevation units above the terrain
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ViewshedParams {
+    /// Pixel coordinates of the pilot
+    pub pilot: (f64, f64),
+    pub eye_height: f32,
+    /// Height the heli is flown at, cells where it's visible at this height
+    /// count as visible
+    pub flight_height: f32,
+}
+
+/// Lowest height above the terrain visible from the eye, for every cell of a
+/// width x height grid of elevations
+///
+/// Rays are cast from the eye to every cell on the edges of the grid,
+/// keeping the steepest line of sight so far. Elevations, `spacing` (the
+/// (x, y) distance between pixels) and `eye_height` are in the same units,
+/// cells never reached are infinite.
+pub fn min_visible_heights(
+    elevations: &[f32],
+    width: usize,
+    height: usize,
+    spacing: (f32, f32),
+    eye: (usize, usize),
+    eye_height: f32,
+) -> Vec<f32> {
+    let mut heights = vec![f32::INFINITY; width * height];
+    let (ex, ey) = (eye.0.min(width - 1), eye.1.min(height - 1));
+    let eye_elevation = elevations[ex + ey * width] + eye_height;
+    heights[ex + ey * width] = 0.0;
+
+    let edges = (0..width)
+        .flat_map(|x| vec![(x, 0), (x, height - 1)])
+        .chain((1..height - 1).flat_map(|y| vec![(0, y), (width - 1, y)]));
+
+    for (tx, ty) in edges {
+        let (dx, dy) = (tx as f32 - ex as f32, ty as f32 - ey as f32);
+        let steps = dx.abs().max(dy.abs()) as usize;
+        let mut max_slope = f32::MIN;
+
+        for s in 1..=steps {
+            let f = s as f32 / steps as f32;
+            let (x, y) = (
+                (ex as f32 + dx * f).round() as usize,
+                (ey as f32 + dy * f).round() as usize,
+            );
+            let i = x + y * width;
+            let dist = ((dx * f * spacing.0).powi(2) + (dy * f * spacing.1).powi(2)).sqrt();
+
+            // Height the line of sight over the terrain so far passes above
+            // this cell at
+            let required = if max_slope == f32::MIN {
+                0.0
+            } else {
+                (eye_elevation + max_slope * dist - elevations[i]).max(0.0)
+            };
+            heights[i] = heights[i].min(required);
+
+            max_slope = max_slope.max((elevations[i] - eye_elevation) / dist);
+        }
+    }
+    heights
+}
+
+/// White where a heli at `flight_height` is visible
+pub fn mask(heights: &[f32], width: usize, height: usize, flight_height: f32) -> GrayImage {
+    ImageBuffer::from_fn(width as _, height as _, |x, y| {
+        let visible = heights[x as usize + y as usize * width] <= flight_height;
+        Luma([if visible { 255 } else { 0 }])
+    })
+}
+
+/// Visibility colored over the shading, green where the terrain is visible,
+/// yellow where only a heli at `flight_height` is and red elsewhere
+pub fn overlay(heights: &[f32], shading: &GrayImage, flight_height: f32) -> RgbImage {
+    let width = shading.width() as usize;
+
+    ImageBuffer::from_fn(shading.width(), shading.height(), |x, y| {
+        let h = heights[x as usize + y as usize * width];
+        let color = if h <= 0.0 {
+            [60.0, 200.0, 60.0]
+        } else if h <= flight_height {
+            [230.0, 200.0, 40.0]
+        } else {
+            [200.0, 40.0, 40.0]
+        };
+
+        let shade = 0.5 + 0.5 * f32::from(shading.get_pixel(x, y)[0]) / 255.0;
+        Rgb([
+            (color[0] * shade) as u8,
+            (color[1] * shade) as u8,
+            (color[2] * shade) as u8,
+        ])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ridge_hides_the_terrain_behind_it() {
+        // Three identical rows with a 10 high ridge in the middle column
+        let row = [0.0, 0.0, 0.0, 10.0, 0.0, 0.0, 0.0];
+        let elevations: Vec<f32> = row.iter().chain(&row).chain(&row).cloned().collect();
+        let heights = min_visible_heights(&elevations, 7, 3, (1.0, 1.0), (0, 1), 0.0);
+        let h = |x: usize| heights[x + 7];
+
+        // In front of and on top of the ridge is visible from the ground
+        for x in 0..4 {
+            assert_eq!(h(x), 0.0);
+        }
+        // Behind it the line of sight over the ridge top rises 10/3 per pixel
+        assert!((h(4) - 40.0 / 3.0).abs() < 1e-4);
+        assert!((h(6) - 20.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn eye_height_lowers_the_shadow() {
+        let row = [0.0, 0.0, 0.0, 10.0, 0.0, 0.0, 0.0];
+        let elevations: Vec<f32> = row.iter().chain(&row).chain(&row).cloned().collect();
+        let heights = min_visible_heights(&elevations, 7, 3, (1.0, 1.0), (0, 1), 4.0);
+
+        // Sight line from 4 over 10 at 3 pixels rises 2 per pixel, 16
+        // instead of 20 at the far edge
+        assert!((heights[6 + 7] - 16.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn overlay_colors_by_flight_height() {
+        let shading = GrayImage::from_pixel(3, 1, Luma([255]));
+        let img = overlay(&[0.0, 5.0, 50.0], &shading, 10.0);
+
+        assert_eq!(img.get_pixel(0, 0)[1], 200);
+        assert_eq!(img.get_pixel(1, 0)[0], 230);
+        assert_eq!(img.get_pixel(2, 0)[1], 40);
+    }
+}